@@ -0,0 +1,599 @@
+//Generates the server side of a game interface as Rust source. This is used by `make_server!` but can also be
+//called from a build script, e.g. `write_server_module("res/games/snake.game", &format!("{}/snake.rs", out_dir))`
+//followed by `include!(concat!(env!("OUT_DIR"), "/snake.rs"));`
+
+use super::game_interface::{BuiltinType, GameInterface, Type, StructFields, StructField, get_enum_variant_type};
+use super::parser::parse_game_interface;
+
+fn make_type_str(ty: &Type) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => "String".to_string(),
+        Type::Builtin(builtin) => builtin.name().to_string(),
+        Type::NamedType(name) => name.to_string(),
+        Type::Array(ty, size) => format!("[{}; {}]", make_type_str(ty), size),
+        Type::DynamicArray(ty) => format!("Vec<{}>", make_type_str(ty)),
+        t => panic!("Unsupported type: {:?}", t)
+    }
+}
+
+fn make_struct_fields(fields: &StructFields) -> String {
+    let mut res = String::new();
+
+    res.push_str("{\n");
+
+    for StructField {name, ty} in fields {
+        res.push_str(&format!("    {}: {},\n", name, make_type_str(ty)));
+    }
+
+    res.push('}');
+
+    res
+}
+
+fn make_serializer(ty: &Type, val: &str, out: &str) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => format!("{{let bytes = {val}.as_bytes();\n{out}.extend(&(bytes.len() as u32).to_le_bytes());\n{out}.extend(bytes);}}\n", val=val, out=out),
+        Type::Builtin(BuiltinType::Bool) => format!("{out}.extend(&[{val} as u8]);\n", val=val, out=out),
+        Type::Builtin(_) => format!("{out}.extend({val}.to_le_bytes());\n", val=val, out=out),
+        Type::NamedType(name) => format!("serialize_{name}(&{val}, {out});\n", val=val, out=out, name=name),
+        Type::Array(ty, _) => {
+            let mut res = String::new();
+
+            res.push_str(&format!("for x in ({}).iter() {{\n", val));
+            res.push_str(&make_serializer(ty, "x", out));
+            res.push_str("}\n");
+
+            res
+        },
+        Type::DynamicArray(ty) => {
+            let mut res = String::new();
+
+            res.push_str(&format!("{out}.extend(&({val}.len() as u32).to_le_bytes());\n", val=val, out=out));
+
+            res.push_str(&format!("for x in ({}).iter() {{\n", val));
+            res.push_str(&make_serializer(ty, "x", out));
+            res.push_str("}\n");
+
+            res
+        },
+        _ => panic!("Cannot make inline serializer{:?}", ty)
+    }
+}
+
+fn make_deserializer(ty: &Type, instance: &str) -> String {
+    match ty {
+        Type::Builtin(x) => format!("{}.read_{}().await?", instance, x.name()),
+        Type::NamedType(name) => format!("deserialize_{}({}).await?", name, instance),
+        Type::Array(ty, count) => {
+            let mut res = String::new();
+            res.push_str("unsafe {\n");
+
+            res.push_str(&format!("let mut res: [std::mem::MaybeUninit<{}>; {}] = std::mem::MaybeUninit::uninit().assume_init();\n", make_type_str(ty), count));
+
+            res.push_str(&format!("for i in 0..{} {{\n", count));
+            res.push_str(&format!("res[i].write({});\n", make_deserializer(ty, instance)));
+            res.push_str("}\n");
+
+            res.push_str(&format!("std::mem::transmute::<_, [{}; {}]>(res)\n", make_type_str(ty), count));
+
+            res.push('}');
+
+            res
+        },
+        Type::DynamicArray(ty) => {
+            let mut res = String::new();
+
+            res.push_str("{\n");
+
+            res.push_str(&format!("let array_size = {}.read_u32().await?;\n", instance));
+            res.push_str(&format!("let mut res: Vec<{}> = Vec::with_capacity(array_size as usize);\n", make_type_str(ty)));
+
+            res.push_str("for _ in 0..array_size {\n");
+            res.push_str(&format!("res.push({});\n", make_deserializer(ty, instance)));
+            res.push_str("}\n");
+
+            res.push_str("res\n");
+
+            res.push('}');
+
+            res
+        },
+        _ => panic!("Cannot make inline deserializer{:?}", ty)
+    }
+}
+
+fn are_struct_fields_copyable(fields: &StructFields, itf: &GameInterface) -> bool {
+    fields.iter().all(|StructField {ty, ..}| is_copyable(ty, itf))
+}
+
+fn is_copyable(ty: &Type, itf: &GameInterface) -> bool {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => false,
+        Type::Builtin(_) => true,
+        Type::NamedType(name) => {
+            let (_, ty) = itf.types.iter().find(|x| x.0 == *name).unwrap();
+
+            is_copyable(ty, itf)
+        },
+        Type::Array(ty, _) => is_copyable(ty, itf),
+        Type::DynamicArray(_) => false,
+        Type::Struct(fields) => are_struct_fields_copyable(fields, itf),
+        Type::Enum(variants) => variants.iter().all(|variant| are_struct_fields_copyable(&variant.types, itf)),
+    }
+}
+
+fn are_struct_fields_equatable(fields: &StructFields, itf: &GameInterface) -> bool {
+    fields.iter().all(|StructField {ty, ..}| is_equatable(ty, itf))
+}
+
+fn is_equatable(ty: &Type, itf: &GameInterface) -> bool {
+    match ty {
+        Type::Builtin(BuiltinType::F32) => false,
+        Type::Builtin(BuiltinType::F64) => false,
+
+        Type::Builtin(_) => true,
+
+        Type::NamedType(name) => {
+            let (_, ty) = itf.types.iter().find(|x| x.0 == *name).unwrap();
+
+            is_equatable(ty, itf)
+        },
+
+        Type::Array(ty, _) => is_equatable(ty, itf),
+
+        Type::DynamicArray(ty) => is_equatable(ty, itf),
+
+        Type::Struct(fields) => are_struct_fields_equatable(fields, itf),
+
+        Type::Enum(variants) => variants.iter().all(|variant| are_struct_fields_equatable(&variant.types, itf)),
+    }
+}
+
+//serde only implements its traits for fixed size arrays of up to 32 elements
+const MAX_SERDE_ARRAY_LEN: usize = 32;
+
+fn are_struct_fields_serializable(fields: &StructFields, itf: &GameInterface) -> bool {
+    fields.iter().all(|StructField {ty, ..}| is_serializable(ty, itf))
+}
+
+fn is_serializable(ty: &Type, itf: &GameInterface) -> bool {
+    match ty {
+        Type::Builtin(_) => true,
+        Type::NamedType(name) => {
+            let (_, ty) = itf.types.iter().find(|x| x.0 == *name).unwrap();
+
+            is_serializable(ty, itf)
+        },
+        Type::Array(ty, size) => *size <= MAX_SERDE_ARRAY_LEN && is_serializable(ty, itf),
+        Type::DynamicArray(ty) => is_serializable(ty, itf),
+        Type::Struct(fields) => are_struct_fields_serializable(fields, itf),
+        Type::Enum(variants) => variants.iter().all(|variant| are_struct_fields_serializable(&variant.types, itf)),
+    }
+}
+
+fn read_struct_fields(fields: &StructFields, instance: &str) -> String {
+    let mut res = String::new();
+
+    res.push_str("{\n");
+
+    for StructField {name, ty} in fields {
+        res.push_str(&format!("{}: {},\n", name, make_deserializer(ty, instance)));
+    }
+
+    res.push('}');
+
+    res
+}
+
+fn get_derives(name: &str, ty: &Type, itf: &GameInterface) -> Option<String> {
+    let mut derives = Vec::new();
+
+    match ty {
+        Type::Builtin(_) | Type::NamedType(_) | Type::Array(_, _) | Type::DynamicArray(_) => return None,
+        _ => {}
+    };
+
+    derives.push("Debug".to_string());
+    derives.push("Clone".to_string());
+    derives.push("PartialEq".to_string());
+
+    if is_serializable(ty, itf) {
+        derives.push("serde::Serialize".to_string());
+        derives.push("serde::Deserialize".to_string());
+    }
+
+    if is_copyable(ty, itf) {
+        derives.push("Copy".to_string());
+    }
+
+    if is_equatable(ty, itf) {
+        derives.push("Eq".to_string());
+    }
+
+    if let Some(extra) = itf.type_derives.get(name) {
+        for derive in extra {
+            if !derives.contains(derive) {
+                derives.push(derive.clone());
+            }
+        }
+    }
+
+    Some(format!("#[derive({})]", derives.join(", ")))
+}
+
+fn make_types(itf: &GameInterface, out: &mut String) {
+    for (name, ty) in &itf.types {
+        if let Some(derives) = get_derives(name, ty, itf) {
+            out.push_str(&derives);
+            out.push('\n');
+        }
+
+        match ty {
+            Type::Builtin(_) | Type::NamedType(_) | Type::Array(_,_) | Type::DynamicArray(_) => {
+                out.push_str(&format!("pub type {} = {};\n\n", name, make_type_str(ty)));
+            },
+            Type::Struct(fields) => {
+                out.push_str(&format!("pub struct {} {}\n\n", name, make_struct_fields(fields)));
+            },
+            Type::Enum(variants) => {
+                out.push_str(&format!("pub enum {} {{\n", name));
+
+                for variant in variants.iter() {
+                    out.push_str(&format!("    {}", variant.name));
+
+                    if !variant.types.is_empty() {
+                        out.push(' ');
+                        out.push_str(&make_struct_fields(&variant.types));
+                    }
+
+                    out.push_str(",\n");
+                }
+
+                out.push_str("}\n\n");
+            }
+        }
+    }
+}
+
+fn make_serializers(itf: &GameInterface, out: &mut String) {
+    for (name, ty) in &itf.types {
+        out.push_str(&format!("fn serialize_{}(value: &{}, out: &mut Vec<u8>) {{\n", name, name));
+
+        match ty {
+            Type::Struct(fields) => {
+                for StructField {name, ty} in fields.iter() {
+                    out.push_str(&make_serializer(ty, &format!("value.{}", name), "out"));
+                }
+            },
+            Type::Enum(variants) => {
+                let variant_type = get_enum_variant_type(variants);
+
+                let width = match variant_type {
+                    BuiltinType::U8 => 1,
+                    BuiltinType::U16 => 2,
+                    BuiltinType::U32 => 4,
+                    BuiltinType::U64 => 8,
+                    _ => panic!("Invalid enum variant type")
+                };
+
+                out.push_str("match value {\n");
+
+                for (i, variant) in variants.iter().enumerate() {
+                    out.push_str(&format!("{}::{}", name, variant.name));
+
+                    if !variant.types.is_empty() {
+                        out.push('{');
+                        for StructField {name, ..} in variant.types.iter() {
+                            out.push_str(name);
+                            out.push(',');
+                        }
+                        out.push('}');
+                    }
+
+                    out.push_str(" => {\n");
+
+                    out.push_str("out.extend(&[");
+                    let bytes = i.to_le_bytes();
+
+                    for byte in bytes.iter().take(width) {
+                        out.push_str(&format!("0x{:02x},", byte));
+                    }
+
+                    out.push_str("]);\n");
+
+                    for StructField {name, ty} in variant.types.iter() {
+                        out.push_str(&make_serializer(ty, name, "out"));
+                    }
+
+                    out.push_str("}\n");
+                }
+
+                out.push_str("}\n");
+            }
+            x => out.push_str(&make_serializer(x, "value", "out"))
+        }
+
+        out.push_str("}\n\n");
+    }
+}
+
+fn make_deserializers(itf: &GameInterface, reader: &str, out: &mut String) {
+    for (name, ty) in &itf.types {
+        out.push_str(&format!("async fn deserialize_{}(instance: &mut {}) -> Result<{}, async_std::io::Error> {{\n", name, reader, name));
+
+        out.push_str("Ok(");
+
+        match ty {
+            Type::Struct(fields) => {
+                out.push_str(name);
+                out.push(' ');
+                out.push_str(&read_struct_fields(fields, "instance"));
+            },
+            Type::Enum(variants) => {
+                let variant_type = get_enum_variant_type(variants);
+
+                out.push_str(&format!("match instance.read_{}().await? {{\n", variant_type.name()));
+
+                for (i, variant) in variants.iter().enumerate() {
+                    out.push_str(&format!("{}{} => {}::{}", i, variant_type.name(), name, variant.name));
+
+                    if !variant.types.is_empty() {
+                        out.push(' ');
+                        out.push_str(&read_struct_fields(&variant.types, "instance"));
+                    }
+
+                    out.push_str(",\n");
+                }
+
+                out.push_str("_ => return Err(async_std::io::Error::new(async_std::io::ErrorKind::InvalidData, \"Invalid enum variant\"))\n");
+                out.push('}');
+            },
+            x => out.push_str(&make_deserializer(x, "instance"))
+        }
+
+        out.push_str(")\n}\n\n");
+    }
+}
+
+//Callbacks are handled by a trait so that games can route them wherever they like. By default they are just logged
+fn make_callbacks(itf: &GameInterface, out: &mut String) {
+    out.push_str("#[async_trait::async_trait]\npub trait AgentCallbacks: Send {\n");
+
+    for (name, callback) in &itf.callbacks {
+        let args: Vec<_> = callback.args.iter().map(|(arg, ty)| format!("{}: {}", arg, make_type_str(ty))).collect();
+        let arg_names: Vec<_> = callback.args.iter().map(|(arg, _)| format!("{} = {{:?}}", arg)).collect();
+        let arg_values: Vec<_> = callback.args.iter().map(|(arg, _)| format!(", {}", arg)).collect();
+
+        out.push_str(&format!("async fn {}(&mut self, {}) {{\n", name, args.join(", ")));
+        out.push_str(&format!("log::debug!(\"Agent callback {}({})\"{});\n", name, arg_names.join(", "), arg_values.join("")));
+        out.push_str("}\n");
+    }
+
+    out.push_str("}\n\n");
+
+    out.push_str("struct DefaultAgentCallbacks;\n");
+    out.push_str("impl AgentCallbacks for DefaultAgentCallbacks {}\n\n");
+}
+
+//Every message from the agent starts with a u32 tag. 0 means the reply follows, anything else is a callback
+fn make_await_reply(itf: &GameInterface, out: &mut String) {
+    if itf.framed {
+        out.push_str("#[allow(dead_code)]\nasync fn await_reply(&mut self, function: &str) -> Result<crate::isolate::protocol::FrameReader, async_std::io::Error> {\n");
+        out.push_str("loop {\n");
+        out.push_str("let (tag, data) = self.instance.read_frame().await?;\n");
+        out.push_str("match tag {\n");
+        out.push_str("0 => return Ok(crate::isolate::protocol::FrameReader::new(format!(\"reply to {}\", function), data)),\n");
+    } else {
+        out.push_str("#[allow(dead_code)]\nasync fn await_reply(&mut self) -> Result<(), async_std::io::Error> {\n");
+        out.push_str("loop {\n");
+        out.push_str("match self.instance.read_u32().await? {\n");
+        out.push_str("0 => return Ok(()),\n");
+    }
+
+    for (i, (name, callback)) in itf.callbacks.iter().enumerate() {
+        out.push_str(&format!("{} => {{\n", i + 1));
+
+        let instance = if itf.framed {
+            out.push_str(&format!("let mut frame = crate::isolate::protocol::FrameReader::new(\"callback {}\".to_string(), data);\n", name));
+            "(&mut frame)"
+        } else {
+            "(&mut self.instance)"
+        };
+
+        for (arg, ty) in &callback.args {
+            out.push_str(&format!("let {}: {} = {};\n", arg, make_type_str(ty), make_deserializer(ty, instance)));
+        }
+
+        if itf.framed {
+            out.push_str("frame.finish()?;\n");
+        }
+
+        let arg_names: Vec<_> = callback.args.iter().map(|(arg, _)| arg.clone()).collect();
+        out.push_str(&format!("self.callbacks.{}({}).await;\n", name, arg_names.join(", ")));
+
+        out.push_str("},\n");
+    }
+
+    out.push_str("tag => return Err(async_std::io::Error::new(async_std::io::ErrorKind::InvalidData, format!(\"Invalid message tag {} from agent\", tag)))\n");
+    out.push_str("}\n}\n}\n\n");
+}
+
+fn make_agent(itf: &GameInterface, out: &mut String) {
+    out.push_str("struct Agent<'a> {\n");
+    out.push_str("    instance: &'a mut crate::isolate::sandbox::RunningJob,\n");
+    out.push_str("    callbacks: Box<dyn AgentCallbacks + 'a>\n");
+    out.push_str("}\n\n");
+
+    out.push_str("impl<'a> Agent<'a> {\n");
+
+    out.push_str("pub fn new(instance: &'a mut crate::isolate::sandbox::RunningJob) -> Self { Self {instance, callbacks: Box::new(DefaultAgentCallbacks)} }\n\n");
+
+    out.push_str("#[allow(dead_code)]\npub fn set_callbacks(&mut self, callbacks: Box<dyn AgentCallbacks + 'a>) { self.callbacks = callbacks; }\n\n");
+
+    //Also done by the first call, but doing it up front keeps the agent's startup out of that call's time
+    out.push_str("#[allow(dead_code)]\npub async fn verify_interface(&mut self) -> Result<(), async_std::io::Error> { self.instance.verify_interface(INTERFACE_HASH).await }\n\n");
+
+    make_await_reply(itf, out);
+
+    for (i, (name, function)) in itf.functions.iter().enumerate() {
+        let mut args = vec!["&mut self".to_string()];
+
+        for (name, ty) in &function.args {
+            //Needs reference?
+            let needs_ref = match ty {
+                Type::Builtin(BuiltinType::Str) => true,
+                Type::Builtin(_) => false,
+                _ => true
+            };
+
+            args.push(format!("{}: {}{}", name, if needs_ref { "&" } else { "" }, make_type_str(ty)));
+        }
+
+        let ret = match &function.ret {
+            Some(ty) => make_type_str(ty),
+            None => "()".to_string()
+        };
+
+        out.push_str(&format!("pub async fn {}({}) -> Result<{}, async_std::io::Error> {{\n", name, args.join(", "), ret));
+
+        out.push_str("self.instance.verify_interface(INTERFACE_HASH).await?;\n");
+        out.push_str("let mut out_bytes: Vec<u8> = Vec::new();\n");
+        out.push_str(&format!("out_bytes.extend(&({}u32).to_le_bytes());\n", i));
+
+        for (name, ty) in &function.args {
+            out.push_str(&make_serializer(ty, name, "(&mut out_bytes)"));
+        }
+
+        if itf.framed {
+            out.push_str("self.instance.write_frame(&out_bytes).await?;\n");
+        } else {
+            out.push_str("self.instance.write(&out_bytes).await?;\n");
+        }
+
+        if let Some(ref ty) = function.ret {
+            if itf.framed {
+                out.push_str(&format!("let mut reply = self.await_reply(\"{}\").await?;\n", name));
+                out.push_str(&format!("let res = {};\n", make_deserializer(ty, "(&mut reply)")));
+                out.push_str("reply.finish()?;\nOk(res)\n");
+            } else {
+                out.push_str("self.await_reply().await?;\n");
+                out.push_str(&format!("Ok({})\n", make_deserializer(ty, "(&mut self.instance)")));
+            }
+        } else if itf.framed {
+            //Void calls are acknowledged too, so callbacks sent during them are handled straight away
+            out.push_str(&format!("let reply = self.await_reply(\"{}\").await?;\n", name));
+            out.push_str("reply.finish()?;\nOk(())\n");
+        } else {
+            out.push_str("self.await_reply().await?;\nOk(())\n");
+        }
+
+        out.push_str("}\n\n");
+    }
+
+    out.push_str("pub async fn kill(mut self) { match self.instance.kill().await {
+    Ok(_) => (),
+    Err(e) => log::error!(\"Failed to kill sandbox: {:?}\", e)
+} }\n\n");
+
+    out.push_str("pub fn set_error(&mut self, error: String) { self.instance.set_error(error) }\n");
+
+    out.push_str("}\n");
+}
+
+//Produces the types, (de)serializers and the `Agent` wrapper used by the server to talk to agents
+pub fn make_server_code(itf: &GameInterface) -> String {
+    let mut out = String::new();
+
+    //In framed mode whole messages are read up front, and the deserializers then work on the frame
+    let reader = if itf.framed {
+        "crate::isolate::protocol::FrameReader"
+    } else {
+        "crate::isolate::sandbox::RunningJob"
+    };
+
+    make_types(itf, &mut out);
+    make_serializers(itf, &mut out);
+    make_deserializers(itf, reader, &mut out);
+
+    out.push_str(&format!("const INTERFACE_HASH: u64 = 0x{:016x};\n\n", itf.interface_hash()));
+
+    make_callbacks(itf, &mut out);
+    make_agent(itf, &mut out);
+
+    out
+}
+
+pub fn make_server_code_from_file(game_file: &str) -> Result<String, String> {
+    let content = std::fs::read_to_string(game_file).map_err(|e| format!("Failed to read {}: {}", game_file, e))?;
+    let name = game_file.replace(' ', "_").replace(['/', '.'], "_");
+
+    let game_interface = parse_game_interface(&content, name)?;
+
+    Ok(make_server_code(&game_interface))
+}
+
+//Meant for build scripts. Only writes when the output changed so that dependents are not rebuilt needlessly
+pub fn write_server_module(game_file: &str, out_file: &str) -> Result<(), String> {
+    let code = make_server_code_from_file(game_file)?;
+
+    if std::fs::read_to_string(out_file).ok().as_deref() == Some(code.as_str()) {
+        return Ok(());
+    }
+
+    std::fs::write(out_file, code).map_err(|e| format!("Failed to write {}: {}", out_file, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_game_interface;
+
+    use super::make_server_code;
+
+    const SOURCE: &str = "
+        #[derive(Hash, PartialOrd)]
+        #[derive(serde::Serialize, Default)]
+        type Pos = struct { row: u8, col: u8 };
+        type Cell = enum { Empty, Full };
+        type Board = [[Cell; 3]; 3];
+
+        function get_move = (board: Board) -> Pos;
+    ";
+
+    #[test]
+    fn test_derives() {
+        let itf = parse_game_interface(SOURCE, "game".to_string()).unwrap();
+
+        //Repeats of the built in derives are dropped
+        assert_eq!(itf.type_derives["Pos"], ["Hash", "PartialOrd", "serde::Serialize", "Default"]);
+        assert!(!itf.type_derives.contains_key("Cell"));
+
+        let code = make_server_code(&itf);
+
+        assert!(code.contains("#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, Copy, Eq, Hash, PartialOrd, Default)]\npub struct Pos"));
+        assert!(code.contains("#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, Copy, Eq)]\npub enum Cell"));
+
+        let unknown = parse_game_interface("#[serde(rename_all)] type Pos = struct { row: u8 };", "game".to_string());
+        assert!(unknown.unwrap_err().starts_with("Unknown attribute 'serde'"));
+
+        let not_struct = parse_game_interface("#[derive(Hash)] type Row = [u8; 3];", "game".to_string());
+        assert!(not_struct.unwrap_err().starts_with("Derives can only be added to struct and enum types"));
+    }
+
+    #[test]
+    fn test_serde_array_limit() {
+        let source = "
+            type Row = [u8; 33];
+            type Cells = [u8; 32];
+            type Small = struct { cells: Cells };
+            type Rows = [Row; 2];
+            type Big = struct { rows: Rows };
+            type Nested = enum { Empty, Full { big: Big } };
+        ";
+
+        let code = make_server_code(&parse_game_interface(source, "game".to_string()).unwrap());
+
+        assert!(code.contains("#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, Copy, Eq)]\npub struct Small"));
+        assert!(code.contains("#[derive(Debug, Clone, PartialEq, Copy, Eq)]\npub struct Big"));
+        assert!(code.contains("#[derive(Debug, Clone, PartialEq, Copy, Eq)]\npub enum Nested"));
+    }
+}
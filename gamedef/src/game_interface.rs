@@ -11,6 +11,28 @@ pub enum BuiltinType {
     Bool, Str
 }
 
+impl BuiltinType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinType::U8 => "u8",
+            BuiltinType::U16 => "u16",
+            BuiltinType::U32 => "u32",
+            BuiltinType::U64 => "u64",
+
+            BuiltinType::I8 => "i8",
+            BuiltinType::I16 => "i16",
+            BuiltinType::I32 => "i32",
+            BuiltinType::I64 => "i64",
+
+            BuiltinType::F32 => "f32",
+            BuiltinType::F64 => "f64",
+
+            BuiltinType::Bool => "bool",
+            BuiltinType::Str => "str",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StructField {
     pub name: String,
@@ -71,7 +93,13 @@ pub struct FunctionSignature {
 pub struct GameInterface {
     pub name: String,
     pub types: Vec<(String, Type)>,
-    pub functions: Vec<(String, FunctionSignature)>
+    pub functions: Vec<(String, FunctionSignature)>,
+    //Calls made by the agent while it computes a reply. These never have a return type
+    pub callbacks: Vec<(String, FunctionSignature)>,
+    //Set by `option framed;`. Every message is then sent with a length and checksum
+    pub framed: bool,
+    //Extra derives for generated server types, from `#[derive(...)]` before a type definition
+    pub type_derives: HashMap<String, Vec<String>>
 }
 
 pub fn try_reduce_struct_fields(fields: &StructFields, lookup: &HashMap<String, Type>) -> Option<StructFields> {
@@ -155,10 +183,36 @@ pub fn try_reduce_type(ty: &Type, lookup: &HashMap<String, Type>) -> Option<Type
     }
 }
 
+fn reduce_signature(name: &str, func: &FunctionSignature, type_lookup: &HashMap<String, Type>) -> FunctionSignature {
+    let mut args = Vec::new();
+
+    for (arg, ty) in &func.args {
+        if let Some(ty) = try_reduce_type(ty, type_lookup) {
+            args.push((arg.clone(), ty));
+        } else {
+            panic!("Failed to reduce type of argument {} in function {}", arg, name);
+        }
+    }
+
+    let ret = if let Some(ty) = &func.ret {
+        if let Some(ty) = try_reduce_type(ty, type_lookup) {
+            Some(ty)
+        } else {
+            panic!("Failed to reduce type of return value in function {}", name);
+        }
+    } else {
+        None
+    };
+
+    FunctionSignature {
+        args,
+        ret
+    }
+}
+
 impl GameInterface {
     pub fn reduced(&self) -> Self {
         let mut types = Vec::new();
-        let mut functions = Vec::new();
 
         let mut type_lookup: HashMap<String, Type> = HashMap::new();
 
@@ -180,37 +234,185 @@ impl GameInterface {
             }
         }
 
-        for (name, func) in &self.functions {
-            let mut args = Vec::new();
+        let functions = self.functions.iter().map(|(name, func)| (name.clone(), reduce_signature(name, func, &type_lookup))).collect();
+        let callbacks = self.callbacks.iter().map(|(name, func)| (name.clone(), reduce_signature(name, func, &type_lookup))).collect();
 
-            for (name, ty) in &func.args {
-                if let Some(ty) = try_reduce_type(ty, &type_lookup) {
-                    args.push((name.clone(), ty));
-                } else {
-                    panic!("Failed to reduce type of argument {} in function {}", name, name);
+        GameInterface {
+            name: self.name.clone(),
+            types,
+            functions,
+            callbacks,
+            framed: self.framed,
+            type_derives: self.type_derives.clone()
+        }
+    }
+}
+
+fn write_canonical_fields(fields: &StructFields, out: &mut String) {
+    out.push_str("{ ");
+
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+
+        out.push_str(&field.name);
+        out.push_str(": ");
+        write_canonical_type(&field.ty, out);
+    }
+
+    out.push_str(" }");
+}
+
+fn write_canonical_type(ty: &Type, out: &mut String) {
+    match ty {
+        Type::Builtin(builtin) => out.push_str(builtin.name()),
+        Type::NamedType(name) => out.push_str(name),
+        Type::Array(ty, size) => {
+            out.push('[');
+            write_canonical_type(ty, out);
+            out.push_str(&format!("; {}]", size));
+        },
+        Type::DynamicArray(ty) => {
+            out.push('[');
+            write_canonical_type(ty, out);
+            out.push(']');
+        },
+        Type::Struct(fields) => {
+            out.push_str("struct ");
+            write_canonical_fields(fields, out);
+        },
+        Type::Enum(variants) => {
+            out.push_str("enum { ");
+
+            for (i, variant) in variants.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
                 }
-            }
 
-            let ret = if let Some(ty) = &func.ret {
-                if let Some(ty) = try_reduce_type(ty, &type_lookup) {
-                    Some(ty)
-                } else {
-                    panic!("Failed to reduce type of return value in function {}", name);
+                out.push_str(&variant.name);
+
+                if !variant.types.is_empty() {
+                    out.push(' ');
+                    write_canonical_fields(&variant.types, out);
                 }
-            } else {
-                None
-            };
+            }
 
-            functions.push((name.clone(), FunctionSignature {
-                args,
-                ret
-            }));
+            out.push_str(" }");
         }
+    }
+}
 
-        GameInterface {
-            name: self.name.clone(),
-            types,
-            functions
+fn write_canonical_signature(keyword: &str, name: &str, func: &FunctionSignature, out: &mut String) {
+    out.push_str(&format!("{} {} = (", keyword, name));
+
+    for (i, (arg, ty)) in func.args.iter().enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+
+        out.push_str(arg);
+        out.push_str(": ");
+        write_canonical_type(ty, out);
+    }
+
+    out.push(')');
+
+    if let Some(ret) = &func.ret {
+        out.push_str(" -> ");
+        write_canonical_type(ret, out);
+    }
+
+    out.push_str(";\n");
+}
+
+//Bumped whenever the wire protocol changes, so agents built with older client files are asked to rebuild
+const PROTOCOL_VERSION: u32 = 2;
+
+impl GameInterface {
+    //The name is left out as it depends on where the interface was loaded from
+    pub fn canonical_form(&self) -> String {
+        let itf = self.reduced();
+        let mut out = format!("protocol {};\n", PROTOCOL_VERSION);
+
+        if itf.framed {
+            out.push_str("option framed;\n");
+        }
+
+        for (name, ty) in &itf.types {
+            out.push_str(&format!("type {} = ", name));
+            write_canonical_type(ty, &mut out);
+            out.push_str(";\n");
         }
+
+        for (name, func) in &itf.functions {
+            write_canonical_signature("function", name, func, &mut out);
+        }
+
+        for (name, func) in &itf.callbacks {
+            write_canonical_signature("callback", name, func, &mut out);
+        }
+
+        out
+    }
+
+    //FNV-1a, so that the value is stable across builds and easy to reproduce
+    pub fn interface_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+
+        let mut hash = FNV_OFFSET;
+
+        for byte in self.canonical_form().bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+
+        hash
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_game_interface;
+
+    const SOURCE: &str = "
+        type Cell = enum { Empty, Nought, Cross };
+        type Pos = struct { row: u8, col: u8 };
+        type Board = [[Cell; 3]; 3];
+
+        function get_move = (board: Board, piece: Cell) -> Pos;
+        callback log_move = (pos: Pos);
+    ";
+
+    fn hash(source: &str) -> u64 {
+        parse_game_interface(source, "game".to_string()).unwrap().interface_hash()
+    }
+
+    #[test]
+    fn test_interface_hash() {
+        let base = hash(SOURCE);
+
+        //Stable across parses, names and formatting
+        assert_eq!(base, hash(SOURCE));
+        assert_eq!(base, parse_game_interface(SOURCE, "other".to_string()).unwrap().interface_hash());
+        assert_eq!(base, hash(&SOURCE.replace("\n        ", " ")));
+
+        let changes = [
+            ("row: u8, col: u8", "row: u8, column: u8"),
+            ("row: u8, col: u8", "col: u8, row: u8"),
+            ("row: u8, col: u8", "row: u16, col: u8"),
+            ("[[Cell; 3]; 3]", "[[Cell; 3]; 4]"),
+            ("Empty, Nought, Cross", "Empty, Cross, Nought"),
+            ("(board: Board, piece: Cell)", "(piece: Cell, board: Board)"),
+            ("-> Pos", "-> u8"),
+            ("callback log_move", "callback log_pos"),
+        ];
+
+        for (from, to) in changes {
+            assert_ne!(base, hash(&SOURCE.replace(from, to)), "{} -> {}", from, to);
+        }
+
+        assert_ne!(base, hash(&format!("option framed;\n{}", SOURCE)));
+    }
+}
@@ -1,4 +1,4 @@
-use std::iter::Peekable;
+use std::{collections::HashMap, iter::Peekable};
 
 use super::game_interface::{BuiltinType, GameInterface, Type, StructFields, StructField, EnumVariants, EnumVariant, FunctionSignature};
 
@@ -10,9 +10,9 @@ enum TokenData {
 
     Identifier(String), Number(i64), BuiltinType(BuiltinType),
 
-    Colon, Comma, Semicolon, Equals, Arrow,
+    Colon, Comma, Semicolon, Equals, Arrow, Hash,
 
-    Type, Function, Enum, Struct
+    Type, Function, Callback, Option, Enum, Struct
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -99,6 +99,7 @@ impl Iterator for Tokenizer {
             ',' => TokenData::Comma,
             ';' => TokenData::Semicolon,
             '=' => TokenData::Equals,
+            '#' => TokenData::Hash,
             '-' => {
                 end += 1;
                 match self.get_char(end - 1) {
@@ -143,6 +144,8 @@ impl Iterator for Tokenizer {
                 match ident.as_str() {
                     "type" => TokenData::Type,
                     "function" => TokenData::Function,
+                    "callback" => TokenData::Callback,
+                    "option" => TokenData::Option,
                     "enum" => TokenData::Enum,
                     "struct" => TokenData::Struct,
                     "u8" => TokenData::BuiltinType(BuiltinType::U8),
@@ -193,7 +196,10 @@ impl Parser {
             res: GameInterface {
                 name,
                 types: Vec::new(),
-                functions: Vec::new()
+                functions: Vec::new(),
+                callbacks: Vec::new(),
+                framed: false,
+                type_derives: HashMap::new()
             }
         }
     }
@@ -234,7 +240,41 @@ impl Parser {
     fn parse_top_level(&mut self) -> Result<(), String> {
         match self.next()? {
             Token {data: TokenData::Type, ..} => self.parse_type_def()?,
-            Token {data: TokenData::Function, ..} => self.parse_function()?,
+            Token {data: TokenData::Function, ..} => {
+                let function = self.parse_function()?;
+                self.res.functions.push(function);
+            },
+            Token {data: TokenData::Callback, ..} => {
+                let (name, signature) = self.parse_function()?;
+
+                if signature.ret.is_some() {
+                    return Err(format!("Callback {} cannot have a return type", name));
+                }
+
+                self.res.callbacks.push((name, signature));
+            },
+            Token {data: TokenData::Option, ..} => self.parse_option()?,
+            Token {data: TokenData::Hash, line, col} => {
+                let derives = self.parse_attributes()?;
+
+                match self.next()? {
+                    Token {data: TokenData::Type, ..} => self.parse_type_def()?,
+                    token => {
+                        return Err(format!("Expected type definition after attributes, got {:?}", token));
+                    }
+                }
+
+                let (name, ty) = self.res.types.last().unwrap();
+
+                match ty {
+                    Type::Struct(_) | Type::Enum(_) => {},
+                    _ => {
+                        return Err(format!("Derives can only be added to struct and enum types, not {}. Line {}, Col {}", name, line, col));
+                    }
+                }
+
+                self.res.type_derives.insert(name.clone(), derives);
+            },
             token => {
                 return Err(format!("Unexpected token {:?} at top level", token));
             }
@@ -245,6 +285,89 @@ impl Parser {
         Ok(())
     }
 
+    //Parses `#[derive(A, b::C)]`, possibly repeated. The leading '#' has already been consumed
+    fn parse_attributes(&mut self) -> Result<Vec<String>, String> {
+        let mut derives = Vec::new();
+
+        loop {
+            self.consume(TokenData::OpenBracket)?;
+
+            match self.next()? {
+                Token {data: TokenData::Identifier(name), ..} if name == "derive" => {},
+                Token {data: TokenData::Identifier(name), line, col} => {
+                    return Err(format!("Unknown attribute '{}'. Line {}, Col {}", name, line, col));
+                },
+                token => {
+                    return Err(format!("Expected attribute name, got {:?}", token));
+                }
+            }
+
+            self.consume(TokenData::OpenParen)?;
+
+            while self.peek()?.data != TokenData::CloseParen {
+                let path = self.parse_path()?;
+
+                if !derives.contains(&path) {
+                    derives.push(path);
+                }
+
+                if self.peek()?.data == TokenData::Comma {
+                    self.consume(TokenData::Comma)?;
+                } else {
+                    break;
+                }
+            }
+
+            self.consume(TokenData::CloseParen)?;
+            self.consume(TokenData::CloseBracket)?;
+
+            if self.peek()?.data == TokenData::Hash {
+                self.consume(TokenData::Hash)?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(derives)
+    }
+
+    fn parse_path(&mut self) -> Result<String, String> {
+        let mut path = String::new();
+
+        loop {
+            match self.next()? {
+                Token {data: TokenData::Identifier(name), ..} => path.push_str(&name),
+                token => {
+                    return Err(format!("Expected identifier, got {:?}", token));
+                }
+            }
+
+            if self.peek()?.data != TokenData::Colon {
+                break;
+            }
+
+            self.consume(TokenData::Colon)?;
+            self.consume(TokenData::Colon)?;
+            path.push_str("::");
+        }
+
+        Ok(path)
+    }
+
+    fn parse_option(&mut self) -> Result<(), String> {
+        match self.next()? {
+            Token {data: TokenData::Identifier(name), ..} if name == "framed" => self.res.framed = true,
+            Token {data: TokenData::Identifier(name), line, col} => {
+                return Err(format!("Unknown option '{}'. Line {}, Col {}", name, line, col));
+            },
+            token => {
+                return Err(format!("Expected option name, got {:?}", token));
+            }
+        };
+
+        Ok(())
+    }
+
     fn parse_type_def(&mut self) -> Result<(), String> {
         let name = match self.tokens.next().unwrap()? {
             Token {data: TokenData::Identifier(name), ..} => name,
@@ -380,7 +503,7 @@ impl Parser {
         }
     }
 
-    fn parse_function(&mut self) -> Result<(), String> {
+    fn parse_function(&mut self) -> Result<(String, FunctionSignature), String> {
         let name = match self.tokens.next().unwrap()? {
             Token{data: TokenData::Identifier(name), ..} => name,
             token => {
@@ -424,15 +547,13 @@ impl Parser {
             None
         };
 
-        self.res.functions.push((
+        Ok((
             name,
             FunctionSignature {
                 args,
                 ret: ret_ty
             }
-        ));
-
-        Ok(())
+        ))
     }
 }
 
@@ -4,6 +4,15 @@ mod m20231105_000002_create_agent;
 mod m20231105_000001_create_user;
 mod m20231107_000003_add_partial;
 mod m20231229_000004_add_color;
+mod m20261018_000005_add_move_time;
+mod m20261018_000006_add_faults;
+mod m20261018_000007_add_source_size;
+mod m20261018_000008_add_variant;
+mod m20261018_000009_add_sessions;
+mod m20261018_000010_add_roles;
+mod m20261018_000011_add_invites;
+mod m20261018_000012_add_api_tokens;
+mod m20261018_000013_hash_session_tokens;
 
 pub struct Migrator;
 
@@ -14,7 +23,16 @@ impl MigratorTrait for Migrator {
             Box::new(m20231105_000002_create_agent::Migration),
             Box::new(m20231105_000001_create_user::Migration),
             Box::new(m20231107_000003_add_partial::Migration),
-            Box::new(m20231229_000004_add_color::Migration)
+            Box::new(m20231229_000004_add_color::Migration),
+            Box::new(m20261018_000005_add_move_time::Migration),
+            Box::new(m20261018_000006_add_faults::Migration),
+            Box::new(m20261018_000007_add_source_size::Migration),
+            Box::new(m20261018_000008_add_variant::Migration),
+            Box::new(m20261018_000009_add_sessions::Migration),
+            Box::new(m20261018_000010_add_roles::Migration),
+            Box::new(m20261018_000011_add_invites::Migration),
+            Box::new(m20261018_000012_add_api_tokens::Migration),
+            Box::new(m20261018_000013_hash_session_tokens::Migration)
         ]
     }
 }
@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231105_000002_create_agent::Agent;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        //Sqlite only supports one change per ALTER TABLE
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .add_column(
+                        ColumnDef::new(Columns::NumMoves)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .add_column(
+                        ColumnDef::new(Columns::MoveTime)
+                            .double()
+                            .not_null()
+                            .default(0.0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .drop_column(Columns::NumMoves)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .drop_column(Columns::MoveTime)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Columns {
+    NumMoves,
+    MoveTime
+}
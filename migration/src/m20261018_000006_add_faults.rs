@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231105_000002_create_agent::Agent;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .add_column(
+                        ColumnDef::new(Columns::FailedGames)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Fault::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Fault::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Fault::AgentId).integer().not_null())
+                    .col(ColumnDef::new(Fault::Kind).string().not_null())
+                    .col(ColumnDef::new(Fault::Message).string().not_null())
+                    .col(ColumnDef::new(Fault::Forfeit).boolean().not_null().default(false))
+                    .col(ColumnDef::new(Fault::Time).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Fault::Table, Fault::AgentId)
+                            .to(Agent::Table, Agent::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Fault::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agent::Table)
+                    .drop_column(Columns::FailedGames)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Columns {
+    FailedGames
+}
+
+#[derive(DeriveIden)]
+pub enum Fault {
+    Table,
+
+    Id,
+    AgentId,
+    Kind,
+    Message,
+    Forfeit,
+    //Unix time in seconds
+    Time
+}
@@ -0,0 +1,94 @@
+use argon2::{password_hash::{rand_core::OsRng, SaltString}, Argon2, PasswordHasher};
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+use crate::m20231105_000001_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Session::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Session::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Session::Token).string().not_null().unique_key())
+                    .col(ColumnDef::new(Session::UserId).integer())
+                    .col(ColumnDef::new(Session::Admin).boolean().not_null().default(false))
+                    .col(ColumnDef::new(Session::Created).big_integer().not_null())
+                    .col(ColumnDef::new(Session::Expires).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Session::Table, Session::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //Passwords used to be stored as they are, hash whatever isn't hashed yet
+        let db = manager.get_connection();
+        let backend = db.get_database_backend();
+
+        let select = Query::select()
+            .columns([User::Id, User::Password])
+            .from(User::Table)
+            .to_owned();
+
+        for row in db.query_all(backend.build(&select)).await? {
+            let id: i32 = row.try_get("", "id")?;
+            let password: String = row.try_get("", "password")?;
+
+            if password.starts_with("$argon2") {
+                continue;
+            }
+
+            let salt = SaltString::generate(&mut OsRng);
+            let hash = Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map_err(|e| DbErr::Custom(format!("Couldn't hash password: {}", e)))?
+                .to_string();
+
+            let update = Query::update()
+                .table(User::Table)
+                .value(User::Password, hash)
+                .and_where(Expr::col(User::Id).eq(id))
+                .to_owned();
+
+            db.execute(backend.build(&update)).await?;
+        }
+
+        Ok(())
+    }
+
+    //The hashes can't be turned back into passwords, so they stay
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Session::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Session {
+    Table,
+
+    Id,
+    Token,
+    //None for admin sessions
+    UserId,
+    Admin,
+    //Unix times in seconds
+    Created,
+    Expires
+}
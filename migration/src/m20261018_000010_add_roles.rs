@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+use crate::{m20231105_000001_create_user::User, m20261018_000009_add_sessions::Session};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(Columns::Role)
+                            .string()
+                            .not_null()
+                            .default("contestant"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        //Admin sessions used to belong to no user, admins are users with the admin role now
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(Session::Table)
+                    .and_where(Expr::col(Session::UserId).is_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .drop_column(Session::Admin)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .add_column(
+                        ColumnDef::new(Session::Admin)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(Columns::Role)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Columns {
+    Role
+}
@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231105_000001_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invite::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Invite::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Invite::Code).string().not_null().unique_key())
+                    .col(ColumnDef::new(Invite::NumAgentsAllowed).integer().not_null())
+                    .col(ColumnDef::new(Invite::Username).string())
+                    .col(ColumnDef::new(Invite::CreatedBy).integer())
+                    .col(ColumnDef::new(Invite::RedeemedBy).integer())
+                    .col(ColumnDef::new(Invite::Created).big_integer().not_null())
+                    .col(ColumnDef::new(Invite::Redeemed).big_integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Invite::Table, Invite::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Invite::Table, Invite::RedeemedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invite::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Invite {
+    Table,
+
+    Id,
+    Code,
+    NumAgentsAllowed,
+    //Set when the code was issued for an imported username, only that name can redeem it
+    Username,
+    CreatedBy,
+    RedeemedBy,
+    //Unix times in seconds
+    Created,
+    Redeemed
+}
@@ -0,0 +1,64 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20231105_000001_create_user::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiToken::UserId).integer().not_null())
+                    .col(ColumnDef::new(ApiToken::Name).string().not_null())
+                    .col(ColumnDef::new(ApiToken::Prefix).string().not_null())
+                    .col(ColumnDef::new(ApiToken::Hash).string().not_null().unique_key())
+                    .col(ColumnDef::new(ApiToken::Scopes).string().not_null())
+                    .col(ColumnDef::new(ApiToken::Created).big_integer().not_null())
+                    .col(ColumnDef::new(ApiToken::LastUsed).big_integer())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(ApiToken::Table, ApiToken::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ApiToken {
+    Table,
+
+    Id,
+    UserId,
+    Name,
+    //Start of the token, so users can tell their tokens apart
+    Prefix,
+    //SHA-256 of the whole token
+    Hash,
+    //Comma separated
+    Scopes,
+    //Unix times in seconds
+    Created,
+    LastUsed
+}
@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20261018_000009_add_sessions::Session;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        //Existing sessions hold the tokens themselves, so everyone logs in again
+        manager
+            .exec_stmt(Query::delete().from_table(Session::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .rename_column(Session::Token, Columns::Hash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(Query::delete().from_table(Session::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Session::Table)
+                    .rename_column(Columns::Hash, Session::Token)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Columns {
+    //SHA-256 of the token in the session cookie
+    Hash
+}
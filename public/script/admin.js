@@ -1,4 +1,7 @@
 let authed = false;
+let role = null;
+
+const ROLES = ['spectator', 'contestant', 'organiser', 'admin'];
 
 function getCookie(name) {
     const value = `; ${document.cookie}`;
@@ -6,48 +9,40 @@ function getCookie(name) {
     if (parts.length === 2) return parts.pop().split(';').shift();
 }
 
-function setPassword() {
-    const element = document.getElementById('admin-password');
-    const content = element.value;
-
-    const expiry = new Date();
-    expiry.setDate(expiry.getTime() + 6 * 60 * 60 * 1000); // 6 hours
-    document.cookie = `admin=${content};expires=${expiry.toUTCString()};path=/;SameSite=Strict`;
-    authed = false;
-
-    verifyPassword();
-}
+//Passwords are only known right after they are generated
+let newPasswords = {};
 
 function onLoad() {
     setInterval(updateProfileList, 10000);
-    updatePassword();
+    verifyAccess();
 }
 
-function verifyPassword() {
-    const element = document.getElementById('admin-password-status');
+function verifyAccess() {
+    const element = document.getElementById('admin-access-status');
     fetch('/admin/verify').then(response => {
         if (response.status === 200) {
-            element.style.color = '#555';
-            element.innerText = 'Password is correct';
-            authed = true;
-            updateProfileList(true);
+            response.json().then(data => {
+                role = data.role;
+                element.style.color = '#555';
+                element.innerText = `Logged in as ${role}`;
+                authed = true;
+
+                //Only admins can reset the competition
+                document.getElementById('admin-powers').style.display = role === 'admin' ? 'block' : 'none';
+                updateProfileList(true);
+                updateInviteList();
+            });
         } else {
             element.style.color = 'red';
-            element.innerText = 'Password is incorrect';
+            element.innerText = 'Log in with an organiser or admin account to use these controls';
             authed = false;
         }
     });
 }
 
-function updatePassword() {
-    const element = document.getElementById('admin-password');
-
-    const password = getCookie('admin');
-
-    if (password) {
-        element.value = password;
-        verifyPassword();
-    }
+//Organisers can only give out the roles below their own
+function assignableRoles() {
+    return role === 'admin' ? ROLES : ROLES.slice(0, ROLES.indexOf(role));
 }
 
 function fullReset() {
@@ -84,7 +79,7 @@ function generateProfileTable(data) {
     table = document.getElementById('profile-list');
     table.innerHTML = '';
 
-    const HEADERS = ['Id', 'Username', 'Password', 'No. Agents', 'Controls'];
+    const HEADERS = ['Id', 'Username', 'Role', 'Password', 'No. Agents', 'Controls'];
 
     const headerRow = document.createElement('tr');
     for (const header of HEADERS) {
@@ -113,10 +108,32 @@ function generateProfileTable(data) {
         username.appendChild(link);
         row.appendChild(username);
 
+        const roleContainer = document.createElement('td');
+        const roleSelect = document.createElement('select');
+        for (const option of ROLES) {
+            const element = document.createElement('option');
+            element.innerText = option;
+            element.value = option;
+            element.disabled = !assignableRoles().includes(option);
+            roleSelect.appendChild(element);
+        }
+        roleSelect.value = profile.role;
+        roleSelect.onchange = e => {
+            fetch(`/admin/set_role?id=${profile.id}&role=${e.target.value}`, {
+                method: 'POST'
+            }).then(() => updateProfileList(true));
+        };
+        roleContainer.appendChild(roleSelect);
+        row.appendChild(roleContainer);
+
         const passwordContainer = document.createElement('td');
         const password = document.createElement('span');
-        password.innerText = profile.password;
-        password.classList.add('show-on-hover');
+        if (profile.id in newPasswords) {
+            password.innerText = newPasswords[profile.id];
+            password.classList.add('show-on-hover');
+        } else {
+            password.innerText = 'Hidden';
+        }
         passwordContainer.appendChild(password);
         row.appendChild(passwordContainer);
 
@@ -173,7 +190,7 @@ function updateProfileList(force) {
         if (response.status === 200) {
             return response.json();
         } else {
-            verifyPassword();
+            verifyAccess();
         }
     }).then(data => {
         if (force || !areObjectsEqual(data, prevData)) {
@@ -183,7 +200,7 @@ function updateProfileList(force) {
     });
 }
 
-function makeNewProfile(username, numAgents) {
+function makeNewProfile(username, numAgents, newRole) {
     if (!authed) return;
 
     if (username.length == 0) {
@@ -193,15 +210,17 @@ function makeNewProfile(username, numAgents) {
         return;
     }
 
-    fetch(`/admin/new_profile?username=${username}&agents=${numAgents}`, {
+    fetch(`/admin/new_profile?username=${username}&agents=${numAgents}&role=${newRole}`, {
         method: 'POST'
     }).then(response => {
         const element = document.getElementById('new-profile-status');
         if (response.status === 200) {
             console.log('Profile created!');
 
-            element.style.color = 'green';
-            element.innerText = 'Profile created!';
+            response.text().then(password => {
+                element.style.color = 'green';
+                element.innerText = `Profile created! Password: ${password}`;
+            });
         } else {
             console.log('Profile creation failed!');
             console.log(response);
@@ -224,20 +243,120 @@ function addProfile() {
         numAgents = agentElement.value;
     }
 
-    makeNewProfile(username, numAgents);
+    const newRole = document.getElementById('new-profile-role').value;
+
+    makeNewProfile(username, numAgents, newRole);
 }
 
 function resetPassword(id) {
     fetch(`/api/reset_password?id=${id}`, {
         method: 'POST'
     }).then(response => response.text()).then(text => {
-        if (getCookie("id") == id) {
-            date = new Date();
-            date.setTime(date.getTime() + (6 * 60 * 60 * 1000));
+        newPasswords[id] = text;
+
+        updateProfileList(true);
+    });
+}
+
+function setInviteStatus(colour, text) {
+    const element = document.getElementById('new-invites-status');
+    element.style.color = colour;
+    element.innerText = text;
+}
+
+function inviteResponse(response) {
+    if (response.status === 200) {
+        response.json().then(invites => {
+            setInviteStatus('green', `Created ${invites.length} invite codes`);
+            updateInviteList();
+        });
+    } else {
+        response.text().then(text => setInviteStatus('red', text));
+    }
+}
+
+function numAgentsForInvites() {
+    const element = document.getElementById('new-invites-agents');
+    return element.value != "" ? element.value : 0;
+}
+
+function createInvites() {
+    if (!authed) return;
+
+    const count = document.getElementById('new-invites-count').value;
+    if (count == "") {
+        setInviteStatus('red', 'Provide a number of codes');
+        return;
+    }
+
+    fetch(`/admin/create_invites?count=${count}&agents=${numAgentsForInvites()}`, {
+        method: 'POST'
+    }).then(inviteResponse);
+}
+
+//One code per username, each can only be redeemed under that name
+function importInvites() {
+    if (!authed) return;
+
+    const file = document.getElementById('import-invites-file').files[0];
+    if (!file) {
+        setInviteStatus('red', 'Choose a CSV file of usernames');
+        return;
+    }
+
+    file.text().then(text => {
+        fetch(`/admin/import_invites?agents=${numAgentsForInvites()}`, {
+            method: 'POST',
+            body: text
+        }).then(inviteResponse);
+    });
+}
+
+function generateInviteTable(data) {
+    const table = document.getElementById('invite-table');
+    table.innerHTML = '';
+
+    const HEADERS = ['Code', 'Reserved For', 'No. Agents', 'Redeemed By', 'Controls'];
+
+    const headerRow = document.createElement('tr');
+    for (const header of HEADERS) {
+        const th = document.createElement('th');
+        th.innerText = header;
+        headerRow.appendChild(th);
+    }
+    table.appendChild(headerRow);
+
+    for (const invite of data) {
+        const row = document.createElement('tr');
 
-            document.cookie = `password=${text};expires=${date.toUTCString()};path=/;SameSite=Strict`;
+        for (const value of [invite.code, invite.username ?? '', invite.num_agents_allowed, invite.redeemed_by ?? '']) {
+            const td = document.createElement('td');
+            td.innerText = value;
+            row.appendChild(td);
         }
 
-        updateProfileList();
+        const controls = document.createElement('td');
+        if (invite.redeemed === null) {
+            const delButton = document.createElement('button');
+            delButton.innerText = 'Delete';
+            delButton.onclick = () => {
+                fetch(`/admin/delete_invite?id=${invite.id}`, {
+                    method: 'POST'
+                }).then(() => updateInviteList());
+            };
+            controls.appendChild(delButton);
+        }
+        row.appendChild(controls);
+
+        table.appendChild(row);
+    }
+}
+
+function updateInviteList() {
+    if (!authed) return;
+    fetch('/admin/invites').then(response => {
+        if (response.status === 200) {
+            response.json().then(generateInviteTable);
+        }
     });
-}
\ No newline at end of file
+}
@@ -231,9 +231,14 @@ function onLoad() {
     fetch(`/api/agent?agent=${agent_id}&error=true&src=true`).then(response => response.json()).then(agent => {
         fetch("/api/lang", { "cache": "force-cache" }).then(res => res.json()).then(langs => {
             lang_map = {};
+            variant_map = {};
 
             for (lang of langs) {
                 lang_map[lang.id] = lang.name;
+
+                for (variant of lang.variants) {
+                    variant_map[lang.id + "/" + variant.id] = variant.name;
+                }
             }
 
             titleElement.innerText = "Agent - " + agent.name;
@@ -245,6 +250,11 @@ function onLoad() {
             if (language in lang_map) {
                 language = lang_map[language];
             }
+
+            let variant = agent.language + "/" + agent.variant;
+            if (variant in variant_map) {
+                language += " (" + variant_map[variant] + ")";
+            }
             document.getElementById("agent-language").innerText = "Written in " + language;
 
             let authed = false;
@@ -298,6 +308,15 @@ function onLoad() {
                 document.getElementById("agent-error-display").innerText = agent.error;
             }
 
+            if ("faults" in agent && agent.faults.length) {
+                document.getElementById("agent-faults").style.display = "block";
+                document.getElementById("agent-faults-display").innerText = agent.faults.map(fault => {
+                    const time = new Date(fault.time * 1000).toLocaleString();
+                    const forfeit = fault.forfeit ? " (forfeited)" : "";
+                    return `${time} ${fault.kind}${forfeit}: ${fault.message}`;
+                }).join("\n");
+            }
+
             if ("src" in agent) {
                 document.getElementById("agent-source").style.display = "block";
                 document.getElementById("agent-source-display").innerText = agent.src;
@@ -311,12 +330,5 @@ function resetPassword() {
         method: 'POST'
     }).then(response => response.text()).then(text => {
         document.getElementById('new-password').innerText = text;
-
-        if (getCookie("id") == id) {
-            date = new Date();
-            date.setTime(date.getTime() + (6 * 60 * 60 * 1000));
-
-            document.cookie = `password=${text};expires=${date.toUTCString()};path=/;SameSite=Strict`;
-        }
     });
 }
\ No newline at end of file
@@ -28,12 +28,15 @@ function areObjectsEqual(o1, o2) {
     }
 }
 
-function logOut() {
-    console.log(document.cookie);
+function clearLogin() {
     document.cookie = "id=;path=/;expires=Thu, 01 Jan 1970 00:00:01 GMT"
-    document.cookie = "password=;path=/;expires=Thu, 01 Jan 1970 00:00:01 GMT"
+}
 
-    location.reload();
+function logOut() {
+    fetch("/api/logout", { method: "POST" }).then(() => {
+        clearLogin();
+        location.reload();
+    });
 }
 
 function makeLoggedOut(menu) {
@@ -102,21 +105,16 @@ function makeLoggedIn(menu, id) {
 function commonLoad() {
     let profileOptions = document.getElementById("header-profile-options");
 
+    //Set straight away for pages that need it while loading, the session check below is what counts
     id = getCookie("id");
-    password = getCookie("password");
-    if (!id || !password) {
-        makeLoggedOut(profileOptions);
-    } else {
-        fetch(`/api/auth?id=${id}&password=${password}`).then(res => {
-            if (res.status != 200) {
-                logOut();
-            } else {
-                return res.json();
-            }
-        }).then(data => {
-            if (!data.correct) logOut();
 
+    fetch("/api/session").then(res => res.json()).then(data => {
+        if (!data.logged_in) {
+            clearLogin();
+            makeLoggedOut(profileOptions);
+        } else {
+            id = data.id;
             makeLoggedIn(profileOptions, id);
-        });
-    }
+        }
+    });
 }
\ No newline at end of file
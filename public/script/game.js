@@ -96,19 +96,21 @@ class TicTacToe {
     }
 
     updateGame(element, data) {
-        if (data.kind == "grid_state") {
+        const [kind, board] = data;
+
+        if (kind == "grid_state") {
             for (let r = 0; r < 3; r++) {
                 for (let c = 0; c < 3; c++) {
                     const id = "ttt-cell-" + r + "-" + c;
                     const cell = document.getElementById(id);
 
-                    if (data.data[r][c] == "Cross") {
+                    if (board[r][c] == "Cross") {
                         cell.innerText = "X";
                         if (!cell.classList.contains("tic-tac-toe-cross")) {
                             cell.classList.add("tic-tac-toe-cross");
                         }
                         cell.style.color = this.xColor;
-                    } else if (data.data[r][c] == "Nought") {
+                    } else if (board[r][c] == "Nought") {
                         cell.innerText = "O";
                         if (!cell.classList.contains("tic-tac-toe-nought")) {
                             cell.classList.add("tic-tac-toe-nought");
@@ -133,7 +135,7 @@ class TicTacToe {
             ]
 
             for (let line of lines) {
-                if (data.data[line[0][0]][line[0][1]] != "Empty" && data.data[line[0][0]][line[0][1]] == data.data[line[1][0]][line[1][1]] && data.data[line[0][0]][line[0][1]] == data.data[line[2][0]][line[2][1]]) {
+                if (board[line[0][0]][line[0][1]] != "Empty" && board[line[0][0]][line[0][1]] == board[line[1][0]][line[1][1]] && board[line[0][0]][line[0][1]] == board[line[2][0]][line[2][1]]) {
                     console.log("Found win!");
                     for (let r = 0; r < 3; r++) {
                         for (let c = 0; c < 3; c++) {
@@ -153,7 +155,7 @@ class TicTacToe {
     }
 
     shouldWaitForUpdate(data) {
-        return true;
+        return data[0] == "grid_state";
     }
 
     endGame(element) {
@@ -291,13 +293,8 @@ class NZOISnake {
                 }
             }
         } else if (packetKind == "upd") {
-            for (let arr of packetData) {
-                const newVal = arr[0];
-
-                for (let i = 1; i < arr.length; i += 2) {
-                    const row = arr[i];
-                    const col = arr[i+1];
-
+            for (let [newVal, positions] of packetData) {
+                for (let {row, col} of positions) {
                     const cell = document.getElementById("snake-cell-" + row + "-" + col);
 
                     let colour;
@@ -314,6 +311,11 @@ class NZOISnake {
                 this.scores[i] += packetData[i];
                 this.scoreElements[i].innerText = `${this.scores[i]}`
             }
+        } else if (packetKind == "time") {
+            for (let i = 0; i < this.scoreElements.length; i++) {
+                const stats = packetData[i];
+                this.scoreElements[i].title = `Time bank: ${stats.bank_s.toFixed(2)}s, slowest move: ${stats.max_s.toFixed(2)}s`;
+            }
         }
     }
 
@@ -453,51 +455,73 @@ function connect() {
         }
     }
 
-    let req;
-
-    if (agentId == -1) {
-        req = "\"Any\"";
-    } else {
-        req = JSON.stringify({ "WithPlayer": agentId });
+    if (activeSource) {
+        activeSource.close();
+        activeSource = undefined;
     }
 
-    let url = `/bruh?req=${encodeURIComponent(req)}`;
+    //Event streams are the fallback for browsers without WebSockets
+    if (!("WebSocket" in window)) {
+        let req;
 
-    if (activeSource) {
-        activeSource.close();
+        if (agentId == -1) {
+            req = "\"Any\"";
+        } else {
+            req = JSON.stringify({ "WithPlayer": agentId });
+        }
+
+        activeSource = new EventSource(`/bruh?req=${encodeURIComponent(req)}`);
+        activeSource.onmessage = (m) => handlePacket(JSON.parse(m.data));
+        return;
     }
 
-    activeSource = new EventSource(url);
-    activeSource.onmessage = (m) => {
-        json = JSON.parse(m.data);
-        console.log(json);
-        const e = document.getElementById("game-display");
+    const scheme = location.protocol == "https:" ? "wss" : "ws";
+    const socket = new WebSocket(`${scheme}://${location.host}/spectate`);
+
+    socket.onopen = () => {
+        socket.send(JSON.stringify({ "cmd": "follow", "agent": agentId == -1 ? null : agentId }));
+    };
+    socket.onmessage = (m) => {
+        const json = JSON.parse(m.data);
 
-        if (json.kind == "connect") {
-            e.innerHTML = "";
-            gameEngine = GAME_MAP[json.data.kind]
-            gameEngine.startGame(e, json.data.players);
-            lastUpdate = new Date();
+        if (json.kind == "error") {
+            console.error(json.data);
+        } else if (json.kind != "games") {
+            handlePacket(json);
+        }
+    };
 
-            eventQueue.length = 0;
+    activeSource = socket;
+}
 
-            for (p of json.data.history) {
-                eventQueue.push({
-                    "kind": "upd",
-                    "data": JSON.parse(p)
-                });
-            }
+function handlePacket(json) {
+    console.log(json);
+    const e = document.getElementById("game-display");
 
-            processQueue();
-        } else {
-            eventQueue.push(json);
+    if (json.kind == "connect") {
+        e.innerHTML = "";
+        gameEngine = GAME_MAP[json.data.kind]
+        gameEngine.startGame(e, json.data.players);
+        lastUpdate = new Date();
 
-            if (json.kind == "end") {
-                activeSource.close();
-                activeSource = undefined;
-            }
+        eventQueue.length = 0;
 
-            processQueue();
+        for (p of json.data.history) {
+            eventQueue.push({
+                "kind": "upd",
+                "data": JSON.parse(p)
+            });
         }
-    };
+
+        processQueue();
+    } else {
+        eventQueue.push(json);
+
+        if (json.kind == "end" && activeSource) {
+            activeSource.close();
+            activeSource = undefined;
+        }
+
+        processQueue();
+    }
 }
\ No newline at end of file
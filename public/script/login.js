@@ -1,15 +1,11 @@
-function login(username, password) {
+function login(id) {
     const expiry = new Date();
-    expiry.setDate(expiry.getTime() + 24 * 60 * 60 * 1000); // 24 hours
+    expiry.setTime(expiry.getTime() + 24 * 60 * 60 * 1000); // 24 hours
 
-    const extra = `;path=/;SameSite=Strict;expires=${expiry.toUTCString()}`;
+    //Only tells the pages who is logged in, the session cookie itself is set by the server
+    document.cookie = `id=${id};path=/;SameSite=Strict;expires=${expiry.toUTCString()}`;
 
-    fetch(`/api/profile?username=${username}`).then(res => res.json()).then(data => {
-        document.cookie = `id=${data.id}` + extra;
-        document.cookie = `password=${password}` + extra;
-
-        window.location = `/pages/profile.html?id=${data.id}`;
-    });
+    window.location = `/pages/profile.html?id=${id}`;
 }
 
 function tryLogin() {
@@ -19,23 +15,27 @@ function tryLogin() {
 
     const feedback = document.getElementById("feedback");
 
-    fetch(`/api/auth?username=${username}&password=${password}`).then(res => {
-        if (res.status == 404) {
+    fetch("/api/login", {
+        method: "POST",
+        body: JSON.stringify({
+            "username": username,
+            "password": password
+        })
+    }).then(res => {
+        if (res.status == 401) {
+            feedback.style.color = 'red';
+            feedback.innerText = "Incorrect Username or Password";
+        } else if (res.status == 429) {
             feedback.style.color = 'red';
-            feedback.innerText = "Incorrect Username";
+            feedback.innerText = `Too many attempts, try again in ${res.headers.get("Retry-After")} seconds`;
         } else if (res.status != 200) {
             feedback.style.color = 'red';
             feedback.innerText = "Error";
         } else {
             res.json().then(data => {
-                if (!data.correct) {
-                    feedback.style.color = 'red';
-                    feedback.innerText = "Incorrect Password";
-                } else {
-                    feedback.style.color = 'green';
-                    feedback.innerText = "Correct";
-                    login(username, password);
-                }
+                feedback.style.color = 'green';
+                feedback.innerText = "Correct";
+                login(data.id);
             })
         }
     })
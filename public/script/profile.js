@@ -66,6 +66,12 @@ function onLoad() {
                 const hidden = document.getElementById("hidden-info");
                 hidden.style.display = "block";
 
+                if (profileId == id) {
+                    document.getElementById("revoke-sessions").style.display = "block";
+                    document.getElementById("api-tokens").style.display = "block";
+                    updateTokenList();
+                }
+
                 const agentGrid = document.getElementById("agent-grid");
 
                 for (const agent of profile.agents) {
@@ -159,12 +165,81 @@ function resetPassword() {
         method: 'POST'
     }).then(response => response.text()).then(text => {
         document.getElementById('new-password').innerText = text;
+    });
+}
+
+function revokeAllSessions() {
+    if (confirm("Log out of every device, including this one?")) {
+        fetch("/api/revoke_all_sessions", {
+            method: 'POST'
+        }).then(() => {
+            clearLogin();
+            window.location = "/public/login.html";
+        });
+    }
+}
+
+function formatTime(seconds) {
+    return seconds === null ? "Never" : new Date(seconds * 1000).toLocaleString();
+}
 
-        if (getCookie("id") == id) {
-            date = new Date();
-            date.setTime(date.getTime() + (6 * 60 * 60 * 1000));
+function updateTokenList() {
+    fetch("/api/tokens").then(response => response.json()).then(tokens => {
+        const table = document.getElementById("token-list");
+        table.innerHTML = "";
 
-            document.cookie = `password=${text};expires=${date.toUTCString()};path=/;SameSite=Strict`;
+        const header = table.insertRow();
+        for (const title of ["Name", "Token", "Scopes", "Created", "Last Used", ""]) {
+            const cell = document.createElement("th");
+            cell.innerText = title;
+            header.appendChild(cell);
         }
+
+        for (const token of tokens) {
+            const row = table.insertRow();
+            row.insertCell().innerText = token.name;
+            row.insertCell().innerText = token.prefix + "...";
+            row.insertCell().innerText = token.scopes.join(", ");
+            row.insertCell().innerText = formatTime(token.created);
+            row.insertCell().innerText = formatTime(token.last_used);
+
+            const revokeButton = document.createElement("button");
+            revokeButton.innerText = "Revoke";
+            revokeButton.onclick = () => {
+                if (confirm(`Revoke ${token.name}? Anything using it will stop working`)) {
+                    fetch(`/api/revoke_token?token=${token.id}`, {
+                        method: 'POST'
+                    }).then(() => updateTokenList());
+                }
+            };
+            row.insertCell().appendChild(revokeButton);
+        }
+    });
+}
+
+function createToken() {
+    const scopes = ["read", "submit", "delete"].filter(scope => document.getElementById(`token-scope-${scope}`).checked);
+    const error = document.getElementById("token-error");
+
+    fetch("/api/create_token", {
+        method: 'POST',
+        body: JSON.stringify({
+            name: document.getElementById("token-name").value,
+            scopes: scopes
+        })
+    }).then(response => {
+        if (!response.ok) {
+            return response.text().then(text => {
+                error.innerText = text;
+            });
+        }
+
+        return response.json().then(token => {
+            error.innerText = "";
+            document.getElementById("token-name").value = "";
+            document.getElementById("new-token-value").innerText = token.token;
+            document.getElementById("new-token").style.display = "block";
+            updateTokenList();
+        });
     });
-}
\ No newline at end of file
+}
@@ -0,0 +1,47 @@
+//Codes can be shared as links, e.g. /public/register.html?code=ABCD-EFGH-JKLM
+function onLoad() {
+    const code = new URLSearchParams(window.location.search).get("code");
+    if (code) {
+        document.getElementById("code-input").value = code;
+    }
+}
+
+function showError(text) {
+    const feedback = document.getElementById("feedback");
+    feedback.style.color = 'red';
+    feedback.innerText = text;
+}
+
+function tryRegister() {
+    const code = document.getElementById("code-input").value;
+    const username = document.getElementById("username-input").value;
+    const password = document.getElementById("password-input").value;
+    const confirm = document.getElementById("confirm-input").value;
+
+    if (password != confirm) {
+        showError("Passwords don't match");
+        return;
+    }
+
+    fetch("/api/register", {
+        method: "POST",
+        body: JSON.stringify({
+            "code": code,
+            "username": username,
+            "password": password
+        })
+    }).then(res => {
+        if (res.status != 200) {
+            res.text().then(text => showError(text.replace("Invalid Data: ", "")));
+        } else {
+            res.json().then(data => {
+                const expiry = new Date();
+                expiry.setTime(expiry.getTime() + 24 * 60 * 60 * 1000); // 24 hours
+
+                document.cookie = `id=${data.id};path=/;SameSite=Strict;expires=${expiry.toUTCString()}`;
+
+                window.location = `/pages/profile.html?id=${data.id}`;
+            });
+        }
+    });
+}
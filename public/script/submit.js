@@ -1,5 +1,8 @@
+let languages = [];
+
 function onLoad() {
     fetch("/api/lang").then(d => d.json()).then(data => {
+        languages = data;
         let select = document.getElementById("agent-language");
 
         for (lang of data) {
@@ -12,9 +15,26 @@ function onLoad() {
     });
 }
 
+function showVariants() {
+    let language = document.getElementById("agent-language").value;
+    let select = document.getElementById("agent-variant");
+    select.innerHTML = "";
+
+    let lang = languages.find(l => l.id === language);
+
+    for (variant of lang ? lang.variants : []) {
+        let option = document.createElement("option");
+        option.innerText = variant.name;
+        option.setAttribute("value", variant.id);
+
+        select.appendChild(option);
+    }
+}
+
 function submit() {
     let name = document.getElementById("agent-name").value.trim();
     let language = document.getElementById("agent-language").value;
+    let variant = document.getElementById("agent-variant").value;
     let source = document.getElementById("source-code").value.trim();
 
     let feedback = document.getElementById("feedback");
@@ -30,17 +50,50 @@ function submit() {
         return;
     }
 
+    let archive = document.getElementById("archive-file").files[0];
+
+    if (archive) {
+        let entry = document.getElementById("archive-entry").value.trim();
+
+        if (entry.length === 0) {
+            feedback.innerText = "Please enter the entry point of the archive!";
+            return;
+        }
+
+        let reader = new FileReader();
+        reader.onload = () => {
+            //Strip the data URL prefix to leave the base64 contents
+            let contents = reader.result.substring(reader.result.indexOf(",") + 1);
+
+            sendAgent({
+                "archive": contents,
+                "format": archive.name.endsWith(".tar") ? "tar" : "zip",
+                "entry": entry,
+                "lang": language,
+                "variant": variant,
+                "name": name
+            });
+        };
+        reader.readAsDataURL(archive);
+        return;
+    }
+
     if (source.length === 0) {
         feedback.innerText = "Please provide source code!";
     }
 
     console.log(name, language, source);
 
-    let body = {
+    sendAgent({
         "src": source,
         "lang": language,
+        "variant": variant,
         "name": name
-    };
+    });
+}
+
+function sendAgent(body) {
+    let feedback = document.getElementById("feedback");
 
     fetch(`/api/add_agent?id=${getCookie("id")}`, {
         "method": "POST",
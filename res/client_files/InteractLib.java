@@ -0,0 +1,163 @@
+import java.io.*;
+import java.nio.charset.StandardCharsets;
+import java.util.zip.CRC32;
+
+// Java has no unsigned types, so unsigned values are read into the next larger signed type (u64 stays a long)
+final class InteractLib {
+    private static final DataInputStream stdin = new DataInputStream(new BufferedInputStream(new FileInputStream(FileDescriptor.in)));
+    private static final OutputStream stdout = new BufferedOutputStream(new FileOutputStream(FileDescriptor.out));
+
+    // In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+    private static boolean framed = false;
+
+    private static DataInputStream in = stdin;
+    private static ByteArrayInputStream frame = null;
+
+    private static final ByteArrayOutputStream outBuffer = new ByteArrayOutputStream();
+    private static final DataOutputStream out = new DataOutputStream(outBuffer);
+
+    private InteractLib() {}
+
+    private static void fail(String message) {
+        System.err.println(message);
+        System.exit(1);
+    }
+
+    private static int readRawInt() throws IOException {
+        return Integer.reverseBytes(stdin.readInt());
+    }
+
+    static void enableFraming() {
+        framed = true;
+    }
+
+    // Reads the next frame from the server. Does nothing when framing is disabled
+    static void beginMessage() {
+        if (!framed) return;
+
+        try {
+            byte[] data = new byte[readRawInt()];
+            stdin.readFully(data);
+
+            CRC32 crc = new CRC32();
+            crc.update(data);
+
+            if (readRawInt() != (int) crc.getValue()) {
+                fail("Protocol error: checksum mismatch");
+            }
+
+            frame = new ByteArrayInputStream(data);
+            in = new DataInputStream(frame);
+        } catch (IOException e) {
+            fail("Unexpected EOF");
+        }
+    }
+
+    static void endMessage() {
+        if (framed && frame.available() != 0) {
+            fail("Protocol error: message is longer than expected");
+        }
+    }
+
+    private static void readFully(byte[] buf) {
+        try {
+            in.readFully(buf);
+        } catch (IOException e) {
+            fail(framed ? "Protocol error: message is shorter than expected" : "Unexpected EOF");
+        }
+    }
+
+    private static long readLittleEndian(int size) {
+        byte[] buf = new byte[size];
+        readFully(buf);
+
+        long res = 0;
+        for (int i = size - 1; i >= 0; i--) {
+            res = (res << 8) | (buf[i] & 0xFF);
+        }
+
+        return res;
+    }
+
+    static short readU8() { return (short) readLittleEndian(1); }
+    static int readU16() { return (int) readLittleEndian(2); }
+    static long readU32() { return readLittleEndian(4); }
+    static long readU64() { return readLittleEndian(8); }
+
+    static byte readI8() { return (byte) readLittleEndian(1); }
+    static short readI16() { return (short) readLittleEndian(2); }
+    static int readI32() { return (int) readLittleEndian(4); }
+    static long readI64() { return readLittleEndian(8); }
+
+    static float readF32() { return Float.intBitsToFloat((int) readLittleEndian(4)); }
+    static double readF64() { return Double.longBitsToDouble(readLittleEndian(8)); }
+
+    static boolean readBool() { return readU8() != 0; }
+
+    static String readStr() {
+        byte[] buf = new byte[(int) readU32()];
+        readFully(buf);
+        return new String(buf, StandardCharsets.UTF_8);
+    }
+
+    private static void writeLittleEndian(long value, int size) {
+        try {
+            for (int i = 0; i < size; i++) {
+                out.writeByte((int) (value >>> (8 * i)));
+            }
+        } catch (IOException e) {
+            fail("Failed to write output");
+        }
+    }
+
+    static void writeU8(short value) { writeLittleEndian(value, 1); }
+    static void writeU16(int value) { writeLittleEndian(value, 2); }
+    static void writeU32(long value) { writeLittleEndian(value, 4); }
+    static void writeU64(long value) { writeLittleEndian(value, 8); }
+
+    static void writeI8(byte value) { writeLittleEndian(value, 1); }
+    static void writeI16(short value) { writeLittleEndian(value, 2); }
+    static void writeI32(int value) { writeLittleEndian(value, 4); }
+    static void writeI64(long value) { writeLittleEndian(value, 8); }
+
+    static void writeF32(float value) { writeLittleEndian(Float.floatToRawIntBits(value), 4); }
+    static void writeF64(double value) { writeLittleEndian(Double.doubleToRawLongBits(value), 8); }
+
+    static void writeBool(boolean value) { writeLittleEndian(value ? 1 : 0, 1); }
+
+    static void writeStr(String value) {
+        byte[] data = value.getBytes(StandardCharsets.UTF_8);
+        writeU32(data.length);
+        outBuffer.write(data, 0, data.length);
+    }
+
+    static void flush() {
+        byte[] data = outBuffer.toByteArray();
+        outBuffer.reset();
+
+        try {
+            if (framed) {
+                if (data.length == 0) return;
+
+                CRC32 crc = new CRC32();
+                crc.update(data);
+
+                writeRaw(data.length, 4);
+                stdout.write(data);
+                writeRaw(crc.getValue(), 4);
+            } else {
+                stdout.write(data);
+            }
+
+            stdout.flush();
+        } catch (IOException e) {
+            fail("Failed to write output");
+        }
+    }
+
+    private static void writeRaw(long value, int size) throws IOException {
+        for (int i = 0; i < size; i++) {
+            stdout.write((int) (value >>> (8 * i)));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+package main
+
+import (
+	"bufio"
+	"encoding/binary"
+	"fmt"
+	"hash/crc32"
+	"io"
+	"math"
+	"os"
+)
+
+// In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+var framed = false
+
+var stdin = bufio.NewReader(os.Stdin)
+
+var inFrame []byte
+var inFramePos = 0
+
+var outBuffer []byte
+
+func fail(message string) {
+	fmt.Fprintln(os.Stderr, message)
+	os.Exit(1)
+}
+
+func readRaw(buf []byte) {
+	if _, err := io.ReadFull(stdin, buf); err != nil {
+		fail("Unexpected EOF")
+	}
+}
+
+func readBytes(n int) []byte {
+	if !framed {
+		buf := make([]byte, n)
+		readRaw(buf)
+		return buf
+	}
+
+	if inFramePos+n > len(inFrame) {
+		fail("Protocol error: message is shorter than expected")
+	}
+
+	buf := inFrame[inFramePos : inFramePos+n]
+	inFramePos += n
+	return buf
+}
+
+func writeBytes(data []byte) {
+	outBuffer = append(outBuffer, data...)
+}
+
+func enableFraming() {
+	framed = true
+}
+
+// Reads the next frame from the server. Does nothing when framing is disabled
+func beginMessage() {
+	if !framed {
+		return
+	}
+
+	header := make([]byte, 4)
+	readRaw(header)
+
+	inFrame = make([]byte, binary.LittleEndian.Uint32(header))
+	inFramePos = 0
+	readRaw(inFrame)
+
+	readRaw(header)
+	if binary.LittleEndian.Uint32(header) != crc32.ChecksumIEEE(inFrame) {
+		fail("Protocol error: checksum mismatch")
+	}
+}
+
+func endMessage() {
+	if framed && inFramePos != len(inFrame) {
+		fail("Protocol error: message is longer than expected")
+	}
+}
+
+func readU8() uint8   { return readBytes(1)[0] }
+func readU16() uint16 { return binary.LittleEndian.Uint16(readBytes(2)) }
+func readU32() uint32 { return binary.LittleEndian.Uint32(readBytes(4)) }
+func readU64() uint64 { return binary.LittleEndian.Uint64(readBytes(8)) }
+
+func readI8() int8   { return int8(readU8()) }
+func readI16() int16 { return int16(readU16()) }
+func readI32() int32 { return int32(readU32()) }
+func readI64() int64 { return int64(readU64()) }
+
+func readF32() float32 { return math.Float32frombits(readU32()) }
+func readF64() float64 { return math.Float64frombits(readU64()) }
+
+func readBool() bool { return readU8() != 0 }
+
+func readStr() string {
+	return string(readBytes(int(readU32())))
+}
+
+func writeU8(value uint8) { writeBytes([]byte{value}) }
+
+func writeU16(value uint16) {
+	var buf [2]byte
+	binary.LittleEndian.PutUint16(buf[:], value)
+	writeBytes(buf[:])
+}
+
+func writeU32(value uint32) {
+	var buf [4]byte
+	binary.LittleEndian.PutUint32(buf[:], value)
+	writeBytes(buf[:])
+}
+
+func writeU64(value uint64) {
+	var buf [8]byte
+	binary.LittleEndian.PutUint64(buf[:], value)
+	writeBytes(buf[:])
+}
+
+func writeI8(value int8)   { writeU8(uint8(value)) }
+func writeI16(value int16) { writeU16(uint16(value)) }
+func writeI32(value int32) { writeU32(uint32(value)) }
+func writeI64(value int64) { writeU64(uint64(value)) }
+
+func writeF32(value float32) { writeU32(math.Float32bits(value)) }
+func writeF64(value float64) { writeU64(math.Float64bits(value)) }
+
+func writeBool(value bool) {
+	if value {
+		writeU8(1)
+	} else {
+		writeU8(0)
+	}
+}
+
+func writeStr(value string) {
+	writeU32(uint32(len(value)))
+	writeBytes([]byte(value))
+}
+
+func flush() {
+	data := outBuffer
+	outBuffer = nil
+
+	if framed {
+		if len(data) == 0 {
+			return
+		}
+
+		frame := make([]byte, len(data)+8)
+		binary.LittleEndian.PutUint32(frame, uint32(len(data)))
+		copy(frame[4:], data)
+		binary.LittleEndian.PutUint32(frame[len(data)+4:], crc32.ChecksumIEEE(data))
+
+		data = frame
+	}
+
+	if _, err := os.Stdout.Write(data); err != nil {
+		fail("Failed to write output")
+	}
+}
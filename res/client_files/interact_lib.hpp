@@ -6,6 +6,7 @@
 #include <stdlib.h>
 #include <stdint.h>
 #include <string>
+#include <vector>
 
 //#define VERBOSE_IO
 
@@ -24,7 +25,7 @@ void* advanceBytes(void* ptr, int n) {
     return (void*) (((uint8_t*) ptr) + n);
 }
 
-void readBytes(int n, void* out) {
+void readRawBytes(int n, void* out) {
     int numRead = 0;
 
     while (numRead < n) {
@@ -42,7 +43,7 @@ void readBytes(int n, void* out) {
     }
 }
 
-void writeBytes(int n, void* bytes) {
+void writeRawBytes(int n, void* bytes) {
 #ifdef VERBOSE_IO
     std::cerr << "  Writing " << n << " bytes: [ ";
 
@@ -68,6 +69,99 @@ void writeBytes(int n, void* bytes) {
     }
 }
 
+// In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+bool FRAMED = false;
+
+std::vector<uint8_t> inFrame;
+size_t inFramePos = 0;
+
+std::vector<uint8_t> outFrame;
+
+uint32_t crc32(const uint8_t* data, size_t n) {
+    static uint32_t table[256];
+    static bool tableReady = false;
+
+    if (!tableReady) {
+        for (uint32_t i = 0; i < 256; i++) {
+            uint32_t c = i;
+            for (int k = 0; k < 8; k++) {
+                c = (c & 1) ? (0xEDB88320u ^ (c >> 1)) : (c >> 1);
+            }
+            table[i] = c;
+        }
+        tableReady = true;
+    }
+
+    uint32_t crc = 0xFFFFFFFFu;
+    for (size_t i = 0; i < n; i++) {
+        crc = table[(crc ^ data[i]) & 0xFF] ^ (crc >> 8);
+    }
+
+    return crc ^ 0xFFFFFFFFu;
+}
+
+void enableFraming() {
+    FRAMED = true;
+}
+
+void readBytes(int n, void* out) {
+    if (!FRAMED) {
+        readRawBytes(n, out);
+        return;
+    }
+
+    if (inFramePos + n > inFrame.size()) {
+        std::cerr << "Protocol error: message is shorter than expected" << std::endl;
+        exit(1);
+    }
+
+    for (int i = 0; i < n; i++) {
+        ((uint8_t*) out)[i] = inFrame[inFramePos + i];
+    }
+
+    inFramePos += n;
+}
+
+void writeBytes(int n, void* bytes) {
+    if (!FRAMED) {
+        writeRawBytes(n, bytes);
+        return;
+    }
+
+    outFrame.insert(outFrame.end(), (uint8_t*) bytes, ((uint8_t*) bytes) + n);
+}
+
+// Reads the next frame from the server. Does nothing when framing is disabled
+void beginMessage() {
+    if (!FRAMED) return;
+
+    uint32_t size;
+    readRawBytes(4, &size);
+
+    inFrame.resize(size);
+    inFramePos = 0;
+    if (size > 0) {
+        readRawBytes(size, &inFrame[0]);
+    }
+
+    uint32_t checksum;
+    readRawBytes(4, &checksum);
+
+    if (checksum != crc32(inFrame.data(), inFrame.size())) {
+        std::cerr << "Protocol error: checksum mismatch" << std::endl;
+        exit(1);
+    }
+}
+
+void endMessage() {
+    if (!FRAMED) return;
+
+    if (inFramePos != inFrame.size()) {
+        std::cerr << "Protocol error: message is longer than expected" << std::endl;
+        exit(1);
+    }
+}
+
 void reverseEndinness(int n, void* data) {
     uint8_t* start = (uint8_t*) data;
     uint8_t* end = start + n - 1;
@@ -151,6 +245,17 @@ void writeString(std::string& s) {
 }
 
 void flushStreams() {
+    if (FRAMED && !outFrame.empty()) {
+        uint32_t size = outFrame.size();
+        uint32_t checksum = crc32(outFrame.data(), outFrame.size());
+
+        writeRawBytes(4, &size);
+        writeRawBytes(size, &outFrame[0]);
+        writeRawBytes(4, &checksum);
+
+        outFrame.clear();
+    }
+
     fflush(stdout);
 }
 
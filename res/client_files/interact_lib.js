@@ -0,0 +1,216 @@
+const fs = require('fs');
+
+// Reads are done synchronously on the raw file descriptors so that values always arrive in order
+
+// In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+let FRAMED = false;
+
+let inFrame = Buffer.alloc(0);
+let inFramePos = 0;
+
+let outChunks = [];
+
+// Node can leave fd 0 non-blocking, where reads fail with EAGAIN instead of waiting for input.
+// Opening the pipe again gives a descriptor of our own that blocks
+const IN_FD = (() => {
+    try {
+        return fs.openSync('/dev/stdin', 'r');
+    } catch (e) {
+        return 0;
+    }
+})();
+
+const SLEEP_CELL = new Int32Array(new SharedArrayBuffer(4));
+
+const CRC_TABLE = (() => {
+    const table = new Uint32Array(256);
+
+    for (let i = 0; i < 256; i++) {
+        let c = i;
+        for (let k = 0; k < 8; k++) {
+            c = (c & 1) ? (0xEDB88320 ^ (c >>> 1)) : (c >>> 1);
+        }
+        table[i] = c >>> 0;
+    }
+
+    return table;
+})();
+
+function crc32(data) {
+    let crc = 0xFFFFFFFF;
+
+    for (let i = 0; i < data.length; i++) {
+        crc = CRC_TABLE[(crc ^ data[i]) & 0xFF] ^ (crc >>> 8);
+    }
+
+    return (crc ^ 0xFFFFFFFF) >>> 0;
+}
+
+function readRaw(n) {
+    const buf = Buffer.alloc(n);
+    let pos = 0;
+
+    while (pos < n) {
+        let read;
+
+        try {
+            read = fs.readSync(IN_FD, buf, pos, n - pos, null);
+        } catch (e) {
+            if (e.code !== 'EAGAIN') throw e;
+
+            // Only reached if fd 0 had to be used. Sleeping keeps the wait off the agent's CPU time
+            Atomics.wait(SLEEP_CELL, 0, 0, 1);
+            continue;
+        }
+
+        if (read === 0) {
+            process.stderr.write('Unexpected EOF\n');
+            process.exit(1);
+        }
+
+        pos += read;
+    }
+
+    return buf;
+}
+
+function read(n) {
+    if (!FRAMED) {
+        return readRaw(n);
+    }
+
+    if (inFramePos + n > inFrame.length) {
+        process.stderr.write('Protocol error: message is shorter than expected\n');
+        process.exit(1);
+    }
+
+    const res = inFrame.subarray(inFramePos, inFramePos + n);
+    inFramePos += n;
+    return res;
+}
+
+function write(data) {
+    outChunks.push(data);
+}
+
+function enable_framing() {
+    FRAMED = true;
+}
+
+// Reads the next frame from the server. Does nothing when framing is disabled
+function begin_message() {
+    if (!FRAMED) return;
+
+    const size = readRaw(4).readUInt32LE(0);
+    inFrame = readRaw(size);
+    inFramePos = 0;
+
+    const checksum = readRaw(4).readUInt32LE(0);
+    if (checksum !== crc32(inFrame)) {
+        process.stderr.write('Protocol error: checksum mismatch\n');
+        process.exit(1);
+    }
+}
+
+function end_message() {
+    if (FRAMED && inFramePos !== inFrame.length) {
+        process.stderr.write('Protocol error: message is longer than expected\n');
+        process.exit(1);
+    }
+}
+
+const read_u8 = () => read(1).readUInt8(0);
+const read_u16 = () => read(2).readUInt16LE(0);
+const read_u32 = () => read(4).readUInt32LE(0);
+const read_u64 = () => read(8).readBigUInt64LE(0);
+
+const read_i8 = () => read(1).readInt8(0);
+const read_i16 = () => read(2).readInt16LE(0);
+const read_i32 = () => read(4).readInt32LE(0);
+const read_i64 = () => read(8).readBigInt64LE(0);
+
+const read_f32 = () => read(4).readFloatLE(0);
+const read_f64 = () => read(8).readDoubleLE(0);
+
+const read_bool = () => read_u8() !== 0;
+
+function read_str() {
+    const length = read_u32();
+    return read(length).toString('utf8');
+}
+
+function writer(size, method) {
+    return (val) => {
+        const buf = Buffer.alloc(size);
+        buf[method](val, 0);
+        write(buf);
+    };
+}
+
+const write_u8 = writer(1, 'writeUInt8');
+const write_u16 = writer(2, 'writeUInt16LE');
+const write_u32 = writer(4, 'writeUInt32LE');
+const write_big_u64 = writer(8, 'writeBigUInt64LE');
+const write_u64 = (val) => write_big_u64(BigInt(val));
+
+const write_i8 = writer(1, 'writeInt8');
+const write_i16 = writer(2, 'writeInt16LE');
+const write_i32 = writer(4, 'writeInt32LE');
+const write_big_i64 = writer(8, 'writeBigInt64LE');
+const write_i64 = (val) => write_big_i64(BigInt(val));
+
+const write_f32 = writer(4, 'writeFloatLE');
+const write_f64 = writer(8, 'writeDoubleLE');
+
+const write_bool = (val) => write_u8(val ? 1 : 0);
+
+function write_str(val) {
+    const data = Buffer.from(val, 'utf8');
+    write_u32(data.length);
+    write(data);
+}
+
+function writeAll(data) {
+    let pos = 0;
+
+    while (pos < data.length) {
+        try {
+            pos += fs.writeSync(1, data, pos, data.length - pos);
+        } catch (e) {
+            if (e.code !== 'EAGAIN') throw e;
+        }
+    }
+}
+
+function flush() {
+    const data = Buffer.concat(outChunks);
+    outChunks = [];
+
+    if (FRAMED) {
+        if (data.length === 0) return;
+
+        const header = Buffer.alloc(4);
+        header.writeUInt32LE(data.length, 0);
+
+        const checksum = Buffer.alloc(4);
+        checksum.writeUInt32LE(crc32(data), 0);
+
+        writeAll(Buffer.concat([header, data, checksum]));
+    } else {
+        writeAll(data);
+    }
+}
+
+module.exports = {
+    enable_framing, begin_message, end_message, flush,
+
+    read_u8, read_u16, read_u32, read_u64,
+    read_i8, read_i16, read_i32, read_i64,
+    read_f32, read_f64, read_bool, read_str,
+
+    write_u8, write_u16, write_u32, write_u64,
+    write_i8, write_i16, write_i32, write_i64,
+    write_f32, write_f64, write_bool, write_str,
+};
+
+process.stderr.write('Interact library loaded\n');
@@ -1,16 +1,74 @@
 import sys
 import struct
+import zlib
 
 READ_BUF = sys.stdin.buffer
 WRITE_BUF = sys.stdout.buffer
 
-def read(n):
+# In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+FRAMED = False
+
+in_frame = b''
+in_frame_pos = 0
+
+out_frame = bytearray()
+
+def read_raw(n):
     res = READ_BUF.read(n)
     while len(res) < n:
-        res += READ_BUF.read(n - len(res))
+        chunk = READ_BUF.read(n - len(res))
+        if not chunk:
+            sys.stderr.write('Unexpected EOF\n')
+            sys.exit(1)
+        res += chunk
         
     return res
 
+def read(n):
+    global in_frame_pos
+
+    if not FRAMED:
+        return read_raw(n)
+
+    if in_frame_pos + n > len(in_frame):
+        sys.stderr.write('Protocol error: message is shorter than expected\n')
+        sys.exit(1)
+
+    res = in_frame[in_frame_pos:in_frame_pos + n]
+    in_frame_pos += n
+    return res
+
+def write(data):
+    if FRAMED:
+        out_frame.extend(data)
+    else:
+        WRITE_BUF.write(data)
+
+def enable_framing():
+    global FRAMED
+    FRAMED = True
+
+# Reads the next frame from the server. Does nothing when framing is disabled
+def begin_message():
+    global in_frame, in_frame_pos
+
+    if not FRAMED:
+        return
+
+    size = struct.unpack('<I', read_raw(4))[0]
+    in_frame = read_raw(size)
+    in_frame_pos = 0
+
+    checksum = struct.unpack('<I', read_raw(4))[0]
+    if checksum != zlib.crc32(in_frame):
+        sys.stderr.write('Protocol error: checksum mismatch\n')
+        sys.exit(1)
+
+def end_message():
+    if FRAMED and in_frame_pos != len(in_frame):
+        sys.stderr.write('Protocol error: message is longer than expected\n')
+        sys.exit(1)
+
 def read_u8():
     return struct.unpack('<B', read(1))[0]
 
@@ -49,44 +107,50 @@ def read_str():
     return read(length).decode('utf-8')
 
 def write_u8(val):
-    WRITE_BUF.write(struct.pack('<B', val))
+    write(struct.pack('<B', val))
     
 def write_u16(val):
-    WRITE_BUF.write(struct.pack('<H', val))
+    write(struct.pack('<H', val))
     
 def write_u32(val):
-    WRITE_BUF.write(struct.pack('<I', val))
+    write(struct.pack('<I', val))
     
 def write_u64(val):
-    WRITE_BUF.write(struct.pack('<Q', val))
+    write(struct.pack('<Q', val))
     
 def write_i8(val):
-    WRITE_BUF.write(struct.pack('<b', val))
+    write(struct.pack('<b', val))
     
 def write_i16(val):
-    WRITE_BUF.write(struct.pack('<h', val))
+    write(struct.pack('<h', val))
     
 def write_i32(val):
-    WRITE_BUF.write(struct.pack('<i', val))
+    write(struct.pack('<i', val))
     
 def write_i64(val):
-    WRITE_BUF.write(struct.pack('<q', val))
+    write(struct.pack('<q', val))
     
 def write_f32(val):
-    WRITE_BUF.write(struct.pack('<f', val))
+    write(struct.pack('<f', val))
     
 def write_f64(val):
-    WRITE_BUF.write(struct.pack('<d', val))
+    write(struct.pack('<d', val))
     
 def write_bool(val):
-    WRITE_BUF.write(struct.pack('<B', 1 if val else 0))
+    write(struct.pack('<B', 1 if val else 0))
     
 def write_str(val):
     data = val.encode('utf-8')
     write_u32(len(data))
-    WRITE_BUF.write(data)
+    write(data)
     
 def flush():
+    if FRAMED and out_frame:
+        WRITE_BUF.write(struct.pack('<I', len(out_frame)))
+        WRITE_BUF.write(out_frame)
+        WRITE_BUF.write(struct.pack('<I', zlib.crc32(out_frame)))
+        out_frame.clear()
+
     WRITE_BUF.flush()
     
 sys.stderr.write('Interact library loaded\n')
\ No newline at end of file
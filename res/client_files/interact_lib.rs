@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::io::{Read, Write};
+
+// In framed mode every message is a u32 length, the payload and the CRC32 of the payload
+struct Io {
+    framed: bool,
+    in_frame: Vec<u8>,
+    in_frame_pos: usize,
+    out: Vec<u8>
+}
+
+thread_local! {
+    static IO: RefCell<Io> = const { RefCell::new(Io { framed: false, in_frame: Vec::new(), in_frame_pos: 0, out: Vec::new() }) };
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn read_raw(buf: &mut [u8]) {
+    if std::io::stdin().lock().read_exact(buf).is_err() {
+        eprintln!("Unexpected EOF");
+        std::process::exit(1);
+    }
+}
+
+fn read_raw_u32() -> u32 {
+    let mut buf = [0u8; 4];
+    read_raw(&mut buf);
+    u32::from_le_bytes(buf)
+}
+
+fn write_raw(data: &[u8]) {
+    let mut stdout = std::io::stdout().lock();
+
+    if stdout.write_all(data).and_then(|_| stdout.flush()).is_err() {
+        eprintln!("Failed to write to stdout");
+        std::process::exit(1);
+    }
+}
+
+pub fn read_bytes(buf: &mut [u8]) {
+    IO.with_borrow_mut(|io| {
+        if !io.framed {
+            read_raw(buf);
+            return;
+        }
+
+        if io.in_frame_pos + buf.len() > io.in_frame.len() {
+            eprintln!("Protocol error: message is shorter than expected");
+            std::process::exit(1);
+        }
+
+        buf.copy_from_slice(&io.in_frame[io.in_frame_pos..io.in_frame_pos + buf.len()]);
+        io.in_frame_pos += buf.len();
+    })
+}
+
+pub fn write_bytes(data: &[u8]) {
+    IO.with_borrow_mut(|io| io.out.extend_from_slice(data))
+}
+
+pub fn enable_framing() {
+    IO.with_borrow_mut(|io| io.framed = true)
+}
+
+// Reads the next frame from the server. Does nothing when framing is disabled
+pub fn begin_message() {
+    IO.with_borrow_mut(|io| {
+        if !io.framed {
+            return;
+        }
+
+        let size = read_raw_u32() as usize;
+        io.in_frame = vec![0u8; size];
+        io.in_frame_pos = 0;
+        read_raw(&mut io.in_frame);
+
+        if read_raw_u32() != crc32(&io.in_frame) {
+            eprintln!("Protocol error: checksum mismatch");
+            std::process::exit(1);
+        }
+    })
+}
+
+pub fn end_message() {
+    IO.with_borrow(|io| {
+        if io.framed && io.in_frame_pos != io.in_frame.len() {
+            eprintln!("Protocol error: message is longer than expected");
+            std::process::exit(1);
+        }
+    })
+}
+
+pub fn flush() {
+    IO.with_borrow_mut(|io| {
+        let data = std::mem::take(&mut io.out);
+
+        if !io.framed {
+            write_raw(&data);
+        } else if !data.is_empty() {
+            let mut frame = Vec::with_capacity(data.len() + 8);
+            frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&data);
+            frame.extend_from_slice(&crc32(&data).to_le_bytes());
+
+            write_raw(&frame);
+        }
+    })
+}
+
+// Every type that can be sent to or from the server implements Wire
+pub trait Wire: Sized {
+    fn read() -> Self;
+    fn write(&self);
+}
+
+macro_rules! wire_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Wire for $ty {
+                fn read() -> Self {
+                    let mut buf = [0u8; std::mem::size_of::<$ty>()];
+                    read_bytes(&mut buf);
+                    <$ty>::from_le_bytes(buf)
+                }
+
+                fn write(&self) {
+                    write_bytes(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+wire_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Wire for bool {
+    fn read() -> Self {
+        u8::read() != 0
+    }
+
+    fn write(&self) {
+        (*self as u8).write();
+    }
+}
+
+impl Wire for String {
+    fn read() -> Self {
+        let mut buf = vec![0u8; u32::read() as usize];
+        read_bytes(&mut buf);
+        String::from_utf8_lossy(&buf).to_string()
+    }
+
+    fn write(&self) {
+        (self.len() as u32).write();
+        write_bytes(self.as_bytes());
+    }
+}
+
+impl<T: Wire> Wire for Vec<T> {
+    fn read() -> Self {
+        let len = u32::read();
+        (0..len).map(|_| T::read()).collect()
+    }
+
+    fn write(&self) {
+        (self.len() as u32).write();
+
+        for x in self {
+            x.write();
+        }
+    }
+}
+
+impl<T: Wire, const N: usize> Wire for [T; N] {
+    fn read() -> Self {
+        std::array::from_fn(|_| T::read())
+    }
+
+    fn write(&self) {
+        for x in self {
+            x.write();
+        }
+    }
+}
@@ -22,10 +22,18 @@ pub struct Model {
     pub owner_id: Option<i32>,
     pub partial: bool,
     pub colour: String,
+    pub num_moves: i32,
+    #[sea_orm(column_type = "Double")]
+    pub move_time: f64,
+    pub failed_games: i32,
+    pub source_size: i64,
+    pub variant: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::fault::Entity")]
+    Fault,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::OwnerId",
@@ -36,6 +44,12 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::fault::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Fault.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
@@ -3,4 +3,8 @@
 pub mod prelude;
 
 pub mod agent;
+pub mod api_token;
+pub mod fault;
+pub mod invite;
+pub mod session;
 pub mod user;
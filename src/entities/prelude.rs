@@ -1,4 +1,8 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.4
 
 pub use super::agent::Entity as Agent;
+pub use super::api_token::Entity as ApiToken;
+pub use super::fault::Entity as Fault;
+pub use super::invite::Entity as Invite;
+pub use super::session::Entity as Session;
 pub use super::user::Entity as User;
@@ -0,0 +1,167 @@
+use std::{fmt::Display, sync::{Arc, Mutex}};
+
+use serde::{Deserialize, Serialize};
+
+//How forgiving a game is towards agents that misbehave
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultPolicy {
+    //Faults an agent can make in one game, the last of these forfeits the game
+    pub strikes_per_game: u32,
+    //Play the game's default move when an agent answers too late, rather than skipping its turn
+    pub default_move_on_timeout: bool,
+    //Forfeited games before the agent is removed for good
+    pub max_failed_games: u32,
+    //How long to keep waiting for a late answer so the agent can stay in sync. Agents that
+    //don't answer within it are forfeited, since their next answer would be for the wrong call
+    pub late_reply_grace_s: f32
+}
+
+impl Default for FaultPolicy {
+    fn default() -> Self {
+        Self {
+            strikes_per_game: 3,
+            default_move_on_timeout: true,
+            max_failed_games: 3,
+            late_reply_grace_s: 1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    //Answered, but after its time ran out
+    Timeout,
+    NoResponse,
+    //The process died or broke the protocol
+    Crash,
+    InvalidMove
+}
+
+impl FaultKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FaultKind::Timeout => "timeout",
+            FaultKind::NoResponse => "no_response",
+            FaultKind::Crash => "crash",
+            FaultKind::InvalidMove => "invalid_move"
+        }
+    }
+
+    //The agent can't be talked to anymore after these
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, FaultKind::NoResponse | FaultKind::Crash)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub message: String,
+    pub forfeit: bool
+}
+
+impl Fault {
+    pub fn new(kind: FaultKind, message: String) -> Self {
+        Self {
+            kind,
+            message,
+            forfeit: false
+        }
+    }
+
+    pub fn crash(error: std::io::Error) -> Self {
+        Self::new(FaultKind::Crash, error.to_string())
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    DefaultMove,
+    SkipTurn,
+    Forfeit
+}
+
+//Applies a FaultPolicy and keeps a log of the faults. Clones share the log, so the RunningJob can
+//hand one to the game and still read the faults once the game is done
+#[derive(Debug, Clone)]
+pub struct FaultTracker {
+    policy: FaultPolicy,
+    faults: Arc<Mutex<Vec<Fault>>>
+}
+
+impl FaultTracker {
+    pub fn new(policy: FaultPolicy) -> Self {
+        Self {
+            policy,
+            faults: Arc::new(Mutex::new(vec![]))
+        }
+    }
+
+    pub fn with_policy(&self, policy: FaultPolicy) -> Self {
+        Self {
+            policy,
+            faults: self.faults.clone()
+        }
+    }
+
+    pub fn record(&mut self, fault: &Fault) -> FaultAction {
+        let mut faults = self.faults.lock().unwrap();
+
+        let mut fault = fault.clone();
+        fault.forfeit = fault.kind.is_fatal() || faults.len() as u32 + 1 >= self.policy.strikes_per_game;
+
+        let action = if fault.forfeit {
+            FaultAction::Forfeit
+        } else if fault.kind == FaultKind::Timeout && self.policy.default_move_on_timeout {
+            FaultAction::DefaultMove
+        } else {
+            FaultAction::SkipTurn
+        };
+
+        faults.push(fault);
+
+        action
+    }
+
+    pub fn faults(&self) -> Vec<Fault> {
+        self.faults.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fault, FaultAction, FaultKind, FaultPolicy, FaultTracker};
+
+    #[test]
+    fn test_strikes() {
+        let mut tracker = FaultTracker::new(FaultPolicy {
+            strikes_per_game: 3,
+            default_move_on_timeout: false,
+            ..FaultPolicy::default()
+        });
+        let shared = tracker.clone();
+
+        let timeout = Fault::new(FaultKind::Timeout, "".to_string());
+
+        assert_eq!(tracker.record(&timeout), FaultAction::SkipTurn);
+        assert_eq!(tracker.record(&timeout), FaultAction::SkipTurn);
+        assert_eq!(tracker.record(&timeout), FaultAction::Forfeit);
+
+        let faults = shared.faults();
+        assert_eq!(faults.len(), 3);
+        assert!(!faults[1].forfeit);
+        assert!(faults[2].forfeit);
+
+        let mut tracker = FaultTracker::new(FaultPolicy::default());
+        assert_eq!(tracker.record(&timeout), FaultAction::DefaultMove);
+        assert_eq!(tracker.record(&Fault::new(FaultKind::Crash, "".to_string())), FaultAction::Forfeit);
+    }
+}
@@ -2,10 +2,14 @@ use std::{future::Future, io::Error, time::{Duration, Instant}};
 
 use async_trait::async_trait;
 
-use crate::{isolate::sandbox::RunningJob, players::reporting::GameReporter};
+use crate::{isolate::{limits::ResourceLimits, sandbox::RunningJob}, players::reporting::GameReporter};
+
+use self::faults::FaultPolicy;
 
 pub mod oxo;
 pub mod nzoi_snake;
+pub mod time_bank;
+pub mod faults;
 
 pub async fn await_seconds<Fut, T>(fut: Fut, seconds: f32) -> Result<T, String>
 where
@@ -49,6 +53,14 @@ pub trait Game: Sync + Send {
     fn num_players(&self) -> usize;
     fn name(&self) -> &'static str;
 
+    fn limits(&self) -> ResourceLimits {
+        ResourceLimits::default()
+    }
+
+    fn fault_policy(&self) -> FaultPolicy {
+        FaultPolicy::default()
+    }
+
     async fn run(&self, players: &mut Vec<RunningJob>, min_delay: Option<Duration>, reporter: GameReporter) -> Vec<f32>;
 }
 
@@ -62,6 +74,14 @@ impl Game for Box<dyn Game> {
         (**self).name()
     }
 
+    fn limits(&self) -> ResourceLimits {
+        (**self).limits()
+    }
+
+    fn fault_policy(&self) -> FaultPolicy {
+        (**self).fault_policy()
+    }
+
     async fn run(&self, players: &mut Vec<RunningJob>, min_delay: Option<Duration>, reporter: GameReporter) -> Vec<f32> {
         (**self).run(players, min_delay, reporter).await
     }
@@ -5,7 +5,7 @@ use log::warn;
 use proc_gamedef::make_server;
 use rand::Rng;
 
-use crate::{isolate::sandbox::RunningJob, games::{await_seconds, Waiter}, players::reporting::GameReporter};
+use crate::{isolate::{limits::ResourceLimits, sandbox::RunningJob}, games::{Waiter, faults::{Fault, FaultAction, FaultPolicy}}, players::reporting::GameReporter};
 
 use super::Game;
 
@@ -14,7 +14,12 @@ pub struct NzoiSnake {
     size: (usize, usize),
     food: usize,
 
-    snakes: Vec<Vec<(usize, usize)>>
+    snakes: Vec<Vec<(usize, usize)>>,
+
+    #[serde(default)]
+    limits: ResourceLimits,
+    #[serde(default)]
+    faults: FaultPolicy
 }
 
 impl NzoiSnake {
@@ -29,6 +34,20 @@ impl NzoiSnake {
 
 make_server!("res/games/nzoi_snake.game");
 
+//The direction the snake went last, used when its agent answers too late
+fn last_move(snake: &VecDeque<Pos>) -> Option<Move> {
+    let head = snake.back()?;
+    let neck = snake.get(snake.len().checked_sub(2)?)?;
+
+    match (head.row - neck.row, head.col - neck.col) {
+        (-1, 0) => Some(Move::Up),
+        (1, 0) => Some(Move::Down),
+        (0, -1) => Some(Move::Left),
+        (0, 1) => Some(Move::Right),
+        _ => None
+    }
+}
+
 fn apply_move(p: Pos, m: Move) -> Pos {
     let Pos {row, col} = p;
 
@@ -62,8 +81,18 @@ impl Game for NzoiSnake {
         self.snakes.len()
     }
 
+    fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    fn fault_policy(&self) -> FaultPolicy {
+        self.faults
+    }
+
     async fn run(&self, players: &mut Vec<RunningJob>, min_delay: Option<Duration>, mut reporter: GameReporter) -> Vec<f32> {
         let mut waiter = Waiter::new(min_delay);
+        let mut time_banks: Vec<_> = players.iter().map(|x| x.time_bank().with_grace(self.faults.late_reply_grace_s)).collect();
+        let mut fault_trackers: Vec<_> = players.iter().map(|x| x.fault_tracker(self.faults)).collect();
         let mut agents: Vec<_> = players.into_iter().map(|x| Agent::new(x)).collect();
 
         let mut grid: Vec<_> = (0..self.rows()).map(|_| vec![0i32; self.cols()]).collect();
@@ -87,8 +116,10 @@ impl Game for NzoiSnake {
             match agents[i].init(&(i as i32 + 1), self.rows() as u32, self.cols() as u32, self.num_players() as u32).await {
                 Err(e) => {
                     warn!("Snake init error!");
+                    let fault = Fault::crash(e);
+                    fault_trackers[i].record(&fault);
                     reporter.update(&(i+1), "init_error").await;
-                    agents[i].set_error(e.to_string());
+                    agents[i].set_error(fault.to_string());
                     dead[i] = true;
                     num_dead += 1;
                 },
@@ -138,16 +169,19 @@ impl Game for NzoiSnake {
                 }
             }
 
-            let futures = agents.iter_mut().enumerate().filter_map(|(i, agent)| 
+            let futures = agents.iter_mut().zip(time_banks.iter_mut()).enumerate().filter_map(|(i, (agent, time_bank))| 
                 if dead[i] {
                     None
                 } else {
-                    Some(await_seconds(agent.get_move(&grid, &snakes[i].back().unwrap()), 1.0))
+                    Some(time_bank.timed(agent.get_move(&grid, &snakes[i].back().unwrap())))
                 }
             );
 
             let moves = futures::future::join_all(futures).await;
 
+            let time_stats: Vec<_> = time_banks.iter().map(|x| x.stats()).collect();
+            reporter.update(&time_stats, "time").await;
+
             waiter.wait().await;
 
             let alive_players: Vec<_> = (0..self.num_players()).filter(|x| !dead[*x]).collect();
@@ -155,28 +189,39 @@ impl Game for NzoiSnake {
             let mut to_kill = vec![];
 
             for (i, res) in alive_players.iter().zip(moves) {
-                match res {
-                    Err(e) => {
-                        warn!("Snake crashed! {:?}", e);
-                        reporter.update(&(i+1), "player_error").await;
-                        agents[*i].set_error(e.clone());
+                let m = match res {
+                    Ok(m) => Some(m),
+                    Err(fault) => {
+                        reporter.update(&(i+1, &fault), "fault").await;
+
+                        match fault_trackers[*i].record(&fault) {
+                            FaultAction::Forfeit => {
+                                warn!("Snake crashed! {}", fault);
+                                reporter.update(&(i+1), "player_error").await;
+                                agents[*i].set_error(fault.to_string());
+                                dead[*i] = true;
+                                num_dead += 1;
+
+                                to_kill.push(*i);
+                                None
+                            },
+                            FaultAction::DefaultMove => last_move(&snakes[*i]),
+                            FaultAction::SkipTurn => None
+                        }
+                    }
+                };
+
+                if let Some(m) = m {
+                    let curr_head = snakes[*i].back().unwrap();
+                    let new_pos = apply_move(*curr_head, m);
+
+                    if new_pos.row < 0 || new_pos.col < 0 || new_pos.row >= self.rows() as i32 || new_pos.col >= self.cols() as i32 {
+                        reporter.update(&(i+1), "wall_crash").await;
                         dead[*i] = true;
                         num_dead += 1;
-
                         to_kill.push(*i);
-                    },
-                    Ok(m) => {
-                        let curr_head = snakes[*i].back().unwrap();
-                        let new_pos = apply_move(*curr_head, m);
-
-                        if new_pos.row < 0 || new_pos.col < 0 || new_pos.row >= self.rows() as i32 || new_pos.col >= self.cols() as i32 {
-                            reporter.update(&(i+1), "wall_crash").await;
-                            dead[*i] = true;
-                            num_dead += 1;
-                            to_kill.push(*i);
-                        } else {
-                            new_positions.push((*i, new_pos));
-                        }
+                    } else {
+                        new_positions.push((*i, new_pos));
                     }
                 }
             }
@@ -249,34 +294,18 @@ impl Game for NzoiSnake {
             // reporter.update(&grid, "grid").await;
             // reporter.update(&scores, "scores").await;
 
-            let mut changes: HashMap<i32, Vec<(usize, usize)>> = HashMap::new();
+            let mut changes: HashMap<GridCell, Vec<Pos>> = HashMap::new();
 
             for i in 0..self.rows() {
                 for j in 0..self.cols() {
                     if prev_grid[i][j] != grid[i][j] {
-                        let new_val = grid[i][j];
-
-                        if !changes.contains_key(&new_val) {
-                            changes.insert(new_val, vec![]);
-                        }
-
-                        changes.get_mut(&new_val).unwrap().push((i, j));
+                        changes.entry(grid[i][j]).or_default().push(Pos { row: i as _, col: j as _ });
                     }
                 }
             }
 
-            let mut update_data = vec![];
-
-            for (val, positions) in changes {
-                let mut sub = vec![val];
-
-                for (i, j) in positions {
-                    sub.push(i as i32);
-                    sub.push(j as i32);
-                }
-
-                update_data.push(sub);
-            }
+            //Each new cell value along with where it now is
+            let update_data: Vec<_> = changes.into_iter().collect();
 
             reporter.update(&update_data, "upd").await;
 
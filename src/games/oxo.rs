@@ -3,7 +3,7 @@ use std::time::Duration;
 use async_trait::async_trait;
 use proc_gamedef::make_server;
 
-use crate::{isolate::sandbox::RunningJob, games::{await_seconds, Waiter}, players::reporting::GameReporter};
+use crate::{isolate::sandbox::RunningJob, games::{Waiter, faults::{Fault, FaultAction, FaultKind}}, players::reporting::{AgentLogger, GameReporter}};
 
 use super::Game;
 
@@ -12,36 +12,36 @@ pub struct TicTacToe;
 
 make_server!("res/games/tic_tac_toe.game");
 
-fn get_piece(cell: &BoardCell) -> Piece {
-    match cell {
-        BoardCell::Nought => Piece::Nought,
-        BoardCell::Cross => Piece::Cross,
-        BoardCell::Empty => panic!("Empty cell"),
-    }
-}
+//The cell in the line everyone has, if it isn't empty
+fn get_winner(grid: &Board) -> Option<BoardCell> {
+    let lines = (0..3).map(|i| [grid[i][0], grid[i][1], grid[i][2]])
+        .chain((0..3).map(|i| [grid[0][i], grid[1][i], grid[2][i]]))
+        .chain([[grid[0][0], grid[1][1], grid[2][2]], [grid[0][2], grid[1][1], grid[2][0]]]);
 
-fn get_winner(grid: &Board) -> Option<Piece> {
-    for i in 0..3 {
-        if grid[i][0] == grid[i][1] && grid[i][1] == grid[i][2] && grid[i][0] != BoardCell::Empty {
-            return Some(get_piece(&grid[i][0]));
+    for [a, b, c] in lines {
+        if a != BoardCell::Empty && a == b && b == c {
+            return Some(a);
         }
     }
 
-    for i in 0..3 {
-        if grid[0][i] == grid[1][i] && grid[1][i] == grid[2][i] && grid[0][i] != BoardCell::Empty {
-            return Some(get_piece(&grid[0][i]));
-        }
-    }
+    None
+}
 
-    if grid[0][0] == grid[1][1] && grid[1][1] == grid[2][2] && grid[0][0] != BoardCell::Empty {
-        return Some(get_piece(&grid[0][0]));
-    }
+//Sends what agents log to their owners' spectator streams
+struct OwnerLog(AgentLogger);
 
-    if grid[0][2] == grid[1][1] && grid[1][1] == grid[2][0] && grid[0][2] != BoardCell::Empty {
-        return Some(get_piece(&grid[0][2]));
+#[async_trait]
+impl AgentCallbacks for OwnerLog {
+    async fn log_debug(&mut self, msg: String) {
+        self.0.log(&msg).await;
     }
+}
 
-    None
+//Played for agents that answer too late
+fn first_empty(grid: &Board) -> Option<Pos> {
+    (0..3).flat_map(|row| (0..3).map(move |col| (row, col)))
+        .find(|&(row, col)| grid[row][col] == BoardCell::Empty)
+        .map(|(row, col)| Pos { row: row as _, col: col as _ })
 }
 
 #[async_trait]
@@ -56,75 +56,112 @@ impl Game for TicTacToe {
 
     async fn run(&self, players: &mut Vec<RunningJob>, min_delay: Option<Duration>, mut reporter: GameReporter) -> Vec<f32> {
         let mut waiter = Waiter::new(min_delay);
+        let policy = self.fault_policy();
+        let mut time_banks: Vec<_> = players.iter().map(|x| x.time_bank().with_grace(policy.late_reply_grace_s)).collect();
+        let mut fault_trackers: Vec<_> = players.iter().map(|x| x.fault_tracker(policy)).collect();
         let mut agents: Vec<_> = players.into_iter().map(|x| Agent::new(x)).collect();
 
+        for (i, agent) in agents.iter_mut().enumerate() {
+            agent.set_callbacks(Box::new(OwnerLog(reporter.agent_logger(i))));
+        }
+
+        //Before the first move, so that starting up isn't taken out of the time bank
+        for player in 0..2 {
+            if let Err(e) = agents[player].verify_interface().await {
+                let fault = Fault::crash(e);
+                fault_trackers[player].record(&fault);
+                reporter.update(&player, "player_error").await;
+                agents[player].set_error(fault.to_string());
+
+                for agent in agents {
+                    agent.kill().await;
+                }
+
+                if player == 0 {
+                    return vec![0.0, 1.0];
+                } else {
+                    return vec![1.0, 0.0];
+                }
+            }
+        }
+
         let mut grid = [[BoardCell::Empty; 3]; 3];
 
         let mut turn = 0;
+        let mut player = 0;
 
         while turn < 9 {
-            let player = turn % 2;
             let piece = if player == 0 {
                 Piece::Cross
             } else {
                 Piece::Nought
             };
 
-            let m = match await_seconds(agents[player].get_move(&grid, &piece), 1.0).await {
-                Ok(m) => m,
-                Err(e) => {
-                    reporter.update(&player, "player_error").await;
-                    agents[player].set_error(e);
-                    for agent in agents {
-                        agent.kill().await;
-                    }
-
-                    if player == 0 {
-                        return vec![0.0, 1.0];
-                    } else {
-                        return vec![1.0, 0.0];
-                    }
-                }
+            let res = match time_banks[player].timed(agents[player].get_move(&grid, &piece)).await {
+                Ok(m) if m.row > 2 || m.col > 2 || grid[m.row as usize][m.col as usize] != BoardCell::Empty => {
+                    Err(Fault::new(FaultKind::InvalidMove, format!("Invalid Move ({}, {})", m.row, m.col)))
+                },
+                res => res
             };
 
-            waiter.wait().await;
+            let time_stats: Vec<_> = time_banks.iter().map(|x| x.stats()).collect();
+            reporter.update(&time_stats, "time").await;
 
-            if m.row > 2 || m.col > 2 || grid[m.row as usize][m.col as usize] != BoardCell::Empty {
-                reporter.update(&player, "player_error").await;
+            waiter.wait().await;
 
-                agents[player].set_error(format!("Invalid Move ({}, {})", m.row, m.col));
-                for agent in agents {
-                    agent.kill().await;
+            let m = match res {
+                Ok(m) => Some(m),
+                Err(fault) => {
+                    reporter.update(&(player, &fault), "fault").await;
+
+                    match fault_trackers[player].record(&fault) {
+                        FaultAction::Forfeit => {
+                            reporter.update(&player, "player_error").await;
+                            agents[player].set_error(fault.to_string());
+                            for agent in agents {
+                                agent.kill().await;
+                            }
+
+                            if player == 0 {
+                                return vec![0.0, 1.0];
+                            } else {
+                                return vec![1.0, 0.0];
+                            }
+                        },
+                        FaultAction::DefaultMove => first_empty(&grid),
+                        FaultAction::SkipTurn => None
+                    }
                 }
+            };
 
-                if player == 0 {
-                    return vec![0.0, 1.0];
+            if let Some(m) = m {
+                grid[m.row as usize][m.col as usize] = if player == 0 {
+                    BoardCell::Cross
                 } else {
-                    return vec![1.0, 0.0];
-                }
-            }
+                    BoardCell::Nought
+                };
 
-            grid[m.row as usize][m.col as usize] = if player == 0 {
-                BoardCell::Cross
-            } else {
-                BoardCell::Nought
-            };
+                reporter.update(&(piece, m), "move").await;
+                reporter.update(&grid, "grid_state").await;
 
-            reporter.update(&grid, "grid_state").await;
+                if let Some(winner) = get_winner(&grid) {
+                    reporter.update(&winner, "winner").await;
 
-            if let Some(winner) = get_winner(&grid) {
-                for agent in agents {
-                    agent.kill().await;
-                }
+                    for agent in agents {
+                        agent.kill().await;
+                    }
 
-                if winner == Piece::Nought {
-                    return vec![0.0, 1.0];
-                } else {
-                    return vec![1.0, 0.0];
+                    if winner == BoardCell::Nought {
+                        return vec![0.0, 1.0];
+                    } else {
+                        return vec![1.0, 0.0];
+                    }
                 }
+
+                turn += 1;
             }
 
-            turn += 1;
+            player = 1 - player;
         }
 
         for agent in agents {
@@ -0,0 +1,117 @@
+use std::{future::Future, io::Error, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use async_std::future::timeout;
+use serde::Serialize;
+
+use super::faults::{Fault, FaultKind};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TimeStats {
+    pub moves: u32,
+    pub total_s: f32,
+    pub max_s: f32,
+    //What is left of the reserve
+    pub bank_s: f32
+}
+
+//Chess clock style timer: every call gets a fixed allowance and anything over it is taken from a reserve
+//shared by the whole game. Clones share the same stats, so the RunningJob can hand one to the game and
+//still read the totals once the game is done
+#[derive(Debug, Clone)]
+pub struct TimeBank {
+    allowance_s: f32,
+    grace_s: f32,
+    stats: Arc<Mutex<TimeStats>>
+}
+
+impl TimeBank {
+    pub fn new(allowance_s: f32, bank_s: f32) -> Self {
+        Self {
+            allowance_s,
+            grace_s: 0.0,
+            stats: Arc::new(Mutex::new(TimeStats {
+                bank_s,
+                ..TimeStats::default()
+            }))
+        }
+    }
+
+    //Keep waiting this long for calls that ran out of time, see FaultPolicy::late_reply_grace_s
+    pub fn with_grace(mut self, grace_s: f32) -> Self {
+        self.grace_s = grace_s;
+        self
+    }
+
+    pub fn stats(&self) -> TimeStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub async fn timed<Fut, T>(&mut self, fut: Fut) -> Result<T, Fault>
+    where
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let available = self.allowance_s + self.stats().bank_s;
+        let mut fut = Box::pin(fut);
+
+        let start = Instant::now();
+        let res = match timeout(Duration::from_secs_f32(available), &mut fut).await {
+            Ok(res) => res.map_err(Fault::crash),
+            Err(_) => match timeout(Duration::from_secs_f32(self.grace_s), &mut fut).await {
+                Ok(Ok(_)) => Err(Fault::new(
+                    FaultKind::Timeout,
+                    format!("Answered after {:.2}s with {:.2}s available", start.elapsed().as_secs_f32(), available)
+                )),
+                Ok(Err(e)) => Err(Fault::crash(e)),
+                Err(_) => Err(Fault::new(FaultKind::NoResponse, format!("No answer after {:.2}s", start.elapsed().as_secs_f32())))
+            }
+        };
+        let elapsed = start.elapsed().as_secs_f32();
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.moves += 1;
+        stats.total_s += elapsed;
+        stats.max_s = stats.max_s.max(elapsed);
+        stats.bank_s = (stats.bank_s - (elapsed - self.allowance_s).max(0.0)).max(0.0);
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::games::faults::FaultKind;
+
+    use super::TimeBank;
+
+    #[test]
+    fn test_bank_drains() {
+        let mut bank = TimeBank::new(0.05, 0.1);
+        let shared = bank.clone();
+
+        let slow = async {
+            async_std::task::sleep(Duration::from_millis(100)).await;
+            Ok::<_, std::io::Error>(())
+        };
+        assert!(pollster::block_on(bank.timed(slow)).is_ok());
+
+        let stats = shared.stats();
+        assert_eq!(stats.moves, 1);
+        assert!(stats.bank_s < 0.06);
+
+        let too_slow = async {
+            async_std::task::sleep(Duration::from_millis(200)).await;
+            Ok::<_, std::io::Error>(())
+        };
+        assert_eq!(pollster::block_on(bank.timed(too_slow)).unwrap_err().kind, FaultKind::NoResponse);
+        assert_eq!(shared.stats().bank_s, 0.0);
+
+        let mut bank = TimeBank::new(0.05, 0.0).with_grace(1.0);
+        let late = async {
+            async_std::task::sleep(Duration::from_millis(100)).await;
+            Ok::<_, std::io::Error>(())
+        };
+        assert_eq!(pollster::block_on(bank.timed(late)).unwrap_err().kind, FaultKind::Timeout);
+    }
+}
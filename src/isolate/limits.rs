@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+//Limits for a single agent, as declared in the game config. Every field is optional there
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    //Wall time the agent gets to answer a single function call
+    pub call_time_s: f32,
+    //Reserve drawn from when a call runs over call_time_s, shared by the whole game
+    pub time_bank_s: f32,
+    //CPU time over the lifetime of the agent's process
+    pub cpu_time_s: f32,
+    pub memory_kb: u32,
+
+    pub compile_time_s: f32,
+    pub compile_memory_kb: u32
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            call_time_s: 1.0,
+            time_bank_s: 2.0,
+            cpu_time_s: 60.0,
+            memory_kb: 51200,
+
+            compile_time_s: 30.0,
+            compile_memory_kb: 1024 * 1024
+        }
+    }
+}
+
+//How much slower or hungrier a language is than C++
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitMultipliers {
+    pub time: f32,
+    pub memory: f32,
+    //Address space the runtime reserves regardless of what the agent does
+    pub memory_overhead_kb: u32
+}
+
+impl Default for LimitMultipliers {
+    fn default() -> Self {
+        Self {
+            time: 1.0,
+            memory: 1.0,
+            memory_overhead_kb: 0
+        }
+    }
+}
+
+impl LimitMultipliers {
+    //What's left of a scaled memory limit for the program's own heap, once the runtime's reservation is taken off
+    pub fn heap_kb(&self, scaled_kb: u32) -> u32 {
+        scaled_kb.saturating_sub(self.memory_overhead_kb)
+    }
+}
+
+impl ResourceLimits {
+    pub fn scaled(&self, multipliers: &LimitMultipliers) -> ResourceLimits {
+        let scale_memory = |kb: u32| (kb as f32 * multipliers.memory) as u32 + multipliers.memory_overhead_kb;
+
+        ResourceLimits {
+            call_time_s: self.call_time_s * multipliers.time,
+            time_bank_s: self.time_bank_s * multipliers.time,
+            cpu_time_s: self.cpu_time_s * multipliers.time,
+            memory_kb: scale_memory(self.memory_kb),
+
+            compile_time_s: self.compile_time_s * multipliers.time,
+            compile_memory_kb: scale_memory(self.compile_memory_kb)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LimitMultipliers, ResourceLimits};
+
+    #[test]
+    fn test_partial_config() {
+        let limits: ResourceLimits = serde_json::from_str(r#"{"call_time_s": 0.5}"#).unwrap();
+
+        assert_eq!(limits.call_time_s, 0.5);
+        assert_eq!(limits.memory_kb, ResourceLimits::default().memory_kb);
+
+        let scaled = limits.scaled(&LimitMultipliers { time: 3.0, memory: 2.0, memory_overhead_kb: 100 });
+
+        assert_eq!(scaled.call_time_s, 1.5);
+        assert_eq!(scaled.cpu_time_s, 180.0);
+        assert_eq!(scaled.memory_kb, 51200 * 2 + 100);
+
+        let multipliers = LimitMultipliers { memory_overhead_kb: 100, ..Default::default() };
+        assert_eq!(multipliers.heap_kb(limits.scaled(&multipliers).memory_kb), 51200);
+    }
+}
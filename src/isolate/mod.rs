@@ -1 +1,3 @@
-pub mod sandbox;
\ No newline at end of file
+pub mod sandbox;
+pub mod protocol;
+pub mod limits;
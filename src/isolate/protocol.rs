@@ -0,0 +1,146 @@
+use async_std::io::Error;
+use std::io::ErrorKind;
+
+use super::sandbox::RunningJob;
+
+//Frames larger than this are assumed to be garbage rather than a real message
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+pub fn protocol_error(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("protocol error {}", message))
+}
+
+fn bytes_str(n: usize) -> &'static str {
+    if n == 1 {
+        "byte"
+    } else {
+        "bytes"
+    }
+}
+
+//A frame is a u32 length, the payload and then the CRC32 of the payload
+impl RunningJob {
+    pub async fn write_frame(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut frame = Vec::with_capacity(payload.len() + 8);
+
+        frame.extend(&(payload.len() as u32).to_le_bytes());
+        frame.extend(payload);
+        frame.extend(&crc32fast::hash(payload).to_le_bytes());
+
+        self.write(&frame).await
+    }
+
+    //Messages from the agent start with a u32 tag, which is returned separately from the rest of the payload
+    pub async fn read_frame(&mut self) -> Result<(u32, Vec<u8>), Error> {
+        let len = self.read_u32().await? as usize;
+
+        if !(4..=MAX_FRAME_SIZE).contains(&len) {
+            return Err(protocol_error(format!("in frame header: invalid frame length {}", len)));
+        }
+
+        let mut payload = vec![0u8; len];
+        self.read(&mut payload).await?;
+
+        let checksum = self.read_u32().await?;
+        let expected = crc32fast::hash(&payload);
+
+        if checksum != expected {
+            return Err(protocol_error(format!("in frame: checksum mismatch (expected {:08x}, got {:08x})", expected, checksum)));
+        }
+
+        let body = payload.split_off(4);
+        let tag = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+
+        Ok((tag, body))
+    }
+}
+
+macro_rules! frame_read_impl {
+    ($name:ident, $ty:ty, $size:expr) => {
+        pub async fn $name(&mut self) -> Result<$ty, Error> {
+            let mut buf = [0u8; $size];
+            self.read(&mut buf)?;
+            Ok(<$ty>::from_le_bytes(buf))
+        }
+    };
+}
+
+//Reads a message out of a single frame. Has the same interface as RunningJob so that generated deserializers work on either
+pub struct FrameReader {
+    context: String,
+    data: Vec<u8>,
+    pos: usize
+}
+
+impl FrameReader {
+    pub fn new(context: String, data: Vec<u8>) -> FrameReader {
+        FrameReader {
+            context,
+            data,
+            pos: 0
+        }
+    }
+
+    fn read(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        let needed = self.pos + out.len();
+
+        if needed > self.data.len() {
+            return Err(protocol_error(format!("in {}: expected {} {}, got {}", self.context, needed, bytes_str(needed), self.data.len())));
+        }
+
+        out.copy_from_slice(&self.data[self.pos..needed]);
+        self.pos = needed;
+
+        Ok(())
+    }
+
+    frame_read_impl!(read_u8, u8, 1);
+    frame_read_impl!(read_u16, u16, 2);
+    frame_read_impl!(read_u32, u32, 4);
+    frame_read_impl!(read_u64, u64, 8);
+
+    frame_read_impl!(read_i8, i8, 1);
+    frame_read_impl!(read_i16, i16, 2);
+    frame_read_impl!(read_i32, i32, 4);
+    frame_read_impl!(read_i64, i64, 8);
+
+    frame_read_impl!(read_f32, f32, 4);
+    frame_read_impl!(read_f64, f64, 8);
+
+    pub async fn read_str(&mut self) -> Result<String, Error> {
+        let len = self.read_u32().await? as usize;
+        let mut buf = vec![0u8; len];
+        self.read(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).to_string())
+    }
+
+    pub async fn read_bool(&mut self) -> Result<bool, Error> {
+        let val = self.read_u8().await?;
+        Ok(val != 0)
+    }
+
+    //Leftover bytes mean the agent and the server disagree on the message layout
+    pub fn finish(self) -> Result<(), Error> {
+        if self.pos != self.data.len() {
+            return Err(protocol_error(format!("in {}: expected {} {}, got {}", self.context, self.pos, bytes_str(self.pos), self.data.len())));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameReader;
+
+    #[test]
+    fn test_short_reply() {
+        let mut reply = FrameReader::new("reply to get_move".to_string(), vec![]);
+        let err = pollster::block_on(reply.read_u8()).unwrap_err();
+        assert_eq!(err.to_string(), "protocol error in reply to get_move: expected 1 byte, got 0");
+
+        let mut reply = FrameReader::new("reply to get_move".to_string(), vec![1, 2, 3]);
+        assert_eq!(pollster::block_on(reply.read_u8()).unwrap(), 1);
+        assert_eq!(reply.finish().unwrap_err().to_string(), "protocol error in reply to get_move: expected 1 byte, got 3");
+    }
+}
@@ -7,8 +7,11 @@ use std::process::ExitStatus;
 use std::pin::Pin;
 use async_std::process::{Child, Command, Output, Stdio, ChildStdout, ChildStdin};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use log::{debug, info, trace, warn};
-use crate::util::temp_file::TempFile;
+use crate::{games::{faults::{Fault, FaultPolicy, FaultTracker}, time_bank::{TimeBank, TimeStats}}, util::temp_file::TempFile};
+
+use super::limits::ResourceLimits;
 
 #[derive(Debug)]
 pub struct IsolateSandbox {
@@ -203,7 +206,11 @@ pub struct LaunchOptions {
 
     pub max_process: MaxProcessCount,
     pub mapped_dirs: Vec<DirMapping>,
-    pub env: Vec<EnvRule>
+    pub env: Vec<EnvRule>,
+
+    //Not enforced by isolate, passed on to the RunningJob for games to use
+    pub call_time_s: Option<f32>,
+    pub time_bank_s: Option<f32>
 }
 
 impl LaunchOptions {
@@ -216,7 +223,10 @@ impl LaunchOptions {
 
             max_process: MaxProcessCount::Fixed(1),
             mapped_dirs: vec![],
-            env: vec![]
+            env: vec![],
+
+            call_time_s: None,
+            time_bank_s: None
         }
     }
 
@@ -290,6 +300,19 @@ impl LaunchOptions {
     pub fn full_env(self) -> Self {
         self.env_rule(EnvRule::InheritAll)
     }
+
+    pub fn run_limits(mut self, limits: &ResourceLimits) -> Self {
+        self.call_time_s = Some(limits.call_time_s);
+        self.time_bank_s = Some(limits.time_bank_s);
+
+        self.memory_limit_kb(limits.memory_kb)
+            .time_limit_s(limits.cpu_time_s)
+    }
+
+    pub fn compile_limits(self, limits: &ResourceLimits) -> Self {
+        self.memory_limit_kb(limits.compile_memory_kb)
+            .time_limit_s(limits.compile_time_s)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -314,6 +337,10 @@ pub struct RunningJob {
     killed: bool,
     attempt_kill: bool,
 
+    interface_verified: bool,
+    time_bank: TimeBank,
+    faults: FaultTracker,
+
     error_message: Option<String>,
     on_exit: Option<Box<dyn FnOnce(&mut RunningJob) + Sync + Send>>,
 }
@@ -468,6 +495,7 @@ impl RunningJob {
     pub fn new(mut child: Child, stderr: TempFile, metafile: TempFile, on_exit: Option<Box<dyn FnOnce(&mut RunningJob) + Sync + Send>>) -> RunningJob {
         let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
         let stdout = Arc::new(Mutex::new(child.stdout.take().unwrap()));
+        let defaults = ResourceLimits::default();
 
         RunningJob {
             child,
@@ -478,7 +506,11 @@ impl RunningJob {
 
             killed: false,
             attempt_kill: false,
-            
+
+            interface_verified: false,
+            time_bank: TimeBank::new(defaults.call_time_s, defaults.time_bank_s),
+            faults: FaultTracker::new(FaultPolicy::default()),
+
             error_message: None,
 
             on_exit,
@@ -521,6 +553,23 @@ impl RunningJob {
         self.error_message.as_deref()
     }
 
+    //Shares its stats with the job, so games don't have to hand them back
+    pub fn time_bank(&self) -> TimeBank {
+        self.time_bank.clone()
+    }
+
+    pub fn time_stats(&self) -> TimeStats {
+        self.time_bank.stats()
+    }
+
+    pub fn fault_tracker(&self, policy: FaultPolicy) -> FaultTracker {
+        self.faults.with_policy(policy)
+    }
+
+    pub fn faults(&self) -> Vec<Fault> {
+        self.faults.faults()
+    }
+
     pub async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
         WriteFuture::new(self.stdin.clone(), data).await
     }
@@ -558,6 +607,33 @@ impl RunningJob {
         Ok(val != 0)
     }
 
+    //Agents write the hash of the interface they were built against as soon as they start
+    pub async fn verify_interface(&mut self, expected: u64) -> Result<(), Error> {
+        if self.interface_verified {
+            return Ok(());
+        }
+
+        //Agents built before the hash existed never send it, and would only fail once the call timed out
+        let hash = match async_std::future::timeout(INTERFACE_HASH_TIMEOUT, self.read_u64()).await {
+            Ok(hash) => hash?,
+            Err(_) => return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Interface mismatch: agent didn't send the hash of its interface. Rebuild the agent with the current client files"
+            ))
+        };
+
+        if hash != expected {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Interface mismatch: agent was built for interface {:016x} but the game uses {:016x}. Rebuild the agent with the current client files", hash, expected)
+            ));
+        }
+
+        self.interface_verified = true;
+
+        Ok(())
+    }
+
     pub async fn wait(&mut self) -> Result<ExitStatus, Error> {
         match self.child.status().await {
             Ok(x) => {
@@ -673,6 +749,9 @@ impl Drop for RunningJob {
 
 const ISOLATE_PATH: &str = "isolate";
 
+//Long enough for a slow runtime like the JVM to start in the sandbox
+const INTERFACE_HASH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub async fn make_public(dir: &str) {
     let mut command = Command::new("chmod");
     command.arg("-R");
@@ -801,7 +880,15 @@ impl IsolateSandbox {
 
         let child = command.spawn().unwrap();
 
-        RunningJob::new(child, stderr_file, metafile_file, None)
+        let mut job = RunningJob::new(child, stderr_file, metafile_file, None);
+
+        let defaults = ResourceLimits::default();
+        job.time_bank = TimeBank::new(
+            options.call_time_s.unwrap_or(defaults.call_time_s),
+            options.time_bank_s.unwrap_or(defaults.time_bank_s)
+        );
+
+        job
     }
 
     pub fn box_dir(&self) -> &str {
@@ -6,16 +6,30 @@ use gamedef::game_interface::{
 };
 
 use crate::{
-    isolate::sandbox::{
-        DirMapping, IsolateSandbox, LaunchOptions, MaxProcessCount,
+    isolate::{
+        limits::ResourceLimits,
+        sandbox::{DirMapping, IsolateSandbox, LaunchOptions, MaxProcessCount},
     },
     util::temp_file::random_dir,
 };
 
-use super::{files::ClientFiles, language::Language};
+use super::{files::ClientFiles, language::{Language, Variant}, submission::Submission};
 
 pub struct CppLang;
 
+//Compiler flags of each variant, the first is the default. The sanitizer catches undefined behaviour
+//but slows agents down, so each variant also comes without it
+const VARIANTS: [(Variant, &[&str]); 8] = [
+    (Variant { id: "c++17", name: "C++17 (-O2)" }, &["-std=c++17", "-O2", "-fsanitize=undefined"]),
+    (Variant { id: "c++17-o3", name: "C++17 (-O3)" }, &["-std=c++17", "-O3", "-fsanitize=undefined"]),
+    (Variant { id: "c++20", name: "C++20 (-O2)" }, &["-std=c++20", "-O2", "-fsanitize=undefined"]),
+    (Variant { id: "c++20-o3", name: "C++20 (-O3)" }, &["-std=c++20", "-O3", "-fsanitize=undefined"]),
+    (Variant { id: "c++17-nosan", name: "C++17 (-O2, no sanitizer)" }, &["-std=c++17", "-O2"]),
+    (Variant { id: "c++17-o3-nosan", name: "C++17 (-O3, no sanitizer)" }, &["-std=c++17", "-O3"]),
+    (Variant { id: "c++20-nosan", name: "C++20 (-O2, no sanitizer)" }, &["-std=c++20", "-O2"]),
+    (Variant { id: "c++20-o3-nosan", name: "C++20 (-O3, no sanitizer)" }, &["-std=c++20", "-O3"])
+];
+
 pub fn struct_fields(fields: &StructFields, pretty: bool, indent: &str) -> String {
     let mut res = String::new();
 
@@ -458,6 +472,13 @@ fn write_encoder(
     }
 }
 
+//Callback arguments are taken by value so that temporaries can be passed in directly
+fn callback_signature(name: &str, args: &[(String, Type)]) -> String {
+    let args: Vec<_> = args.iter().map(|(name, ty)| format!("{} {}", type_as_inline_cpp(ty), name)).collect();
+
+    format!("void {}({})", name, args.join(", "))
+}
+
 fn make_default_value(ty: &Type, out: &mut String, name: Option<&str>, itf: &GameInterface) {
     match ty {
         Type::Builtin(BuiltinType::Bool) => out.push_str("false"),
@@ -546,6 +567,8 @@ impl Language for CppLang {
         type_defs.push_str("#include <stdint.h>\n");
         type_defs.push_str("\n\n");
 
+        type_defs.push_str(&format!("const uint64_t INTERFACE_HASH = 0x{:016x}ULL;\n\n", game_interface.interface_hash()));
+
         for (name, ty) in &game_interface.types {
             match ty {
                 Type::Struct(fields) => {
@@ -569,6 +592,17 @@ impl Language for CppLang {
             }
         }
 
+        if !game_interface.callbacks.is_empty() {
+            type_defs.push_str("// Callbacks can be called at any point to send data back to the server\n");
+
+            for (callback_name, signature) in &game_interface.callbacks {
+                type_defs.push_str(&callback_signature(callback_name, &signature.args));
+                type_defs.push_str(";\n");
+            }
+
+            type_defs.push('\n');
+        }
+
         res.add_file("game_types.h", type_defs, false, "Defines types for game", "game_types.h");
 
         let mut function_decl = String::new();
@@ -634,13 +668,39 @@ impl Language for CppLang {
             interactor.push_str("}\n\n");
         }
 
+        //Callbacks are tagged with their index + 1 (0 marks a reply)
+        for (idx, (callback_name, signature)) in game_interface.callbacks.iter().enumerate() {
+            interactor.push_str(&callback_signature(callback_name, &signature.args));
+            interactor.push_str(" {\n");
+            interactor.push_str(&format!("    writeData<uint32_t>({});\n", idx + 1));
+
+            for (name, ty) in &signature.args {
+                let mut x = 0;
+                write_encoder(ty, 1, name.clone(), &mut interactor, &mut x);
+            }
+
+            interactor.push_str("    flushStreams();\n}\n\n");
+        }
+
         interactor.push_str(
             "
 int main(){
     std::cerr << \"Starting Interactor!\" << std::endl;
+    writeData<uint64_t>(INTERFACE_HASH);
+    flushStreams();
+",
+        );
+
+        if game_interface.framed {
+            interactor.push_str("    enableFraming();\n");
+        }
+
+        interactor.push_str(
+            "
     while(true) {
-        uint8_t func_id;
-        readData<uint8_t>(func_id);
+        beginMessage();
+        uint32_t func_id;
+        readData<uint32_t>(func_id);
 ",
         );
 
@@ -661,6 +721,8 @@ int main(){
                 write_decoder(ty, 3, format!("param_{name}"), &mut interactor, &mut x);
             }
 
+            interactor.push_str("            endMessage();\n");
+
             //interactor.push_str(&format!("std::cerr << \"  Calling!\" << std::endl;\n"));
 
             if signature.args.len() > 0 {
@@ -688,8 +750,12 @@ int main(){
 
             if let Some(ret) = &signature.ret {
                 let mut x = 0;
+                interactor.push_str("            writeData<uint32_t>(0);\n");
                 write_encoder(ret, 3, "ret".to_string(), &mut interactor, &mut x);
                 interactor.push_str("            flushStreams();\n");
+            } else {
+                interactor.push_str("            writeData<uint32_t>(0);\n");
+                interactor.push_str("            flushStreams();\n");
             }
 
             interactor.push_str("        } ")
@@ -752,37 +818,49 @@ int main(){
         res
     }
 
+    fn variants(&self) -> Vec<Variant> {
+        VARIANTS.iter().map(|(x, _)| *x).collect()
+    }
+
     async fn prepare(
         &self,
-        src: &str,
+        submission: &Submission,
         out: &mut super::language::PreparedProgram,
         game_interface: &gamedef::game_interface::GameInterface,
         sandboxes: Pool<IsolateSandbox>,
+        limits: &ResourceLimits,
+        variant: &str
     ) -> Result<(), String> {
         let mut sandbox = sandboxes.get().await.unwrap();
         sandbox.initialize().await;
 
         let temp_folder = random_dir("./tmp");
-        async_std::fs::write(format!("{}/{}", temp_folder, "agent.cpp"), src)
+        async_std::fs::write(format!("{}/{}", temp_folder, "agent.cpp"), &submission.entry)
             .await
             .unwrap();
+        submission.write_files(&temp_folder)?;
+        out.add_files(submission)?;
 
         //make_public(&out.dir_as_string()).await;
 
+        let variant = self.variant(variant);
+        let (_, flags) = VARIANTS.iter().find(|(x, _)| *x == variant).unwrap_or(&VARIANTS[0]);
+
+        let mut args = vec![
+            /*"-DVERBOSE_IO".to_string(),*/
+            "-I/client_files/".to_string(),
+            "-Wall".to_string(),
+            "-o".to_string(),
+            "/box/agent.o".to_string(),
+            "/client_files/interactor.cpp".to_string(),
+            "/src/agent.cpp".to_string()
+        ];
+        args.extend(flags.iter().map(|x| x.to_string()));
+        args.extend(submission.files_with_extension(&["cpp", "cc"]).map(|x| format!("/src/{}", x)));
+
         let mut compile_job: crate::isolate::sandbox::RunningJob = sandbox.launch(
             "/usr/bin/g++".to_string(),
-            vec![
-                /*"-DVERBOSE_IO".to_string(),*/
-                "-I/client_files/".to_string(),
-                "-O2".to_string(),
-                "-Wall".to_string(),
-                "-std=c++17".to_string(),
-                "-o".to_string(),
-                "/box/agent.o".to_string(),
-                "/client_files/interactor.cpp".to_string(),
-                "/src/agent.cpp".to_string(),
-                "-fsanitize=undefined".to_string()
-            ],
+            args,
             /*vec![
                 (self.get_dir(&game_interface), "/client_files".to_string()),
                 (out.dir_as_string(), "/out".to_string()),
@@ -792,8 +870,7 @@ int main(){
             vec![],
             None,*/
             &LaunchOptions::new()
-                .memory_limit_kb(512000)
-                .time_limit_s(30.0)
+                .compile_limits(limits)
                 .max_processes(MaxProcessCount::Unlimited)
                 .map_dir("/client_files", self.get_dir(game_interface))
                 .add_mapping(DirMapping::named("/out", out.dir_as_string()).read_write())
@@ -829,12 +906,14 @@ int main(){
         data_dir: &str,
         sandbox: &crate::isolate::sandbox::IsolateSandbox,
         _itf: &gamedef::game_interface::GameInterface,
+        limits: &ResourceLimits,
+        _variant: &str
     ) -> crate::isolate::sandbox::RunningJob {
         sandbox.launch(
             format!("{data_dir}/agent.o"), 
             vec![], 
             &LaunchOptions::new()
-                .memory_limit_kb(51200)
+                .run_limits(limits)
                 .map_full(data_dir)
         )
     }
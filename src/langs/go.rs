@@ -0,0 +1,472 @@
+use async_std::path::PathBuf;
+use async_trait::async_trait;
+use deadpool::unmanaged::Pool;
+use gamedef::game_interface::{GameInterface, Type, BuiltinType, get_enum_variant_type, is_basic_enum, StructFields};
+
+use crate::{
+    isolate::{
+        limits::{LimitMultipliers, ResourceLimits},
+        sandbox::{DirMapping, IsolateSandbox, LaunchOptions, MaxProcessCount, RunningJob},
+    },
+    util::temp_file::random_dir,
+};
+
+use super::{files::ClientFiles, language::{Language, PreparedProgram}, submission::Submission};
+
+pub struct GoLang;
+
+//Shared between compiles so that the standard library is only built once
+const GO_CACHE_DIR: &str = "./tmp/gocache";
+
+const SOURCE_FILES: [&str; 3] = ["interact_lib.go", "game_types.go", "interactor.go"];
+
+pub fn type_as_go(ty: &Type) -> String {
+    match ty {
+        Type::Builtin(builtin) => match builtin {
+            BuiltinType::U8 => "uint8",
+            BuiltinType::U16 => "uint16",
+            BuiltinType::U32 => "uint32",
+            BuiltinType::U64 => "uint64",
+
+            BuiltinType::I8 => "int8",
+            BuiltinType::I16 => "int16",
+            BuiltinType::I32 => "int32",
+            BuiltinType::I64 => "int64",
+
+            BuiltinType::F32 => "float32",
+            BuiltinType::F64 => "float64",
+
+            BuiltinType::Bool => "bool",
+            BuiltinType::Str => "string",
+        }.to_string(),
+
+        Type::NamedType(name) => name.clone(),
+        Type::Array(ty, size) => format!("[{}]{}", size, type_as_go(ty)),
+        Type::DynamicArray(ty) => format!("[]{}", type_as_go(ty)),
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+fn lib_suffix(builtin: &BuiltinType) -> String {
+    let name = builtin.name();
+
+    format!("{}{}", name[..1].to_uppercase(), &name[1..])
+}
+
+fn write_decoder(ty: &Type, target: &str, indent: usize, depth: usize, out: &mut String) {
+    let indent_str = "\t".repeat(indent);
+
+    match ty {
+        Type::Builtin(builtin) => {
+            out.push_str(&format!("{}{} = read{}()\n", indent_str, target, lib_suffix(builtin)));
+        },
+
+        Type::NamedType(name) => {
+            out.push_str(&format!("{}{} = read_{}()\n", indent_str, target, name));
+        },
+
+        Type::Array(elem, _) | Type::DynamicArray(elem) => {
+            if let Type::DynamicArray(_) = ty {
+                out.push_str(&format!("{}{} = make({}, readU32())\n", indent_str, target, type_as_go(ty)));
+            }
+
+            let i = format!("i{}", depth);
+
+            out.push_str(&format!("{}for {} := range {} {{\n", indent_str, i, target));
+            write_decoder(elem, &format!("{}[{}]", target, i), indent + 1, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent_str));
+        },
+
+        _ => panic!("{:?} must be deserialized through named type", ty)
+    }
+}
+
+fn write_encoder(ty: &Type, value: &str, indent: usize, depth: usize, out: &mut String) {
+    let indent_str = "\t".repeat(indent);
+
+    match ty {
+        Type::Builtin(builtin) => {
+            out.push_str(&format!("{}write{}({})\n", indent_str, lib_suffix(builtin), value));
+        },
+
+        Type::NamedType(name) => {
+            out.push_str(&format!("{}write_{}({})\n", indent_str, name, value));
+        },
+
+        Type::Array(elem, _) | Type::DynamicArray(elem) => {
+            if let Type::DynamicArray(_) = ty {
+                out.push_str(&format!("{}writeU32(uint32(len({})))\n", indent_str, value));
+            }
+
+            let x = format!("x{}", depth);
+
+            out.push_str(&format!("{}for _, {} := range {} {{\n", indent_str, x, value));
+            write_encoder(elem, &x, indent + 1, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent_str));
+        },
+
+        _ => panic!("{:?} must be serialized through named type", ty)
+    }
+}
+
+//Functions are exported Go style, which also keeps names like `init` and `main` from clashing with Go's own
+fn func_name(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| format!("{}{}", part[..1].to_uppercase(), &part[1..]))
+        .collect()
+}
+
+fn struct_fields(fields: &StructFields) -> String {
+    if fields.is_empty() {
+        return "struct{}".to_string();
+    }
+
+    let mut res = String::new();
+
+    res.push_str("struct {\n");
+
+    for field in fields.iter() {
+        res.push_str(&format!("\t{} {}\n", field.name, type_as_go(&field.ty)));
+    }
+
+    res.push('}');
+
+    res
+}
+
+fn make_args(args: &[(String, Type)]) -> String {
+    let args: Vec<_> = args.iter().map(|(name, ty)| format!("{} {}", name, type_as_go(ty))).collect();
+
+    args.join(", ")
+}
+
+fn make_default_value(ty: &Type, itf: &GameInterface) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => "\"\"".to_string(),
+        Type::Builtin(BuiltinType::Bool) => "false".to_string(),
+        Type::Builtin(_) => "0".to_string(),
+
+        Type::Array(_, _) | Type::DynamicArray(_) => format!("{}{{}}", type_as_go(ty)),
+
+        Type::NamedType(name) => {
+            let (_, t) = itf.types.iter().find(|(x, _)| x == name).unwrap();
+
+            match t {
+                Type::Enum(variants) if is_basic_enum(variants) => format!("{}{}", name, variants[0].name),
+                Type::Enum(variants) => format!("{}{}{{}}", name, variants[0].name),
+                _ => format!("{}{{}}", name)
+            }
+        },
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+#[async_trait]
+impl Language for GoLang {
+    fn name(&self) -> &'static str {
+        "Go"
+    }
+
+    fn id(&self) -> &'static str {
+        "go"
+    }
+
+    fn extension(&self) -> &'static str {
+        "go"
+    }
+
+    fn generate(
+        &self,
+        game_interface: &GameInterface,
+    ) -> ClientFiles {
+        let game_interface = game_interface.reduced();
+
+        let mut res = ClientFiles::new();
+
+        res.include_client_file("interact_lib.go", ".", "Dependency of interactor (You do not need this file)", "interact_lib.go");
+
+        let mut type_defs = String::new();
+
+        type_defs.push_str("package main\n\n");
+        type_defs.push_str(&format!("const INTERFACE_HASH uint64 = 0x{:016x}\n\n", game_interface.interface_hash()));
+
+        for (name, ty) in &game_interface.types {
+            match ty {
+                Type::Struct(fields) => {
+                    type_defs.push_str(&format!("type {} {}\n\n", name, struct_fields(fields)));
+                },
+
+                Type::Enum(variants) => {
+                    if is_basic_enum(variants) {
+                        type_defs.push_str(&format!("type {} {}\n\nconst (\n", name, type_as_go(&Type::Builtin(get_enum_variant_type(variants)))));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            type_defs.push_str(&format!("\t{}{} {} = {}\n", name, variant.name, name, i));
+                        }
+
+                        type_defs.push_str(")\n\n");
+                    } else {
+                        //Full enums are an interface implemented by one struct per variant
+                        type_defs.push_str(&format!("type {} interface {{\n\tis{}()\n}}\n\n", name, name));
+
+                        for variant in variants.iter() {
+                            type_defs.push_str(&format!("type {}{} {}\n\n", name, variant.name, struct_fields(&variant.types)));
+                            type_defs.push_str(&format!("func ({}{}) is{}() {{}}\n\n", name, variant.name, name));
+                        }
+                    }
+                },
+
+                ty => {
+                    type_defs.push_str(&format!("type {} = {}\n\n", name, type_as_go(ty)));
+                }
+            }
+        }
+
+        //Callbacks are tagged with their index + 1 (0 marks a reply)
+        for (idx, (name, signature)) in game_interface.callbacks.iter().enumerate() {
+            type_defs.push_str(&format!("func {}({}) {{\n", func_name(name), make_args(&signature.args)));
+            type_defs.push_str(&format!("\twriteU32({})\n", idx + 1));
+
+            for (arg, ty) in &signature.args {
+                write_encoder(ty, arg, 1, 0, &mut type_defs);
+            }
+
+            type_defs.push_str("\tflush()\n}\n\n");
+        }
+
+        res.add_file("game_types.go", type_defs, false, "Defines types for game", "game_types.go");
+
+        let mut interactor = String::new();
+
+        interactor.push_str("package main\n\n");
+
+        for (name, ty) in &game_interface.types {
+            match ty {
+                Type::Struct(fields) => {
+                    interactor.push_str(&format!("func read_{0}() {0} {{\n\tvar value {0}\n", name));
+                    for field in fields.iter() {
+                        write_decoder(&field.ty, &format!("value.{}", field.name), 1, 0, &mut interactor);
+                    }
+                    interactor.push_str("\treturn value\n}\n\n");
+
+                    interactor.push_str(&format!("func write_{0}(value {0}) {{\n", name));
+                    for field in fields.iter() {
+                        write_encoder(&field.ty, &format!("value.{}", field.name), 1, 0, &mut interactor);
+                    }
+                    interactor.push_str("}\n\n");
+                },
+
+                Type::Enum(variants) => {
+                    let tag_type = get_enum_variant_type(variants);
+                    let tag_suffix = lib_suffix(&tag_type);
+                    let tag_go = type_as_go(&Type::Builtin(tag_type));
+
+                    if is_basic_enum(variants) {
+                        interactor.push_str(&format!("func read_{0}() {0} {{\n\tvalue := {0}(read{1}())\n", name, tag_suffix));
+                        interactor.push_str(&format!("\tif value >= {} {{\n\t\tfail(\"Invalid variant for {}\")\n\t}}\n", variants.len(), name));
+                        interactor.push_str("\treturn value\n}\n\n");
+
+                        interactor.push_str(&format!("func write_{0}(value {0}) {{\n\twrite{1}({2}(value))\n}}\n\n", name, tag_suffix, tag_go));
+                    } else {
+                        interactor.push_str(&format!("func read_{0}() {0} {{\n\tswitch read{1}() {{\n", name, tag_suffix));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            interactor.push_str(&format!("\tcase {}:\n\t\tvar value {}{}\n", i, name, variant.name));
+
+                            for field in variant.types.iter() {
+                                write_decoder(&field.ty, &format!("value.{}", field.name), 2, 0, &mut interactor);
+                            }
+
+                            interactor.push_str("\t\treturn value\n");
+                        }
+
+                        interactor.push_str(&format!("\t}}\n\n\tfail(\"Invalid variant for {}\")\n\treturn nil\n}}\n\n", name));
+
+                        //At least one variant has fields, so `v` is always used
+                        interactor.push_str(&format!("func write_{0}(value {0}) {{\n\tswitch v := value.(type) {{\n", name));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            interactor.push_str(&format!("\tcase {}{}:\n\t\twrite{}({})\n", name, variant.name, tag_suffix, i));
+
+                            for field in variant.types.iter() {
+                                write_encoder(&field.ty, &format!("v.{}", field.name), 2, 0, &mut interactor);
+                            }
+                        }
+
+                        interactor.push_str(&format!("\tdefault:\n\t\tfail(\"Invalid variant for {}\")\n\t}}\n}}\n\n", name));
+                    }
+                },
+
+                _ => {}
+            }
+        }
+
+        interactor.push_str("func main() {\n\twriteU64(INTERFACE_HASH)\n\tflush()\n");
+
+        if game_interface.framed {
+            interactor.push_str("\tenableFraming()\n");
+        }
+
+        interactor.push_str("\n\tfor {\n\t\tbeginMessage()\n\n\t\tswitch readU32() {\n");
+
+        for (idx, (name, signature)) in game_interface.functions.iter().enumerate() {
+            interactor.push_str(&format!("\t\tcase {}: // {}\n", idx, name));
+
+            for (arg, ty) in &signature.args {
+                interactor.push_str(&format!("\t\t\tvar param_{} {}\n", arg, type_as_go(ty)));
+                write_decoder(ty, &format!("param_{}", arg), 3, 0, &mut interactor);
+            }
+
+            interactor.push_str("\t\t\tendMessage()\n\n");
+
+            let args: Vec<_> = signature.args.iter().map(|(arg, _)| format!("param_{}", arg)).collect();
+            let call = format!("{}({})", func_name(name), args.join(", "));
+
+            if let Some(ret) = &signature.ret {
+                interactor.push_str(&format!("\t\t\tret := {}\n", call));
+                interactor.push_str("\t\t\twriteU32(0)\n");
+                write_encoder(ret, "ret", 3, 0, &mut interactor);
+                interactor.push_str("\t\t\tflush()\n");
+            } else {
+                interactor.push_str(&format!("\t\t\t{}\n", call));
+                interactor.push_str("\t\t\twriteU32(0)\n");
+                interactor.push_str("\t\t\tflush()\n");
+            }
+        }
+
+        interactor.push_str("\t\tdefault:\n\t\t\tfail(\"Unknown function\")\n\t\t}\n\t}\n}\n");
+
+        res.add_file("interactor.go", interactor, false, "The interactor (You do not need this file)", "interactor.go");
+
+        let mut template = String::new();
+
+        template.push_str("package main\n");
+
+        for (name, signature) in &game_interface.functions {
+            let ret = match &signature.ret {
+                Some(ret) => format!(" {}", type_as_go(ret)),
+                None => String::new()
+            };
+
+            template.push_str(&format!("\nfunc {}({}){} {{\n\t//Implement logic here...\n", func_name(name), make_args(&signature.args), ret));
+
+            if let Some(ret) = &signature.ret {
+                template.push_str(&format!("\treturn {}\n", make_default_value(ret, &game_interface)));
+            }
+
+            template.push_str("}\n");
+        }
+
+        res.add_file("agent.go", template, false, "Basic template for agent", "agent.go");
+
+        res
+    }
+
+    async fn prepare(
+        &self,
+        submission: &Submission,
+        out: &mut PreparedProgram,
+        game_interface: &GameInterface,
+        sandboxes: Pool<IsolateSandbox>,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> Result<(), String> {
+        let mut sandbox = sandboxes.get().await.unwrap();
+        sandbox.initialize().await;
+
+        //go build needs every file of the package in the same directory
+        let temp_folder = random_dir("./tmp");
+        async_std::fs::write(format!("{}/{}", temp_folder, "agent.go"), &submission.entry)
+            .await
+            .unwrap();
+        submission.write_files(&temp_folder)?;
+        out.add_files(submission)?;
+
+        for file in SOURCE_FILES {
+            async_std::fs::copy(format!("{}/{}", self.get_dir(game_interface), file), format!("{}/{}", temp_folder, file))
+                .await
+                .map_err(|x| format!("Error {x} when copying {file}"))?;
+        }
+
+        std::fs::create_dir_all(GO_CACHE_DIR).unwrap();
+
+        let mut args = vec![
+            "build".to_string(),
+            "-o".to_string(),
+            "/box/agent.o".to_string()
+        ];
+        args.extend(SOURCE_FILES.iter().map(|file| format!("/src/{}", file)));
+        args.push("/src/agent.go".to_string());
+        args.extend(submission.files_with_extension(&["go"]).map(|x| format!("/src/{}", x)));
+
+        let mut compile_job: RunningJob = sandbox.launch(
+            "/usr/bin/go".to_string(),
+            args,
+            &LaunchOptions::new()
+                .compile_limits(limits)
+                .max_processes(MaxProcessCount::Unlimited)
+                .add_mapping(DirMapping::named("/gocache", GO_CACHE_DIR).read_write())
+                .map_dir("/src", temp_folder.clone())
+                .map_dir("/usr/bin", "/usr/bin")
+                .full_env()
+                .set_env("GOCACHE", "/gocache")
+                .set_env("GOPATH", "/box/go")
+                .set_env("HOME", "/box")
+        );
+
+        let status = compile_job.wait().await.unwrap();
+
+        if !status.success() {
+            return Err(compile_job.stderr.read_as_string().await);
+        } else {
+            println!("Compile result\n\n{}", compile_job.stderr.read_as_string().await);
+        }
+
+        let mut output_file = PathBuf::from(sandbox.box_dir());
+        output_file.push("box");
+        output_file.push("agent.o");
+
+        let mut target_location = out.dir.clone();
+        target_location.push("agent.o");
+
+        std::fs::copy(&output_file, &target_location).map_err(|x| format!("Error {x} when copying '{:?}' to '{:?}'", output_file, target_location))?;
+
+        sandbox.cleanup().await;
+
+        Ok(())
+    }
+
+    //The Go runtime reserves a large heap arena up front
+    fn limit_multipliers(&self) -> LimitMultipliers {
+        LimitMultipliers {
+            memory_overhead_kb: 1024 * 1024,
+            ..LimitMultipliers::default()
+        }
+    }
+
+    //The Go runtime starts a few threads even with GOMAXPROCS=1
+    fn launch(
+        &self,
+        data_dir: &str,
+        sandbox: &IsolateSandbox,
+        _itf: &GameInterface,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> RunningJob {
+        sandbox.launch(
+            format!("{data_dir}/agent.o"),
+            vec![],
+            &LaunchOptions::new()
+                .run_limits(limits)
+                .max_processes(MaxProcessCount::Fixed(16))
+                .map_full(data_dir)
+                .set_env("GOMAXPROCS", "1")
+        )
+    }
+}
+
+unsafe impl Send for GoLang {}
+unsafe impl Sync for GoLang {}
@@ -0,0 +1,516 @@
+use async_trait::async_trait;
+use deadpool::unmanaged::Pool;
+use gamedef::game_interface::{GameInterface, Type, BuiltinType, get_enum_variant_type, is_basic_enum, StructFields};
+
+use crate::{
+    isolate::{
+        limits::{LimitMultipliers, ResourceLimits},
+        sandbox::{DirMapping, IsolateSandbox, LaunchOptions, MaxProcessCount, RunningJob},
+    },
+    util::temp_file::random_dir,
+};
+
+use super::{files::ClientFiles, language::{Language, PreparedProgram}, submission::Submission};
+
+pub struct JavaLang;
+
+//Caps the regions the JVM reserves up front so that it starts under the address space limit.
+//Warnings would otherwise be logged to stdout, which is the channel to the server
+const JVM_ARGS: [&str; 8] = [
+    "-Xss16m",
+    "-XX:+UseSerialGC",
+    "-XX:ActiveProcessorCount=1",
+    "-XX:ReservedCodeCacheSize=64m",
+    "-XX:MaxMetaspaceSize=128m",
+    "-XX:CompressedClassSpaceSize=64m",
+    "-Xlog:disable",
+    "-Xlog:all=warning:stderr",
+];
+
+//The heap gets what the memory limit leaves after the JVM's overhead, so Java agents are held to the
+//same limit as everything else
+fn jvm_args(heap_kb: u32) -> Vec<String> {
+    let mut args = vec![format!("-Xmx{}m", (heap_kb / 1024).max(16))];
+    args.extend(JVM_ARGS.iter().map(|x| x.to_string()));
+
+    args
+}
+
+//Java has no unsigned types, so unsigned values use the next larger signed type
+pub fn type_as_java(ty: &Type) -> String {
+    match ty {
+        Type::Builtin(builtin) => match builtin {
+            BuiltinType::U8 => "short",
+            BuiltinType::U16 => "int",
+            BuiltinType::U32 => "long",
+            BuiltinType::U64 => "long",
+
+            BuiltinType::I8 => "byte",
+            BuiltinType::I16 => "short",
+            BuiltinType::I32 => "int",
+            BuiltinType::I64 => "long",
+
+            BuiltinType::F32 => "float",
+            BuiltinType::F64 => "double",
+
+            BuiltinType::Bool => "boolean",
+            BuiltinType::Str => "String",
+        }.to_string(),
+
+        Type::NamedType(name) => name.clone(),
+        Type::Array(ty, _) | Type::DynamicArray(ty) => format!("{}[]", type_as_java(ty)),
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+fn lib_suffix(builtin: &BuiltinType) -> String {
+    let name = builtin.name();
+
+    format!("{}{}", name[..1].to_uppercase(), &name[1..])
+}
+
+//`new int[n][]` rather than `new int[][n]`, so the size goes before any nested dimensions
+fn new_array(elem: &Type, size: &str) -> String {
+    let elem = type_as_java(elem);
+
+    match elem.find('[') {
+        Some(idx) => format!("new {}[{}]{}", &elem[..idx], size, &elem[idx..]),
+        None => format!("new {}[{}]", elem, size)
+    }
+}
+
+fn write_decoder(ty: &Type, target: &str, indent: usize, depth: usize, out: &mut String) {
+    let indent_str = "    ".repeat(indent);
+
+    match ty {
+        Type::Builtin(builtin) => {
+            out.push_str(&format!("{}{} = InteractLib.read{}();\n", indent_str, target, lib_suffix(builtin)));
+        },
+
+        Type::NamedType(name) => {
+            out.push_str(&format!("{}{} = GameIO.read_{}();\n", indent_str, target, name));
+        },
+
+        Type::Array(elem, _) | Type::DynamicArray(elem) => {
+            let size = match ty {
+                Type::Array(_, size) => size.to_string(),
+                _ => "(int) InteractLib.readU32()".to_string()
+            };
+
+            let i = format!("i{}", depth);
+
+            out.push_str(&format!("{}{} = {};\n", indent_str, target, new_array(elem, &size)));
+            out.push_str(&format!("{indent_str}for (int {i} = 0; {i} < {target}.length; {i}++) {{\n"));
+            write_decoder(elem, &format!("{}[{}]", target, i), indent + 1, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent_str));
+        },
+
+        _ => panic!("{:?} must be deserialized through named type", ty)
+    }
+}
+
+fn write_encoder(ty: &Type, value: &str, indent: usize, depth: usize, out: &mut String) {
+    let indent_str = "    ".repeat(indent);
+
+    match ty {
+        Type::Builtin(builtin) => {
+            out.push_str(&format!("{}InteractLib.write{}({});\n", indent_str, lib_suffix(builtin), value));
+        },
+
+        Type::NamedType(name) => {
+            out.push_str(&format!("{}GameIO.write_{}({});\n", indent_str, name, value));
+        },
+
+        Type::Array(elem, _) | Type::DynamicArray(elem) => {
+            if let Type::DynamicArray(_) = ty {
+                out.push_str(&format!("{}InteractLib.writeU32({}.length);\n", indent_str, value));
+            }
+
+            let x = format!("x{}", depth);
+
+            out.push_str(&format!("{}for ({} {} : {}) {{\n", indent_str, type_as_java(elem), x, value));
+            write_encoder(elem, &x, indent + 1, depth + 1, out);
+            out.push_str(&format!("{}}}\n", indent_str));
+        },
+
+        _ => panic!("{:?} must be serialized through named type", ty)
+    }
+}
+
+fn make_args(args: &[(String, Type)]) -> String {
+    let args: Vec<_> = args.iter().map(|(name, ty)| format!("{} {}", type_as_java(ty), name)).collect();
+
+    args.join(", ")
+}
+
+fn make_default_value(ty: &Type, itf: &GameInterface) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => "\"\"".to_string(),
+        Type::Builtin(BuiltinType::Bool) => "false".to_string(),
+        Type::Builtin(BuiltinType::F32) => "0.0f".to_string(),
+        Type::Builtin(BuiltinType::F64) => "0.0".to_string(),
+        Type::Builtin(BuiltinType::U8) | Type::Builtin(BuiltinType::I16) => "(short) 0".to_string(),
+        Type::Builtin(BuiltinType::I8) => "(byte) 0".to_string(),
+        Type::Builtin(_) => "0".to_string(),
+
+        Type::Array(elem, size) => new_array(elem, &size.to_string()),
+        Type::DynamicArray(elem) => new_array(elem, "0"),
+
+        Type::NamedType(name) => {
+            let (_, t) = itf.types.iter().find(|(x, _)| x == name).unwrap();
+
+            match t {
+                Type::Enum(variants) if is_basic_enum(variants) => format!("{}.{}", name, variants[0].name),
+                Type::Enum(variants) => {
+                    let fields: Vec<_> = variants[0].types.iter().map(|field| make_default_value(&field.ty, itf)).collect();
+
+                    format!("new {}.{}({})", name, variants[0].name, fields.join(", "))
+                },
+                _ => format!("new {}()", name)
+            }
+        },
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+fn make_struct(name: &str, fields: &StructFields) -> String {
+    let mut res = String::new();
+
+    res.push_str(&format!("class {} {{\n", name));
+
+    for field in fields.iter() {
+        res.push_str(&format!("    public {} {};\n", type_as_java(&field.ty), field.name));
+    }
+
+    res.push_str(&format!("\n    public {}() {{}}\n", name));
+
+    if !fields.is_empty() {
+        let args: Vec<_> = fields.iter().map(|field| (field.name.clone(), field.ty.clone())).collect();
+
+        res.push_str(&format!("\n    public {}({}) {{\n", name, make_args(&args)));
+
+        for field in fields.iter() {
+            res.push_str(&format!("        this.{0} = {0};\n", field.name));
+        }
+
+        res.push_str("    }\n");
+    }
+
+    res.push_str("}\n\n");
+
+    res
+}
+
+#[async_trait]
+impl Language for JavaLang {
+    fn name(&self) -> &'static str {
+        "Java"
+    }
+
+    fn id(&self) -> &'static str {
+        "java"
+    }
+
+    fn extension(&self) -> &'static str {
+        "java"
+    }
+
+    fn generate(
+        &self,
+        game_interface: &GameInterface,
+    ) -> ClientFiles {
+        let game_interface = game_interface.reduced();
+
+        let mut res = ClientFiles::new();
+
+        res.include_client_file("InteractLib.java", ".", "Dependency of interactor (You do not need this file)", "InteractLib.java");
+
+        //Everything is in the default package so that the agent can use the types without imports
+        let mut type_defs = String::new();
+
+        type_defs.push_str("final class Game {\n");
+        type_defs.push_str(&format!("    static final long INTERFACE_HASH = 0x{:016x}L;\n\n", game_interface.interface_hash()));
+        type_defs.push_str("    private Game() {}\n");
+
+        //Callbacks are tagged with their index + 1 (0 marks a reply)
+        for (idx, (name, signature)) in game_interface.callbacks.iter().enumerate() {
+            type_defs.push_str(&format!("\n    static void {}({}) {{\n", name, make_args(&signature.args)));
+            type_defs.push_str(&format!("        InteractLib.writeU32({});\n", idx + 1));
+
+            for (arg, ty) in &signature.args {
+                write_encoder(ty, arg, 2, 0, &mut type_defs);
+            }
+
+            type_defs.push_str("        InteractLib.flush();\n    }\n");
+        }
+
+        type_defs.push_str("}\n\n");
+
+        let mut game_io = String::new();
+
+        game_io.push_str("final class GameIO {\n    private GameIO() {}\n");
+
+        for (name, ty) in &game_interface.types {
+            match ty {
+                Type::Struct(fields) => {
+                    type_defs.push_str(&make_struct(name, fields));
+
+                    game_io.push_str(&format!("\n    static {0} read_{0}() {{\n        {0} value = new {0}();\n", name));
+                    for field in fields.iter() {
+                        write_decoder(&field.ty, &format!("value.{}", field.name), 2, 0, &mut game_io);
+                    }
+                    game_io.push_str("        return value;\n    }\n");
+
+                    game_io.push_str(&format!("\n    static void write_{0}({0} value) {{\n", name));
+                    for field in fields.iter() {
+                        write_encoder(&field.ty, &format!("value.{}", field.name), 2, 0, &mut game_io);
+                    }
+                    game_io.push_str("    }\n");
+                },
+
+                Type::Enum(variants) => {
+                    let tag_type = get_enum_variant_type(variants);
+                    let tag_suffix = lib_suffix(&tag_type);
+
+                    if is_basic_enum(variants) {
+                        let names: Vec<_> = variants.iter().map(|variant| variant.name.as_str()).collect();
+                        type_defs.push_str(&format!("enum {} {{\n    {}\n}}\n\n", name, names.join(", ")));
+
+                        game_io.push_str(&format!("\n    static {0} read_{0}() {{\n", name));
+                        game_io.push_str(&format!("        int tag = (int) InteractLib.read{}();\n", tag_suffix));
+                        game_io.push_str(&format!("        if (tag >= {}.values().length) throw new IllegalStateException(\"Invalid variant \" + tag + \" for {}\");\n", name, name));
+                        game_io.push_str(&format!("        return {}.values()[tag];\n    }}\n", name));
+
+                        game_io.push_str(&format!("\n    static void write_{0}({0} value) {{\n", name));
+                        write_encoder(&Type::Builtin(tag_type), &format!("({}) value.ordinal()", type_as_java(&Type::Builtin(tag_type))), 2, 0, &mut game_io);
+                        game_io.push_str("    }\n");
+                    } else {
+                        //Full enums become a sealed interface with a record per variant
+                        type_defs.push_str(&format!("sealed interface {} {{\n", name));
+
+                        for variant in variants.iter() {
+                            let args: Vec<_> = variant.types.iter().map(|field| (field.name.clone(), field.ty.clone())).collect();
+                            type_defs.push_str(&format!("    record {}({}) implements {} {{}}\n", variant.name, make_args(&args), name));
+                        }
+
+                        type_defs.push_str("}\n\n");
+
+                        game_io.push_str(&format!("\n    static {0} read_{0}() {{\n", name));
+                        game_io.push_str(&format!("        int tag = (int) InteractLib.read{}();\n\n", tag_suffix));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            game_io.push_str(&format!("        if (tag == {}) {{\n", i));
+
+                            for field in variant.types.iter() {
+                                game_io.push_str(&format!("            {} f_{};\n", type_as_java(&field.ty), field.name));
+                                write_decoder(&field.ty, &format!("f_{}", field.name), 3, 0, &mut game_io);
+                            }
+
+                            let args: Vec<_> = variant.types.iter().map(|field| format!("f_{}", field.name)).collect();
+                            game_io.push_str(&format!("            return new {}.{}({});\n        }}\n", name, variant.name, args.join(", ")));
+                        }
+
+                        game_io.push_str(&format!("\n        throw new IllegalStateException(\"Invalid variant \" + tag + \" for {}\");\n    }}\n", name));
+
+                        game_io.push_str(&format!("\n    static void write_{0}({0} value) {{\n", name));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            game_io.push_str(&format!("        if (value instanceof {}.{} v) {{\n", name, variant.name));
+                            write_encoder(&Type::Builtin(tag_type), &format!("({}) {}", type_as_java(&Type::Builtin(tag_type)), i), 3, 0, &mut game_io);
+
+                            for field in variant.types.iter() {
+                                write_encoder(&field.ty, &format!("v.{}()", field.name), 3, 0, &mut game_io);
+                            }
+
+                            game_io.push_str("        }\n");
+                        }
+
+                        game_io.push_str("    }\n");
+                    }
+                },
+
+                //Aliases are inlined by `reduced`, so they don't need a class of their own
+                _ => {}
+            }
+        }
+
+        game_io.push_str("}\n");
+
+        res.add_file("GameTypes.java", type_defs, false, "Defines types for game", "GameTypes.java");
+        res.add_file("GameIO.java", game_io, false, "Reads and writes game types (You do not need this file)", "GameIO.java");
+
+        let mut interactor = String::new();
+
+        interactor.push_str("public class Interactor {\n    public static void main(String[] args) {\n");
+        interactor.push_str("        Agent agent = new Agent();\n\n");
+        interactor.push_str("        InteractLib.writeU64(Game.INTERFACE_HASH);\n        InteractLib.flush();\n");
+
+        if game_interface.framed {
+            interactor.push_str("        InteractLib.enableFraming();\n");
+        }
+
+        interactor.push_str("\n        while (true) {\n            InteractLib.beginMessage();\n            long funcId = InteractLib.readU32();\n");
+
+        for (idx, (name, signature)) in game_interface.functions.iter().enumerate() {
+            interactor.push_str(&format!("\n            if (funcId == {}) {{\n", idx));
+
+            for (arg, ty) in &signature.args {
+                interactor.push_str(&format!("                {} param_{};\n", type_as_java(ty), arg));
+                write_decoder(ty, &format!("param_{}", arg), 4, 0, &mut interactor);
+            }
+
+            interactor.push_str("                InteractLib.endMessage();\n\n");
+
+            let args: Vec<_> = signature.args.iter().map(|(arg, _)| format!("param_{}", arg)).collect();
+            let call = format!("agent.{}({})", name, args.join(", "));
+
+            if let Some(ret) = &signature.ret {
+                interactor.push_str(&format!("                {} ret = {};\n", type_as_java(ret), call));
+                interactor.push_str("                InteractLib.writeU32(0);\n");
+                write_encoder(ret, "ret", 4, 0, &mut interactor);
+                interactor.push_str("                InteractLib.flush();\n");
+            } else {
+                interactor.push_str(&format!("                {};\n", call));
+                interactor.push_str("                InteractLib.writeU32(0);\n");
+                interactor.push_str("                InteractLib.flush();\n");
+            }
+
+            interactor.push_str("                continue;\n            }\n");
+        }
+
+        interactor.push_str("\n            System.err.println(\"Unknown function \" + funcId);\n            System.exit(1);\n");
+        interactor.push_str("        }\n    }\n}\n");
+
+        res.add_file("Interactor.java", interactor, false, "The interactor (You do not need this file)", "Interactor.java");
+
+        let mut template = String::new();
+
+        template.push_str("public class Agent {\n");
+
+        for (idx, (name, signature)) in game_interface.functions.iter().enumerate() {
+            if idx != 0 {
+                template.push('\n');
+            }
+
+            let ret = match &signature.ret {
+                Some(ret) => type_as_java(ret),
+                None => "void".to_string()
+            };
+
+            template.push_str(&format!("    public {} {}({}) {{\n        //Implement logic here...\n", ret, name, make_args(&signature.args)));
+
+            if let Some(ret) = &signature.ret {
+                template.push_str(&format!("        return {};\n", make_default_value(ret, &game_interface)));
+            }
+
+            template.push_str("    }\n");
+        }
+
+        template.push_str("}\n");
+
+        res.add_file("Agent.java", template, false, "Basic template for agent", "Agent.java");
+
+        res
+    }
+
+    async fn prepare(
+        &self,
+        submission: &Submission,
+        out: &mut PreparedProgram,
+        game_interface: &GameInterface,
+        sandboxes: Pool<IsolateSandbox>,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> Result<(), String> {
+        let mut sandbox = sandboxes.get().await.unwrap();
+        sandbox.initialize().await;
+
+        //javac requires a public class to live in a file with the same name
+        let temp_folder = random_dir("./tmp");
+        async_std::fs::write(format!("{}/{}", temp_folder, "Agent.java"), &submission.entry)
+            .await
+            .unwrap();
+        submission.write_files(&temp_folder)?;
+        out.add_files(submission)?;
+
+        //javac runs under the compile limits
+        let heap_kb = self.limit_multipliers().heap_kb(limits.compile_memory_kb);
+        let mut args: Vec<String> = jvm_args(heap_kb).iter().map(|x| format!("-J{}", x)).collect();
+        args.extend([
+            "-encoding", "UTF-8",
+            "-d", "/out",
+            "/client_files/InteractLib.java",
+            "/client_files/GameTypes.java",
+            "/client_files/GameIO.java",
+            "/client_files/Interactor.java",
+            "/src/Agent.java"
+        ].iter().map(|x| x.to_string()));
+        args.extend(submission.files_with_extension(&["java"]).map(|x| format!("/src/{}", x)));
+
+        let mut compile_job: RunningJob = sandbox.launch(
+            "/usr/bin/javac".to_string(),
+            args,
+            &LaunchOptions::new()
+                .compile_limits(limits)
+                .max_processes(MaxProcessCount::Unlimited)
+                .map_dir("/client_files", self.get_dir(game_interface))
+                .add_mapping(DirMapping::named("/out", out.dir_as_string()).read_write())
+                .map_dir("/src", temp_folder.clone())
+                .map_dir("/usr/bin", "/usr/bin")
+                .map_dir("/etc", "/etc")
+                .full_env()
+        );
+
+        let status = compile_job.wait().await.unwrap();
+
+        sandbox.cleanup().await;
+
+        if !status.success() {
+            return Err(compile_job.stderr.read_as_string().await);
+        } else {
+            println!("Compile result\n\n{}", compile_job.stderr.read_as_string().await);
+        }
+
+        Ok(())
+    }
+
+    //The JVM reserves far more address space than it uses
+    fn limit_multipliers(&self) -> LimitMultipliers {
+        LimitMultipliers {
+            time: 2.0,
+            memory: 1.0,
+            memory_overhead_kb: 2 * 1024 * 1024
+        }
+    }
+
+    //The JVM starts a number of service threads.
+    //Its binaries and config are symlinked through /etc on most distributions
+    fn launch(
+        &self,
+        data_dir: &str,
+        sandbox: &IsolateSandbox,
+        _itf: &GameInterface,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> RunningJob {
+        let mut args = jvm_args(self.limit_multipliers().heap_kb(limits.memory_kb));
+        args.push("-cp".to_string());
+        args.push("/game".to_string());
+        args.push("Interactor".to_string());
+
+        sandbox.launch(
+            "/usr/bin/java".to_string(),
+            args,
+            &LaunchOptions::new()
+                .run_limits(limits)
+                .max_processes(MaxProcessCount::Fixed(64))
+                .map_dir("/game", data_dir)
+                .map_dir("/etc", "/etc")
+        )
+    }
+}
+
+unsafe impl Send for JavaLang {}
+unsafe impl Sync for JavaLang {}
@@ -1,39 +1,85 @@
-use gamedef::game_interface::{GameInterface, Type, BuiltinType, get_enum_variant_type, StructField, EnumVariant};
+use async_trait::async_trait;
+use deadpool::unmanaged::Pool;
+use gamedef::game_interface::{GameInterface, Type, BuiltinType, get_enum_variant_type, is_basic_enum, StructField, EnumVariant};
 
+use crate::isolate::{limits::{LimitMultipliers, ResourceLimits}, sandbox::{RunningJob, IsolateSandbox, LaunchOptions, MaxProcessCount}};
+
+use super::{language::{Language, PreparedProgram}, files::ClientFiles, submission::Submission};
+
+pub struct JavaScript;
+
+const LIB_IMPORTS: &str = "const {
+    enable_framing, begin_message, end_message, flush,
+    read_u8, read_u16, read_u32, read_u64, read_i8, read_i16, read_i32, read_i64, read_f32, read_f64, read_bool, read_str,
+    write_u8, write_u16, write_u32, write_u64, write_i8, write_i16, write_i32, write_i64, write_f32, write_f64, write_bool, write_str,
+} = require('./interact_lib');\n";
+
+pub fn type_as_jsdoc(ty: &Type) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::U64) | Type::Builtin(BuiltinType::I64) => "bigint".to_string(),
+        Type::Builtin(BuiltinType::Bool) => "boolean".to_string(),
+        Type::Builtin(BuiltinType::Str) => "string".to_string(),
+        Type::Builtin(_) => "number".to_string(),
+        Type::NamedType(name) => name.clone(),
+        Type::Array(ty, _) | Type::DynamicArray(ty) => format!("{}[]", type_as_jsdoc(ty)),
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+//Reads happen synchronously, so these expressions are evaluated strictly in order
 fn make_deserializer(ty: &Type) -> String {
     match ty {
-        Type::Builtin(BuiltinType::Bool) => "((await data.read_u8()) != 0)".to_string(),
-        Type::Builtin(BuiltinType::U8) => "(await data.read_u8())".to_string(),
-        Type::Builtin(BuiltinType::U16) => "(await data.read_u16()".to_string(),
-        Type::Builtin(BuiltinType::U32) => "(await data.read_u32())".to_string(),
-        Type::Builtin(BuiltinType::U64) => "(await data.read_u64())".to_string(),
-        Type::Builtin(BuiltinType::I8) => "(await data.read_i8())".to_string(),
-        Type::Builtin(BuiltinType::I16) => "(await data.read_i16())".to_string(),
-        Type::Builtin(BuiltinType::I32) => "(await data.read_i32())".to_string(),
-        Type::Builtin(BuiltinType::I64) => "(await data.read_i64())".to_string(),
-        Type::Builtin(BuiltinType::F32) => "(await data.read_f32())".to_string(),
-        Type::Builtin(BuiltinType::F64) => "(await data.read_f64())".to_string(),
-        Type::Builtin(BuiltinType::Str) => "(await data.read_str())".to_string(),
-
-        Type::Array(ty, size) => {
+        Type::Builtin(builtin) => format!("read_{}()", builtin.name()),
+
+        Type::Array(ty, size) => format!("Array.from({{length: {}}}, () => {})", size, make_deserializer(ty)),
+
+        Type::DynamicArray(ty) => format!("Array.from({{length: read_u32()}}, () => {})", make_deserializer(ty)),
+
+        Type::NamedType(name) => format!("read_{}()", name),
+
+        _ => panic!("{:?} must be deserialized through named type", ty)
+    }
+}
+
+fn make_serializer(ty: &Type, value: &str, indent: usize, depth: usize) -> String {
+    let indent_str = "    ".repeat(indent);
+
+    match ty {
+        Type::Builtin(builtin) => format!("{}write_{}({});\n", indent_str, builtin.name(), value),
+
+        Type::NamedType(name) => format!("{}write_{}({});\n", indent_str, name, value),
+
+        Type::Array(ty, _) => {
+            let x = format!("x{}", depth);
+
             format!(
-                "await Promise.all(Array({}).fill(0).map(async () => {}))",
-                size,
-                make_deserializer(ty)
+                "{indent_str}for (const {x} of {value}) {{\n{}{indent_str}}}\n",
+                make_serializer(ty, &x, indent + 1, depth + 1)
             )
         },
 
         Type::DynamicArray(ty) => {
+            let x = format!("x{}", depth);
+
             format!(
-                "await Promise.all(Array(await data.read_u32()).fill(0).map(async () => {}))",
-                make_deserializer(ty)
+                "{indent_str}write_u32({value}.length);\n{indent_str}for (const {x} of {value}) {{\n{}{indent_str}}}\n",
+                make_serializer(ty, &x, indent + 1, depth + 1)
             )
         },
 
-        Type::NamedType(name) => format!("(await deserialize_{}(data))", name),
+        _ => panic!("{:?} must be serialized through named type", ty)
+    }
+}
 
-        _ => panic!("{:?} must be deserialized through named type", ty)
+fn make_fields_object(fields: &[StructField]) -> String {
+    if fields.is_empty() {
+        return "{}".to_string();
     }
+
+    let fields: Vec<_> = fields.iter().map(|StructField {name, ty}| format!("{}: {}", name, make_deserializer(ty))).collect();
+
+    format!("{{ {} }}", fields.join(", "))
 }
 
 pub fn make_js_deserializers(itf: &GameInterface) -> String {
@@ -41,58 +87,381 @@ pub fn make_js_deserializers(itf: &GameInterface) -> String {
     let mut deserializers = String::new();
 
     for (name, ty) in &itf.types {
-        deserializers.push_str(&format!(
-            "async function deserialize_{}(data) {{\n",
-            name
-        ));
+        deserializers.push_str(&format!("function read_{}() {{\n", name));
 
         match ty {
             Type::Builtin(_) | Type::Array(_, _) | Type::DynamicArray(_) | Type::NamedType(_) => {
-                deserializers.push_str(&format!("  return {};\n", make_deserializer(ty)));
+                deserializers.push_str(&format!("    return {};\n", make_deserializer(ty)));
             },
 
             Type::Struct(fields) => {
-                deserializers.push_str("  return {\n");
+                deserializers.push_str(&format!("    return {};\n", make_fields_object(fields)));
+            },
 
-                for StructField {name, ty} in fields.iter() {
-                    deserializers.push_str(&format!(
-                        "    {}: {},\n",
-                        name,
-                        make_deserializer(ty)
-                    ));
+            Type::Enum(variants) => {
+                let variant_reader = make_deserializer(&Type::Builtin(get_enum_variant_type(variants)));
+
+                if is_basic_enum(variants) {
+                    deserializers.push_str(&format!("    return {};\n", variant_reader));
+                } else {
+                    deserializers.push_str(&format!("    switch ({}) {{\n", variant_reader));
+
+                    for (i, EnumVariant{name, types}) in variants.iter().enumerate() {
+                        deserializers.push_str(&format!(
+                            "        case {}: return {{ variant: \"{}\", data: {} }};\n",
+                            i, name, make_fields_object(types)
+                        ));
+                    }
+
+                    deserializers.push_str("        default: throw new Error(\"Invalid enum variant\");\n");
+                    deserializers.push_str("    }\n");
                 }
+            }
+        }
+
+        deserializers.push_str("}\n\n");
+    }
+
+    deserializers
+}
 
-                deserializers.push_str("  };\n");
+pub fn make_js_serializers(itf: &GameInterface) -> String {
+    let itf = itf.reduced();
+    let mut serializers = String::new();
+
+    for (name, ty) in &itf.types {
+        serializers.push_str(&format!("function write_{}(value) {{\n", name));
+
+        match ty {
+            Type::Builtin(_) | Type::Array(_, _) | Type::DynamicArray(_) | Type::NamedType(_) => {
+                serializers.push_str(&make_serializer(ty, "value", 1, 0));
+            },
+
+            Type::Struct(fields) => {
+                for StructField {name, ty} in fields.iter() {
+                    serializers.push_str(&make_serializer(ty, &format!("value.{}", name), 1, 0));
+                }
             },
-            
+
             Type::Enum(variants) => {
-                deserializers.push_str(&format!("  switch ({}) {{\n", make_deserializer(&Type::Builtin(get_enum_variant_type(variants)))));
+                let variant_type = Type::Builtin(get_enum_variant_type(variants));
 
-                for (i, EnumVariant{name, types}) in variants.iter().enumerate() {
-                    deserializers.push_str(&format!(
-                        "    case {}:\n      return {{\n        variant: \"{}\",\n        data: {{\n",
-                        i, name
-                    ));
+                if is_basic_enum(variants) {
+                    serializers.push_str(&make_serializer(&variant_type, "value", 1, 0));
+                } else {
+                    serializers.push_str("    switch (value.variant) {\n");
 
-                    for StructField {name, ty} in types.iter() {
-                        deserializers.push_str(&format!(
-                            "          {}: {},\n",
-                            name,
-                            make_deserializer(ty)
-                        ));
+                    for (i, EnumVariant{name, types}) in variants.iter().enumerate() {
+                        serializers.push_str(&format!("        case \"{}\":\n", name));
+                        serializers.push_str(&make_serializer(&variant_type, &i.to_string(), 3, 0));
+
+                        for StructField {name, ty} in types.iter() {
+                            serializers.push_str(&make_serializer(ty, &format!("value.data.{}", name), 3, 0));
+                        }
+
+                        serializers.push_str("            break;\n");
                     }
 
-                    deserializers.push_str("        }\n      };\n");
+                    serializers.push_str("        default: throw new Error(\"Invalid enum variant \" + value.variant);\n");
+                    serializers.push_str("    }\n");
                 }
+            }
+        }
 
-                deserializers.push_str("    default: throw new Error(\"Invalid enum variant\");\n");
+        serializers.push_str("}\n\n");
+    }
 
-                deserializers.push_str("  }\n");
+    serializers
+}
+
+fn make_default_value(ty: &Type, itf: &GameInterface) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::U64) | Type::Builtin(BuiltinType::I64) => "0n".to_string(),
+        Type::Builtin(BuiltinType::Bool) => "false".to_string(),
+        Type::Builtin(BuiltinType::Str) => "\"\"".to_string(),
+        Type::Builtin(_) => "0".to_string(),
+
+        Type::Array(ty, size) => format!("Array.from({{length: {}}}, () => {})", size, make_default_value(ty, itf)),
+        Type::DynamicArray(_) => "[]".to_string(),
+
+        Type::NamedType(name) => {
+            let (_, t) = itf.types.iter().find(|(x, _)| x == name).unwrap();
+
+            match t {
+                Type::Enum(variants) if is_basic_enum(variants) => format!("{}.{}", name, variants[0].name),
+                t => make_default_value(t, itf)
+            }
+        },
+
+        Type::Struct(fields) => {
+            let fields: Vec<_> = fields.iter().map(|field| format!("{}: {}", field.name, make_default_value(&field.ty, itf))).collect();
+
+            format!("{{ {} }}", fields.join(", "))
+        },
+
+        Type::Enum(variants) => {
+            let fields: Vec<_> = variants[0].types.iter().map(|field| format!("{}: {}", field.name, make_default_value(&field.ty, itf))).collect();
+
+            if fields.is_empty() {
+                format!("{{ variant: \"{}\", data: {{}} }}", variants[0].name)
+            } else {
+                format!("{{ variant: \"{}\", data: {{ {} }} }}", variants[0].name, fields.join(", "))
             }
         }
+    }
+}
 
-        deserializers.push_str("}\n\n");
+fn make_jsdoc_fields(fields: &[StructField], indent: &str) -> String {
+    let fields: Vec<_> = fields.iter().map(|field| format!("{}: {}", field.name, type_as_jsdoc(&field.ty))).collect();
+
+    format!("{}{{{}}}", indent, fields.join(", "))
+}
+
+#[async_trait]
+impl Language for JavaScript {
+    fn name(&self) -> &'static str {
+        "JavaScript (Node.js)"
     }
 
-    deserializers   
-}
\ No newline at end of file
+    fn id(&self) -> &'static str {
+        "javascript"
+    }
+
+    fn extension(&self) -> &'static str {
+        "js"
+    }
+
+    fn generate(
+        &self,
+        game_interface: &GameInterface,
+    ) -> ClientFiles {
+        let game_interface = game_interface.reduced();
+
+        let mut res = ClientFiles::new();
+
+        res.include_client_file("interact_lib.js", "run", "Dependency of Interactor (You do not need this file)", "interact_lib.js");
+
+        let mut type_defs = String::new();
+
+        type_defs.push_str(&format!("const INTERFACE_HASH = 0x{:016x}n;\n\n", game_interface.interface_hash()));
+
+        let mut exports = vec!["INTERFACE_HASH".to_string()];
+
+        for (name, ty) in &game_interface.types {
+            match ty {
+                Type::Struct(fields) => {
+                    type_defs.push_str(&format!("/**\n * @typedef {{Object}} {}\n", name));
+
+                    for field in fields.iter() {
+                        type_defs.push_str(&format!(" * @property {{{}}} {}\n", type_as_jsdoc(&field.ty), field.name));
+                    }
+
+                    type_defs.push_str(" */\n\n");
+                },
+                Type::Enum(variants) => {
+                    if is_basic_enum(variants) {
+                        type_defs.push_str(&format!("/** @enum {{number}} */\nconst {} = Object.freeze({{\n", name));
+
+                        for (i, variant) in variants.iter().enumerate() {
+                            type_defs.push_str(&format!("    {}: {},\n", variant.name, i));
+                        }
+
+                        type_defs.push_str("});\n\n");
+
+                        exports.push(name.clone());
+                    } else {
+                        let variants: Vec<_> = variants.iter().map(|variant| format!(
+                            "{{variant: \"{}\", data: {}}}",
+                            variant.name,
+                            make_jsdoc_fields(&variant.types, "")
+                        )).collect();
+
+                        type_defs.push_str(&format!("/**\n * @typedef {{{}}} {}\n */\n\n", variants.join(" | "), name));
+                    }
+                },
+                ty => {
+                    type_defs.push_str(&format!("/** @typedef {{{}}} {} */\n\n", type_as_jsdoc(ty), name));
+                }
+            }
+        }
+
+        type_defs.push_str(&format!("module.exports = {{ {} }};\n", exports.join(", ")));
+
+        res.add_file("run/game_types.js", type_defs, false, "Defines types for game", "game_types.js");
+
+        let mut game_io = String::new();
+
+        game_io.push_str(LIB_IMPORTS);
+        game_io.push('\n');
+
+        game_io.push_str(&make_js_deserializers(&game_interface));
+        game_io.push_str(&make_js_serializers(&game_interface));
+
+        let mut io_exports = vec![];
+
+        for (name, _) in &game_interface.types {
+            io_exports.push(format!("read_{}", name));
+            io_exports.push(format!("write_{}", name));
+        }
+
+        //Callbacks are sent straight away, tagged with their index + 1 (0 marks a reply)
+        for (i, (name, signature)) in game_interface.callbacks.iter().enumerate() {
+            let args: Vec<_> = signature.args.iter().map(|(name, _)| name.as_str()).collect();
+
+            game_io.push_str("/**\n");
+            for (arg, ty) in &signature.args {
+                game_io.push_str(&format!(" * @param {{{}}} {}\n", type_as_jsdoc(ty), arg));
+            }
+            game_io.push_str(" */\n");
+
+            game_io.push_str(&format!("function {}({}) {{\n", name, args.join(", ")));
+            game_io.push_str(&format!("    write_u32({});\n", i + 1));
+
+            for (arg, ty) in &signature.args {
+                game_io.push_str(&make_serializer(ty, arg, 1, 0));
+            }
+
+            game_io.push_str("    flush();\n}\n\n");
+
+            io_exports.push(name.clone());
+        }
+
+        game_io.push_str(&format!("module.exports = {{\n    {}\n}};\n", io_exports.join(",\n    ")));
+
+        res.add_file("run/game_io.js", game_io, false, "Reads and writes game types (You do not need this file)", "game_io.js");
+
+        let mut template = String::new();
+
+        let type_exports: Vec<_> = exports.iter().filter(|x| *x != "INTERFACE_HASH").cloned().collect();
+        if !type_exports.is_empty() {
+            template.push_str(&format!("const {{ {} }} = require('game_types');\n", type_exports.join(", ")));
+        }
+
+        if !game_interface.callbacks.is_empty() {
+            let callbacks: Vec<_> = game_interface.callbacks.iter().map(|(name, _)| name.as_str()).collect();
+            template.push_str(&format!("const {{ {} }} = require('game_io');\n", callbacks.join(", ")));
+        }
+
+        template.push('\n');
+
+        for (name, signature) in &game_interface.functions {
+            template.push_str("/**\n");
+
+            for (arg, ty) in &signature.args {
+                template.push_str(&format!(" * @param {{{}}} {}\n", type_as_jsdoc(ty), arg));
+            }
+
+            if let Some(ret) = &signature.ret {
+                template.push_str(&format!(" * @returns {{{}}}\n", type_as_jsdoc(ret)));
+            }
+
+            template.push_str(" */\n");
+
+            let args: Vec<_> = signature.args.iter().map(|(name, _)| name.as_str()).collect();
+            template.push_str(&format!("function {}({}) {{\n", name, args.join(", ")));
+            template.push_str("    // Implement logic here...\n");
+
+            if let Some(ret) = &signature.ret {
+                template.push_str(&format!("    return {};\n", make_default_value(ret, &game_interface)));
+            }
+
+            template.push_str("}\n\n");
+        }
+
+        let functions: Vec<_> = game_interface.functions.iter().map(|(name, _)| name.as_str()).collect();
+        template.push_str(&format!("module.exports = {{ {} }};\n", functions.join(", ")));
+
+        res.add_file("agent.js", template, false, "Basic template for agent", "game.js");
+
+        let mut interactor = String::new();
+
+        interactor.push_str(LIB_IMPORTS);
+        interactor.push_str("const { INTERFACE_HASH } = require('./game_types');\n");
+        let type_io: Vec<_> = game_interface.types.iter().flat_map(|(name, _)| [format!("read_{}", name), format!("write_{}", name)]).collect();
+        if !type_io.is_empty() {
+            interactor.push_str(&format!("const {{ {} }} = require('./game_io');\n", type_io.join(", ")));
+        }
+
+        interactor.push_str("const agent = require('game');\n\n");
+
+        interactor.push_str("function mainloop() {\n    write_u64(INTERFACE_HASH);\n    flush();\n");
+
+        if game_interface.framed {
+            interactor.push_str("    enable_framing();\n");
+        }
+
+        interactor.push_str("\n    while (true) {\n        begin_message();\n        const func_id = read_u32();\n");
+
+        for (i, (name, signature)) in game_interface.functions.iter().enumerate() {
+            interactor.push_str(&format!("\n        if (func_id === {}) {{\n", i));
+
+            //Arguments are read up front so that the whole message is checked before the agent runs
+            for (arg, ty) in &signature.args {
+                interactor.push_str(&format!("            const param_{} = {};\n", arg, make_deserializer(ty)));
+            }
+
+            interactor.push_str("            end_message();\n");
+
+            let args: Vec<_> = signature.args.iter().map(|(arg, _)| format!("param_{}", arg)).collect();
+            let call = format!("agent.{}({})", name, args.join(", "));
+
+            if let Some(ret) = &signature.ret {
+                interactor.push_str(&format!("            const ret = {};\n", call));
+                interactor.push_str("            write_u32(0);\n");
+                interactor.push_str(&make_serializer(ret, "ret", 3, 0));
+                interactor.push_str("            flush();\n");
+            } else {
+                interactor.push_str(&format!("            {};\n", call));
+                interactor.push_str("            write_u32(0);\n");
+                interactor.push_str("            flush();\n");
+            }
+
+            interactor.push_str("            continue;\n        }\n");
+        }
+
+        interactor.push_str("    }\n}\n\nmainloop();\n");
+
+        res.add_file("run/interactor.js", interactor, false, "The interactor (You do not need this file)", "interactor.js");
+
+        res
+    }
+
+    async fn prepare(&self, submission: &Submission, out: &mut PreparedProgram, _game_interface: &GameInterface, _sandboxes: Pool<IsolateSandbox>, _limits: &ResourceLimits, _variant: &str) -> Result<(), String> {
+        out.add_src_file("game.js", &submission.entry);
+        out.add_files(submission)?;
+
+        Ok(())
+    }
+
+    //V8 reserves a lot of address space up front
+    fn limit_multipliers(&self) -> LimitMultipliers {
+        LimitMultipliers {
+            time: 2.0,
+            memory: 1.0,
+            memory_overhead_kb: 1024 * 1024
+        }
+    }
+
+    //V8 runs a few helper threads next to the main one. Its heap gets the game's memory limit, the
+    //overhead is left for the rest of the runtime
+    fn launch(&self, data_dir: &str, sandbox: &IsolateSandbox, game_interface: &GameInterface, limits: &ResourceLimits, _variant: &str) -> RunningJob {
+        let heap_mb = (self.limit_multipliers().heap_kb(limits.memory_kb) / 1024).max(16);
+
+        sandbox.launch(
+            "/usr/bin/node".to_string(),
+            vec![
+                format!("--max-old-space-size={}", heap_mb),
+                "/prog/run/interactor.js".to_string()
+            ],
+            &LaunchOptions::new()
+                .run_limits(limits)
+                .max_processes(MaxProcessCount::Fixed(16))
+                .map_dir("/prog", self.get_dir(game_interface))
+                .map_dir("/game", data_dir)
+                .set_env("NODE_PATH", "/game:/prog/run")
+        )
+    }
+}
+
+unsafe impl Send for JavaScript {}
+unsafe impl Sync for JavaScript {}
@@ -5,9 +5,9 @@ use async_trait::async_trait;
 use deadpool::unmanaged::Pool;
 use gamedef::game_interface::GameInterface;
 
-use crate::{isolate::sandbox::{IsolateSandbox, RunningJob}, util::{temp_file::random_dir, RUN_DIR}};
+use crate::{isolate::{limits::{LimitMultipliers, ResourceLimits}, sandbox::{IsolateSandbox, RunningJob}}, util::{temp_file::random_dir, RUN_DIR}};
 
-use super::files::ClientFiles;
+use super::{files::ClientFiles, submission::Submission};
 
 pub struct PreparedProgram {
     pub dir: PathBuf,
@@ -35,6 +35,11 @@ impl PreparedProgram {
         
     }
 
+    //Submitted files other than the entry point go next to it, so they can be read at runtime
+    pub fn add_files(&self, submission: &Submission) -> Result<(), String> {
+        submission.write_files(&self.dir_as_string())
+    }
+
     pub fn dir_as_string(&self) -> String {
         self.dir.to_str().unwrap().to_string()
     }
@@ -51,6 +56,18 @@ impl PreparedProgram {
     }
 }
 
+//A version or set of compiler options a submission can pick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+    pub id: &'static str,
+    pub name: &'static str
+}
+
+pub const DEFAULT_VARIANT: Variant = Variant {
+    id: "default",
+    name: "Default"
+};
+
 #[async_trait]
 pub trait Language: Send + Sync {
     fn name(&self) -> &'static str;
@@ -60,9 +77,29 @@ pub trait Language: Send + Sync {
     fn generate(&self, game_interface: &GameInterface) -> ClientFiles;
 
     //TODO: Make prepare async to allow for compiled languages to work
-    async fn prepare(&self, src: &str, out: &mut PreparedProgram, game_interface: &GameInterface, sandboxes: Pool<IsolateSandbox>) -> Result<(), String>;
+    async fn prepare(&self, submission: &Submission, out: &mut PreparedProgram, game_interface: &GameInterface, sandboxes: Pool<IsolateSandbox>, limits: &ResourceLimits, variant: &str) -> Result<(), String>;
+
+    fn launch(&self, data_dir: &str, sandbox: &IsolateSandbox, itf: &GameInterface, limits: &ResourceLimits, variant: &str) -> RunningJob;
 
-    fn launch(&self, data_dir: &str, sandbox: &IsolateSandbox, itf: &GameInterface) -> RunningJob;
+    //The first variant is the default
+    fn variants(&self) -> Vec<Variant> {
+        vec![DEFAULT_VARIANT]
+    }
+
+    //Unknown ids fall back to the default, so agents stored with a variant that was since removed still run
+    fn variant(&self, id: &str) -> Variant {
+        let variants = self.variants();
+        variants.iter().find(|x| x.id == id).copied().unwrap_or(variants[0])
+    }
+
+    fn limit_multipliers(&self) -> LimitMultipliers {
+        LimitMultipliers::default()
+    }
+
+    //Turns the limits from the game config into the ones prepare and launch expect
+    fn scale_limits(&self, limits: &ResourceLimits) -> ResourceLimits {
+        limits.scaled(&self.limit_multipliers())
+    }
 
     fn get_dir(&self, itf: &GameInterface) -> String {
         format!("gen/{}/{}", itf.name, self.id())
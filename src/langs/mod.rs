@@ -1,20 +1,28 @@
 use std::sync::Arc;
 
-use crate::langs::{python::Python, cpp::CppLang};
+use crate::langs::{python::Python, cpp::CppLang, javascript::JavaScript, rust::RustLang, java::JavaLang, go::GoLang};
 
 use self::language::Language;
 
 pub mod language;
 pub mod files;
+pub mod submission;
 
 pub mod python;
 pub mod javascript;
 pub mod cpp;
+pub mod rust;
+pub mod java;
+pub mod go;
 
 pub fn get_all_languages() -> Vec<Arc<dyn Language>> {
     vec![
         Arc::new(Python),
-        Arc::new(CppLang)
+        Arc::new(CppLang),
+        Arc::new(JavaScript),
+        Arc::new(RustLang),
+        Arc::new(JavaLang),
+        Arc::new(GoLang)
     ]
 }
 
@@ -27,11 +35,19 @@ mod tests {
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
 
-    use crate::{games::await_seconds, isolate::sandbox::IsolateSandbox, langs::language::Language};
-    use super::{cpp::CppLang, language::PreparedProgram, python::Python};
+    use crate::{games::await_seconds, isolate::{limits::ResourceLimits, sandbox::IsolateSandbox}, langs::language::Language};
+    use super::{submission::Submission, cpp::CppLang, go::GoLang, java::JavaLang, javascript::JavaScript, language::PreparedProgram, python::Python, rust::RustLang};
 
     make_server!("test_res/games/ser_test.game");
 
+    struct CollectLogs<'a>(&'a mut Vec<String>);
+
+    #[async_trait::async_trait]
+    impl<'a> AgentCallbacks for CollectLogs<'a> {
+        async fn log_debug(&mut self, msg: String) {
+            self.0.push(msg);
+        }
+    }
 
     #[test]
     fn test_serialisation() {
@@ -51,6 +67,10 @@ mod tests {
         let tests: Vec<(Box<dyn Language>, &str)> = vec![
             (Box::new(CppLang), "test_res/ser_test_agents/agent.cpp"),
             (Box::new(Python), "test_res/ser_test_agents/agent.py"),
+            (Box::new(JavaScript), "test_res/ser_test_agents/agent.js"),
+            (Box::new(RustLang), "test_res/ser_test_agents/agent.rs"),
+            (Box::new(JavaLang), "test_res/ser_test_agents/Agent.java"),
+            (Box::new(GoLang), "test_res/ser_test_agents/agent.go"),
         ];
 
         let sandboxes = Pool::new(1);
@@ -61,19 +81,29 @@ mod tests {
 
         for (lang, agent_file) in tests {
             let client_files = lang.prepare_files(&itf);
+            let limits = lang.scale_limits(&ResourceLimits {
+                call_time_s: 0.1,
+                ..ResourceLimits::default()
+            });
 
             let src = std::fs::read_to_string(agent_file).unwrap();
             let mut program = PreparedProgram::new();
             
-            pollster::block_on(lang.prepare(&src, &mut program, &itf, sandboxes.clone())).unwrap();
+            pollster::block_on(lang.prepare(&Submission::single(src), &mut program, &itf, sandboxes.clone(), &limits, "")).unwrap();
 
             let mut sandbox = pollster::block_on(sandboxes.get()).unwrap();
             pollster::block_on(sandbox.initialize());
-            let mut job: crate::isolate::sandbox::RunningJob = lang.launch(&program.dir_as_string(), &sandbox, &itf);
+            let mut job: crate::isolate::sandbox::RunningJob = lang.launch(&program.dir_as_string(), &sandbox, &itf, &limits, "");
             job.stderr.freeze();
             job._metafile.freeze();
 
+            let mut logs = vec![];
+
             let mut agent = Agent::new(&mut job);
+            agent.set_callbacks(Box::new(CollectLogs(&mut logs)));
+
+            //Read before the calls are timed, as starting the JVM or Node takes longer than a call is allowed
+            pollster::block_on(agent.verify_interface()).unwrap();
 
             let mut whole = vec![];
 
@@ -97,25 +127,29 @@ mod tests {
                     a, b, c, d, e, f, g, h, i, j, k, l
                 };
 
-                assert_eq!(pollster::block_on(await_seconds(agent.get_a(&s), 0.1)).unwrap(), s.a);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_b(&s), 0.1)).unwrap(), s.b);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_c(&s), 0.1)).unwrap(), s.c);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_d(&s), 0.1)).unwrap(), s.d);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_e(&s), 0.1)).unwrap(), s.e);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_f(&s), 0.1)).unwrap(), s.f);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_g(&s), 0.1)).unwrap(), s.g);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_h(&s), 0.1)).unwrap(), s.h);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_i(&s), 0.1)).unwrap(), s.i);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_j(&s), 0.1)).unwrap(), s.j);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_k(&s), 0.1)).unwrap(), s.k);
-                assert_eq!(pollster::block_on(await_seconds(agent.get_l(&s), 0.1)).unwrap(), s.l);
-                assert_eq!(pollster::block_on(await_seconds(agent.list_test(&whole), 0.1)).unwrap(), whole);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_a(&s), limits.call_time_s)).unwrap(), s.a);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_b(&s), limits.call_time_s)).unwrap(), s.b);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_c(&s), limits.call_time_s)).unwrap(), s.c);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_d(&s), limits.call_time_s)).unwrap(), s.d);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_e(&s), limits.call_time_s)).unwrap(), s.e);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_f(&s), limits.call_time_s)).unwrap(), s.f);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_g(&s), limits.call_time_s)).unwrap(), s.g);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_h(&s), limits.call_time_s)).unwrap(), s.h);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_i(&s), limits.call_time_s)).unwrap(), s.i);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_j(&s), limits.call_time_s)).unwrap(), s.j);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_k(&s), limits.call_time_s)).unwrap(), s.k);
+                assert_eq!(pollster::block_on(await_seconds(agent.get_l(&s), limits.call_time_s)).unwrap(), s.l);
+                assert_eq!(pollster::block_on(await_seconds(agent.list_test(&whole), limits.call_time_s)).unwrap(), whole);
+                //Logged during a call without a return value, so only seen if the server waits for it to finish
+                pollster::block_on(await_seconds(agent.note(&format!("note {}", i)), limits.call_time_s)).unwrap();
 
                 whole.push(s);
             }
 
             pollster::block_on(agent.kill());
             pollster::block_on(sandbox.cleanup());
+
+            assert_eq!(logs, (0..1000).flat_map(|i| [format!("list_test {}", i), format!("note {}", i)]).collect::<Vec<_>>());
         }
     }
 }
\ No newline at end of file
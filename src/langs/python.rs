@@ -2,12 +2,16 @@ use async_trait::async_trait;
 use deadpool::unmanaged::Pool;
 use gamedef::game_interface::{GameInterface, self, BuiltinType, Type, get_enum_variant_type, is_basic_enum};
 
-use crate::isolate::sandbox::{RunningJob, IsolateSandbox, LaunchOptions};
+use crate::isolate::{limits::{LimitMultipliers, ResourceLimits}, sandbox::{RunningJob, IsolateSandbox, LaunchOptions}};
 
-use super::{language::{Language, PreparedProgram}, files::ClientFiles};
+use super::{language::{Language, PreparedProgram, Variant}, files::ClientFiles, submission::Submission};
 
 pub struct Python;
 
+const CPYTHON: Variant = Variant { id: "cpython", name: "CPython 3" };
+const PYPY: Variant = Variant { id: "pypy", name: "PyPy 3" };
+const PYPY_PATH: &str = "/usr/bin/pypy3";
+
 pub fn type_as_inline_python(ty: &Type) -> String {
     match ty {
         Type::Builtin(ty) => match ty {
@@ -135,6 +139,8 @@ impl Language for Python {
         type_defs.push_str("from enum import Enum\n");
         type_defs.push_str("from typing import List, ClassVar\n\n");
 
+        type_defs.push_str(&format!("INTERFACE_HASH = 0x{:016x}\n\n", game_interface.interface_hash()));
+
         let mut types = vec![];
         for (name, ty) in &game_interface.types {
             match ty {
@@ -211,6 +217,12 @@ impl Language for Python {
         }
 
         template.push_str("\n");
+
+        if !game_interface.callbacks.is_empty() {
+            let callbacks: Vec<_> = game_interface.callbacks.iter().map(|(name, _)| name.as_str()).collect();
+            template.push_str(&format!("from game_io import {}\n", callbacks.join(", ")));
+        }
+
         template.push_str("from typing import List\n\n");
 
         for (name, signature) in &game_interface.functions {
@@ -236,48 +248,47 @@ impl Language for Python {
 
         res.add_file("agent.py", template, false, "Basic template for agent", "game.py");
 
-        let mut interactor = String::new();
+        let mut game_io = String::new();
 
-        interactor.push_str("from interact_lib import *\n");
-        interactor.push_str("from game_types import *\n");
-        interactor.push_str("from game import *\n\n");
+        game_io.push_str("from interact_lib import *\n");
+        game_io.push_str("from game_types import *\n\n");
 
         for (name, ty) in &game_interface.types {
             match ty {
                 Type::Struct(fields) => {
-                    interactor.push_str(&format!("def read_{}():\n    return {}(\n", name, name));
+                    game_io.push_str(&format!("def read_{}():\n    return {}(\n", name, name));
 
                     for field in fields.iter() {
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "        {}={},\n",
                             field.name,
                             write_inline_decoder(&field.ty)
                         ));
                     }
 
-                    interactor.push_str("    )\n\n");
+                    game_io.push_str("    )\n\n");
 
-                    interactor.push_str(&format!("def write_{}(value):\n", name));
+                    game_io.push_str(&format!("def write_{}(value):\n", name));
 
                     for field in fields.iter() {
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "{}\n",
                             write_encoder(&field.ty, &format!("value.{}", field.name), 1)
                         ));
                     }
 
-                    interactor.push_str("\n\n");
+                    game_io.push_str("\n\n");
                 }
                 Type::Enum(variants) => {
                     if is_basic_enum(variants) {
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "def read_{}():\n    return {}({})\n\n",
                             name, name,
                             write_inline_decoder(&Type::Builtin(get_enum_variant_type(
                                 variants
                             )))
                         ));
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "def write_{}(value):\n    {}\n\n\n",
                             name,
                             write_encoder(
@@ -288,55 +299,55 @@ impl Language for Python {
                         ));
                     } else {
                         for variant in variants.iter() {
-                            interactor.push_str(&format!(
+                            game_io.push_str(&format!(
                                 "def read_enum_variant_{}_{}():\n    return {}.{}(\n",
                                 name, variant.name, name, variant.name
                             ));
 
                             for field in variant.types.iter() {
-                                interactor.push_str(&format!(
+                                game_io.push_str(&format!(
                                     "        {}={},\n",
                                     field.name,
                                     write_inline_decoder(&field.ty)
                                 ));
                             }
 
-                            interactor.push_str("    )\n\n");
+                            game_io.push_str("    )\n\n");
                         }
 
-                        interactor.push_str(&format!("ENUM_VARIANT_READERS_{} = [\n", name));
+                        game_io.push_str(&format!("ENUM_VARIANT_READERS_{} = [\n", name));
 
                         for variant in variants.iter() {
-                            interactor.push_str(&format!(
+                            game_io.push_str(&format!(
                                 "    read_enum_variant_{}_{},\n",
                                 name, variant.name
                             ));
                         }
 
-                        interactor.push_str("]\n\n");
+                        game_io.push_str("]\n\n");
 
-                        interactor.push_str(&format!("def read_{}():\n", name));
+                        game_io.push_str(&format!("def read_{}():\n", name));
 
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "    variant_id = {}\n",
                             write_inline_decoder(&Type::Builtin(get_enum_variant_type(
                                 variants
                             )))
                         ));
 
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "    return {}(ENUM_VARIANT_READERS_{}[variant_id]())\n\n",
                             name, name
                         ));
 
                         for variant in variants.iter() {
-                            interactor.push_str(&format!(
+                            game_io.push_str(&format!(
                                 "def write_enum_variant_{}_{}(value):\n",
                                 name, variant.name
                             ));
 
                             for field in variant.types.iter() {
-                                interactor.push_str(&format!(
+                                game_io.push_str(&format!(
                                     "{}\n",
                                     write_encoder(
                                         &field.ty,
@@ -347,46 +358,46 @@ impl Language for Python {
                             }
 
                             if variant.types.len() == 0 {
-                                interactor.push_str("    pass\n");
+                                game_io.push_str("    pass\n");
                             }
 
-                            interactor.push_str("\n");
+                            game_io.push_str("\n");
                         }
 
-                        interactor.push_str(&format!("ENUM_VARIANT_WRITERS_{} = [\n", name));
+                        game_io.push_str(&format!("ENUM_VARIANT_WRITERS_{} = [\n", name));
 
                         for variant in variants.iter() {
-                            interactor.push_str(&format!(
+                            game_io.push_str(&format!(
                                 "    write_enum_variant_{}_{},\n",
                                 name, variant.name
                             ));
                         }
 
-                        interactor.push_str("]\n\n");
+                        game_io.push_str("]\n\n");
 
-                        interactor.push_str(&format!("def write_{}(value):\n", name));
+                        game_io.push_str(&format!("def write_{}(value):\n", name));
 
-                        interactor.push_str(&write_encoder(
+                        game_io.push_str(&write_encoder(
                             &Type::Builtin(get_enum_variant_type(variants)),
                             "value.data.VARIANT_ID",
                             1,
                         ));
 
-                        interactor.push_str(&format!(
+                        game_io.push_str(&format!(
                             "\n    ENUM_VARIANT_WRITERS_{}[value.data.VARIANT_ID](value)\n",
                             name
                         ));
 
-                        interactor.push_str("\n\n");
+                        game_io.push_str("\n\n");
                     }
                 }
                 ty => {
-                    interactor.push_str(&format!(
+                    game_io.push_str(&format!(
                         "def read_{}():\n    return {}\n\n",
                         name,
                         write_inline_decoder(ty)
                     ));
-                    interactor.push_str(&format!(
+                    game_io.push_str(&format!(
                         "def write_{}(value):\n{}\n\n\n",
                         name,
                         write_encoder(ty, "value", 1)
@@ -395,31 +406,66 @@ impl Language for Python {
             }
         }
 
-        interactor.push_str("def mainloop():\n    while True:\n        func_id = read_u8()");
+        //Callbacks are sent straight away, tagged with their index + 1 (0 marks a reply)
+        for (i, (name, signature)) in game_interface.callbacks.iter().enumerate() {
+            let args: Vec<_> = signature.args.iter().map(|(name, _)| name.as_str()).collect();
+            game_io.push_str(&format!("def {}({}):\n", name, args.join(", ")));
+            game_io.push_str(&format!("    write_u32({})\n", i + 1));
+
+            for (name, ty) in &signature.args {
+                game_io.push_str(&format!("{}\n", write_encoder(ty, name, 1)));
+            }
+
+            game_io.push_str("    flush()\n\n\n");
+        }
+
+        res.add_file("run/game_io.py", game_io, false, "Reads and writes game types (You do not need this file)", "game_io.py");
+
+        let mut interactor = String::new();
+        interactor.push_str("from game_io import *\n");
+        interactor.push_str("from game import *\n\n");
+
+        interactor.push_str("def mainloop():\n    write_u64(INTERFACE_HASH)\n    flush()\n");
+
+        if game_interface.framed {
+            interactor.push_str("    enable_framing()\n");
+        }
+
+        interactor.push_str("\n    while True:\n        begin_message()\n        func_id = read_u32()");
 
         for (i, (name, signature)) in game_interface.functions.iter().enumerate() {
             interactor.push_str(&format!("\n        if func_id == {}:\n", i));
 
+            //Arguments are read up front so that the whole message is checked before the agent runs
+            for (arg, ty) in signature.args.iter() {
+                interactor.push_str(&format!("            param_{} = {}\n", arg, write_inline_decoder(ty)));
+            }
+
+            interactor.push_str("            end_message()\n");
+
             let mut func_call = String::new();
 
             func_call.push_str(&format!("{}(", name));
 
-            for (i, (_, ty)) in signature.args.iter().enumerate() {
+            for (i, (arg, _)) in signature.args.iter().enumerate() {
                 if i != 0 {
                     func_call.push_str(", ");
                 }
 
-                func_call.push_str(&write_inline_decoder(ty));
+                func_call.push_str(&format!("param_{}", arg));
             }
 
             func_call.push_str(")");
 
             if let Some(ret) = &signature.ret {
                 interactor.push_str(&format!("            ret = {}\n", func_call));
+                interactor.push_str("            write_u32(0)\n");
                 interactor.push_str(&format!("{}\n", write_encoder(ret, "ret", 3)));
                 interactor.push_str("            flush()\n");
             } else {
                 interactor.push_str(&format!("            {}\n", func_call));
+                interactor.push_str("            write_u32(0)\n");
+                interactor.push_str("            flush()\n");
             }
 
             interactor.push_str("            continue\n");
@@ -432,18 +478,37 @@ impl Language for Python {
         res
     }
 
-    async fn prepare(&self, src: &str, out: &mut PreparedProgram, _game_interface: &GameInterface, _sandboxes: Pool<IsolateSandbox>) -> Result<(), String> {
-        out.add_src_file("game.py", src);
+    async fn prepare(&self, submission: &Submission, out: &mut PreparedProgram, _game_interface: &GameInterface, _sandboxes: Pool<IsolateSandbox>, _limits: &ResourceLimits, _variant: &str) -> Result<(), String> {
+        out.add_src_file("game.py", &submission.entry);
+        out.add_files(submission)?;
 
         Ok(())
     }
 
-    fn launch(&self, data_dir: &str, sandbox: &crate::isolate::sandbox::IsolateSandbox, game_interface: &GameInterface) -> RunningJob {
+    fn limit_multipliers(&self) -> LimitMultipliers {
+        LimitMultipliers {
+            time: 3.0,
+            ..LimitMultipliers::default()
+        }
+    }
+
+    //PyPy is only offered where it is installed
+    fn variants(&self) -> Vec<Variant> {
+        if std::path::Path::new(PYPY_PATH).exists() {
+            vec![CPYTHON, PYPY]
+        } else {
+            vec![CPYTHON]
+        }
+    }
+
+    fn launch(&self, data_dir: &str, sandbox: &crate::isolate::sandbox::IsolateSandbox, game_interface: &GameInterface, limits: &ResourceLimits, variant: &str) -> RunningJob {
+        let interpreter = if self.variant(variant) == PYPY { PYPY_PATH } else { "/usr/bin/python3" };
+
         sandbox.launch(
-            "/usr/bin/python3".to_string(),
+            interpreter.to_string(),
             vec!["/prog/run/interactor.py".to_string()], 
             &LaunchOptions::new()
-                .memory_limit_kb(51200)
+                .run_limits(limits)
                 .map_dir("/prog", self.get_dir(game_interface))
                 .map_dir("/game", data_dir)
                 .set_env("PYTHONPATH", "/game")
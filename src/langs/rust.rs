@@ -0,0 +1,410 @@
+use async_std::path::PathBuf;
+use async_trait::async_trait;
+use deadpool::unmanaged::Pool;
+use gamedef::game_interface::{GameInterface, Type, BuiltinType, get_enum_variant_type, is_basic_enum, StructFields};
+
+use crate::{
+    isolate::{
+        limits::ResourceLimits,
+        sandbox::{DirMapping, IsolateSandbox, LaunchOptions, MaxProcessCount, RunningJob},
+    },
+    util::temp_file::random_dir,
+};
+
+use super::{files::ClientFiles, language::{Language, PreparedProgram}, submission::Submission};
+
+pub struct RustLang;
+
+pub fn type_as_rust(ty: &Type) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => "String".to_string(),
+        Type::Builtin(builtin) => builtin.name().to_string(),
+        Type::NamedType(name) => name.clone(),
+        Type::Array(ty, size) => format!("[{}; {}]", type_as_rust(ty), size),
+        Type::DynamicArray(ty) => format!("Vec<{}>", type_as_rust(ty)),
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+//Enum variant fields are always public, so only struct fields get the `pub` keyword
+fn struct_fields(fields: &StructFields, public: bool, indent: &str) -> String {
+    let mut res = String::new();
+
+    res.push_str("{\n");
+
+    for field in fields.iter() {
+        let visibility = if public { "pub " } else { "" };
+
+        res.push_str(&format!("{}    {}{}: {},\n", indent, visibility, field.name, type_as_rust(&field.ty)));
+    }
+
+    res.push_str(indent);
+    res.push('}');
+
+    res
+}
+
+//Struct literal fields are evaluated in the order they are written, which keeps reads in wire order
+fn read_fields(fields: &StructFields) -> String {
+    let fields: Vec<_> = fields.iter().map(|field| format!("{}: Wire::read()", field.name)).collect();
+
+    format!("{{ {} }}", fields.join(", "))
+}
+
+fn field_names(fields: &StructFields) -> String {
+    let names: Vec<_> = fields.iter().map(|field| field.name.as_str()).collect();
+
+    format!("{{ {} }}", names.join(", "))
+}
+
+fn make_wire_impl(name: &str, ty: &Type) -> String {
+    let mut res = String::new();
+
+    res.push_str(&format!("impl Wire for {} {{\n", name));
+
+    match ty {
+        Type::Struct(fields) => {
+            res.push_str(&format!("    fn read() -> Self {{\n        {} {}\n    }}\n\n", name, read_fields(fields)));
+
+            res.push_str("    fn write(&self) {\n");
+            for field in fields.iter() {
+                res.push_str(&format!("        self.{}.write();\n", field.name));
+            }
+            res.push_str("    }\n");
+        },
+
+        Type::Enum(variants) => {
+            let tag_type = get_enum_variant_type(variants).name();
+
+            res.push_str(&format!("    fn read() -> Self {{\n        match {}::read() {{\n", tag_type));
+
+            for (i, variant) in variants.iter().enumerate() {
+                if variant.types.is_empty() {
+                    res.push_str(&format!("            {} => {}::{},\n", i, name, variant.name));
+                } else {
+                    res.push_str(&format!("            {} => {}::{} {},\n", i, name, variant.name, read_fields(&variant.types)));
+                }
+            }
+
+            res.push_str(&format!("            x => panic!(\"Invalid variant {{}} for {}\", x)\n        }}\n    }}\n\n", name));
+
+            res.push_str("    fn write(&self) {\n        match self {\n");
+
+            for (i, variant) in variants.iter().enumerate() {
+                if variant.types.is_empty() {
+                    res.push_str(&format!("            {}::{} => {}{}.write(),\n", name, variant.name, i, tag_type));
+                } else {
+                    res.push_str(&format!("            {}::{} {} => {{\n", name, variant.name, field_names(&variant.types)));
+                    res.push_str(&format!("                {}{}.write();\n", i, tag_type));
+
+                    for field in variant.types.iter() {
+                        res.push_str(&format!("                {}.write();\n", field.name));
+                    }
+
+                    res.push_str("            }\n");
+                }
+            }
+
+            res.push_str("        }\n    }\n");
+        },
+
+        _ => panic!("{:?} does not need its own Wire implementation", ty)
+    }
+
+    res.push_str("}\n\n");
+
+    res
+}
+
+fn make_default_value(ty: &Type, itf: &GameInterface) -> String {
+    match ty {
+        Type::Builtin(BuiltinType::Str) => "String::new()".to_string(),
+        Type::Builtin(BuiltinType::Bool) => "false".to_string(),
+        Type::Builtin(BuiltinType::F32) | Type::Builtin(BuiltinType::F64) => "0.0".to_string(),
+        Type::Builtin(_) => "0".to_string(),
+
+        Type::Array(ty, _) => format!("std::array::from_fn(|_| {})", make_default_value(ty, itf)),
+        Type::DynamicArray(_) => "Vec::new()".to_string(),
+
+        Type::NamedType(name) => {
+            let (_, t) = itf.types.iter().find(|(x, _)| x == name).unwrap();
+
+            match t {
+                Type::Struct(fields) => {
+                    let fields: Vec<_> = fields.iter().map(|field| format!("{}: {}", field.name, make_default_value(&field.ty, itf))).collect();
+
+                    format!("{} {{ {} }}", name, fields.join(", "))
+                },
+                Type::Enum(variants) => {
+                    let variant = &variants[0];
+
+                    if variant.types.is_empty() {
+                        format!("{}::{}", name, variant.name)
+                    } else {
+                        let fields: Vec<_> = variant.types.iter().map(|field| format!("{}: {}", field.name, make_default_value(&field.ty, itf))).collect();
+
+                        format!("{}::{} {{ {} }}", name, variant.name, fields.join(", "))
+                    }
+                },
+                t => make_default_value(t, itf)
+            }
+        },
+
+        _ => panic!("{:?} must be referred to through a named type", ty)
+    }
+}
+
+fn function_signature(name: &str, args: &[(String, Type)], ret: &Option<Type>) -> String {
+    let args: Vec<_> = args.iter().map(|(name, ty)| format!(", {}: {}", name, type_as_rust(ty))).collect();
+
+    match ret {
+        Some(ret) => format!("fn {}(&mut self{}) -> {}", name, args.join(""), type_as_rust(ret)),
+        None => format!("fn {}(&mut self{})", name, args.join(""))
+    }
+}
+
+#[async_trait]
+impl Language for RustLang {
+    fn name(&self) -> &'static str {
+        "Rust"
+    }
+
+    fn id(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn generate(
+        &self,
+        game_interface: &GameInterface,
+    ) -> ClientFiles {
+        let game_interface = game_interface.reduced();
+
+        let mut res = ClientFiles::new();
+
+        //Everything the agent needs lives in game.rs so that the client is a single module
+        let mut game = String::new();
+
+        game.push_str("#![allow(dead_code, non_camel_case_types, non_snake_case)]\n\n");
+        game.push_str(&std::fs::read_to_string("res/client_files/interact_lib.rs").unwrap());
+        game.push('\n');
+
+        game.push_str(&format!("pub const INTERFACE_HASH: u64 = 0x{:016x};\n\n", game_interface.interface_hash()));
+
+        for (name, ty) in &game_interface.types {
+            match ty {
+                Type::Struct(fields) => {
+                    game.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+                    game.push_str(&format!("pub struct {} {}\n\n", name, struct_fields(fields, true, "")));
+                    game.push_str(&make_wire_impl(name, ty));
+                },
+                Type::Enum(variants) => {
+                    if is_basic_enum(variants) {
+                        game.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq)]\n");
+                    } else {
+                        game.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+                    }
+
+                    game.push_str(&format!("pub enum {} {{\n", name));
+
+                    for variant in variants.iter() {
+                        if variant.types.is_empty() {
+                            game.push_str(&format!("    {},\n", variant.name));
+                        } else {
+                            game.push_str(&format!("    {} {},\n", variant.name, struct_fields(&variant.types, false, "    ")));
+                        }
+                    }
+
+                    game.push_str("}\n\n");
+                    game.push_str(&make_wire_impl(name, ty));
+                },
+                ty => {
+                    game.push_str(&format!("pub type {} = {};\n\n", name, type_as_rust(ty)));
+                }
+            }
+        }
+
+        //Callbacks are tagged with their index + 1 (0 marks a reply)
+        for (idx, (name, signature)) in game_interface.callbacks.iter().enumerate() {
+            let args: Vec<_> = signature.args.iter().map(|(name, ty)| format!("{}: {}", name, type_as_rust(ty))).collect();
+
+            game.push_str(&format!("pub fn {}({}) {{\n", name, args.join(", ")));
+            game.push_str(&format!("    {}u32.write();\n", idx + 1));
+
+            for (arg, _) in &signature.args {
+                game.push_str(&format!("    {}.write();\n", arg));
+            }
+
+            game.push_str("    flush();\n}\n\n");
+        }
+
+        game.push_str("pub trait Bot {\n");
+
+        for (name, signature) in &game_interface.functions {
+            game.push_str(&format!("    {};\n", function_signature(name, &signature.args, &signature.ret)));
+        }
+
+        game.push_str("}\n\n");
+
+        game.push_str("pub fn run(mut bot: impl Bot) {\n    INTERFACE_HASH.write();\n    flush();\n");
+
+        if game_interface.framed {
+            game.push_str("    enable_framing();\n");
+        }
+
+        game.push_str("\n    loop {\n        begin_message();\n\n        match u32::read() {\n");
+
+        for (idx, (name, signature)) in game_interface.functions.iter().enumerate() {
+            game.push_str(&format!("            {} => {{\n", idx));
+
+            for (arg, ty) in &signature.args {
+                game.push_str(&format!("                let param_{}: {} = Wire::read();\n", arg, type_as_rust(ty)));
+            }
+
+            game.push_str("                end_message();\n\n");
+
+            let args: Vec<_> = signature.args.iter().map(|(arg, _)| format!("param_{}", arg)).collect();
+            let call = format!("bot.{}({})", name, args.join(", "));
+
+            if signature.ret.is_some() {
+                game.push_str(&format!("                let ret = {};\n", call));
+                game.push_str("                0u32.write();\n                ret.write();\n                flush();\n");
+            } else {
+                game.push_str(&format!("                {};\n", call));
+                game.push_str("                0u32.write();\n                flush();\n");
+            }
+
+            game.push_str("            }\n");
+        }
+
+        game.push_str("            x => {\n                eprintln!(\"Unknown function {}\", x);\n                std::process::exit(1);\n            }\n");
+        game.push_str("        }\n    }\n}\n");
+
+        res.add_file("game.rs", game, false, "Defines types for game and handles communication with the server", "game.rs");
+
+        res.add_file(
+            "main.rs",
+            "mod game;\nmod agent;\n\nfn main() {\n    game::run(agent::make_bot());\n}\n".to_string(),
+            false,
+            "Entry point, compile with `rustc -O main.rs` (You do not need this file)",
+            "main.rs"
+        );
+
+        let mut template = String::new();
+
+        template.push_str("use crate::game::*;\n\n");
+        template.push_str("pub struct MyBot;\n\n");
+        template.push_str("pub fn make_bot() -> MyBot {\n    MyBot\n}\n\n");
+        template.push_str("impl Bot for MyBot {\n");
+
+        for (idx, (name, signature)) in game_interface.functions.iter().enumerate() {
+            if idx != 0 {
+                template.push('\n');
+            }
+
+            template.push_str(&format!("    {} {{\n        //Implement logic here...\n", function_signature(name, &signature.args, &signature.ret)));
+
+            if let Some(ret) = &signature.ret {
+                template.push_str(&format!("        {}\n", make_default_value(ret, &game_interface)));
+            }
+
+            template.push_str("    }\n");
+        }
+
+        template.push_str("}\n");
+
+        res.add_file("agent.rs", template, false, "Basic template for agent", "agent.rs");
+
+        res
+    }
+
+    async fn prepare(
+        &self,
+        submission: &Submission,
+        out: &mut PreparedProgram,
+        game_interface: &GameInterface,
+        sandboxes: Pool<IsolateSandbox>,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> Result<(), String> {
+        let mut sandbox = sandboxes.get().await.unwrap();
+        sandbox.initialize().await;
+
+        //rustc looks for modules next to main.rs, so the generated files are copied in beside the agent
+        let temp_folder = random_dir("./tmp");
+        async_std::fs::write(format!("{}/{}", temp_folder, "agent.rs"), &submission.entry)
+            .await
+            .unwrap();
+        submission.write_files(&temp_folder)?;
+        out.add_files(submission)?;
+
+        for file in ["main.rs", "game.rs"] {
+            async_std::fs::copy(format!("{}/{}", self.get_dir(game_interface), file), format!("{}/{}", temp_folder, file))
+                .await
+                .map_err(|x| format!("Error {x} when copying {file}"))?;
+        }
+
+        let mut compile_job: RunningJob = sandbox.launch(
+            "/usr/bin/rustc".to_string(),
+            vec![
+                "--edition=2021".to_string(),
+                "-O".to_string(),
+                "-o".to_string(),
+                "/box/agent.o".to_string(),
+                "/src/main.rs".to_string()
+            ],
+            &LaunchOptions::new()
+                .compile_limits(limits)
+                .max_processes(MaxProcessCount::Unlimited)
+                .add_mapping(DirMapping::named("/out", out.dir_as_string()).read_write())
+                .map_dir("/src", temp_folder.clone())
+                .map_dir("/usr/bin", "/usr/bin")
+                .full_env()
+        );
+
+        let status = compile_job.wait().await.unwrap();
+
+        if !status.success() {
+            return Err(compile_job.stderr.read_as_string().await);
+        } else {
+            println!("Compile result\n\n{}", compile_job.stderr.read_as_string().await);
+        }
+
+        let mut output_file = PathBuf::from(sandbox.box_dir());
+        output_file.push("box");
+        output_file.push("agent.o");
+
+        let mut target_location = out.dir.clone();
+        target_location.push("agent.o");
+
+        std::fs::copy(&output_file, &target_location).map_err(|x| format!("Error {x} when copying '{:?}' to '{:?}'", output_file, target_location))?;
+
+        sandbox.cleanup().await;
+
+        Ok(())
+    }
+
+    fn launch(
+        &self,
+        data_dir: &str,
+        sandbox: &IsolateSandbox,
+        _itf: &GameInterface,
+        limits: &ResourceLimits,
+        _variant: &str
+    ) -> RunningJob {
+        sandbox.launch(
+            format!("{data_dir}/agent.o"),
+            vec![],
+            &LaunchOptions::new()
+                .run_limits(limits)
+                .map_full(data_dir)
+        )
+    }
+}
+
+unsafe impl Send for RustLang {}
+unsafe impl Sync for RustLang {}
@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, io::{Cursor, Read}, path::Path};
+
+//Largest a submission can be once unpacked
+pub const MAX_SUBMISSION_SIZE: usize = 4 * 1024 * 1024;
+pub const MAX_SUBMISSION_FILES: usize = 256;
+
+//The files an agent was submitted as. The entry point takes the place of the single source file each
+//language expects, the other files are placed around it with the same relative paths
+#[derive(Debug, Clone)]
+pub struct Submission {
+    pub entry: String,
+    pub files: BTreeMap<String, Vec<u8>>
+}
+
+//Only plain relative paths, so that no file can end up outside the program's directory
+pub fn validate_path(path: &str) -> Result<String, String> {
+    let path = path.strip_prefix("./").unwrap_or(path);
+
+    if path.is_empty() || path.len() > 255 {
+        return Err(format!("Invalid file name '{}'", path));
+    }
+
+    if path.starts_with('/') || path.contains('\\') || path.contains('\0') {
+        return Err(format!("File '{}' must be a relative path", path));
+    }
+
+    for part in path.split('/') {
+        if part.is_empty() || part == "." || part == ".." {
+            return Err(format!("Invalid path '{}'", path));
+        }
+    }
+
+    Ok(path.to_string())
+}
+
+impl Submission {
+    pub fn single(src: String) -> Self {
+        Self {
+            entry: src,
+            files: BTreeMap::new()
+        }
+    }
+
+    pub fn from_files(files: Vec<(String, Vec<u8>)>, entry: &str) -> Result<Self, String> {
+        if files.len() > MAX_SUBMISSION_FILES {
+            return Err(format!("Submissions can have at most {} files", MAX_SUBMISSION_FILES));
+        }
+
+        let entry = validate_path(entry)?;
+        let mut entry_src = None;
+        let mut res = BTreeMap::new();
+        let mut size = 0;
+
+        for (path, content) in files {
+            let path = validate_path(&path)?;
+
+            size += content.len();
+            if size > MAX_SUBMISSION_SIZE {
+                return Err(format!("Submissions can be at most {} bytes", MAX_SUBMISSION_SIZE));
+            }
+
+            if path == entry {
+                entry_src = Some(String::from_utf8(content).map_err(|_| format!("Entry point '{}' isn't valid UTF-8", entry))?);
+            } else if res.insert(path.clone(), content).is_some() {
+                return Err(format!("File '{}' appears twice", path));
+            }
+        }
+
+        match entry_src {
+            Some(entry) => Ok(Self {
+                entry,
+                files: res
+            }),
+            None => Err(format!("Entry point '{}' not found", entry))
+        }
+    }
+
+    pub fn from_zip(data: &[u8], entry: &str) -> Result<Self, String> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Invalid zip archive: {}", e))?;
+
+        //Directories count too, a real submission won't have anywhere near this many
+        if archive.len() > MAX_SUBMISSION_FILES {
+            return Err(format!("Submissions can have at most {} files", MAX_SUBMISSION_FILES));
+        }
+
+        let mut files = vec![];
+        let mut size = 0;
+
+        for i in 0..archive.len() {
+            let file = archive.by_index(i).map_err(|e| format!("Invalid zip archive: {}", e))?;
+
+            if file.is_dir() {
+                continue;
+            }
+
+            if file.is_symlink() {
+                return Err(format!("Symlinks aren't allowed ('{}')", file.name()));
+            }
+
+            let name = file.name().to_string();
+
+            //The declared size can't be trusted, so reading stops just past what's left of the limit
+            let mut content = vec![];
+            file.take((MAX_SUBMISSION_SIZE - size) as u64 + 1).read_to_end(&mut content).map_err(|e| format!("Error reading '{}': {}", name, e))?;
+
+            size += content.len();
+            if size > MAX_SUBMISSION_SIZE {
+                return Err(format!("Submissions can be at most {} bytes", MAX_SUBMISSION_SIZE));
+            }
+
+            files.push((name, content));
+        }
+
+        Self::from_files(files, entry)
+    }
+
+    pub fn from_tar(data: &[u8], entry: &str) -> Result<Self, String> {
+        const BLOCK: usize = 512;
+
+        let parse_octal = |field: &[u8]| -> Result<usize, String> {
+            let text = std::str::from_utf8(field).map_err(|_| "Invalid tar archive".to_string())?;
+            let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+
+            if text.is_empty() {
+                return Ok(0);
+            }
+
+            usize::from_str_radix(text, 8).map_err(|_| "Invalid tar archive".to_string())
+        };
+
+        let mut files = vec![];
+        let mut pos = 0;
+
+        while pos + BLOCK <= data.len() {
+            let header = &data[pos..pos + BLOCK];
+
+            //The archive ends with zeroed blocks
+            if header.iter().all(|&x| x == 0) {
+                break;
+            }
+
+            let field = |start: usize, len: usize| {
+                let field = &header[start..start + len];
+                let end = field.iter().position(|&x| x == 0).unwrap_or(len);
+                String::from_utf8_lossy(&field[..end]).to_string()
+            };
+
+            let name = field(0, 100);
+            let prefix = if &header[257..262] == b"ustar" { field(345, 155) } else { String::new() };
+            let name = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+
+            let size = parse_octal(&header[124..136])?;
+            let kind = header[156];
+
+            let start = pos + BLOCK;
+            let end = start.checked_add(size).filter(|&end| end <= data.len()).ok_or("Truncated tar archive".to_string())?;
+
+            match kind {
+                b'0' | b'\0' | b'7' => files.push((name, data[start..end].to_vec())),
+                //Directories and metadata headers
+                b'5' | b'x' | b'g' => {},
+                _ => return Err(format!("Unsupported entry '{}' in tar archive, only regular files are allowed", name))
+            }
+
+            pos = start + size.div_ceil(BLOCK) * BLOCK;
+        }
+
+        Self::from_files(files, entry)
+    }
+
+    pub fn size(&self) -> usize {
+        self.entry.len() + self.files.values().map(|x| x.len()).sum::<usize>()
+    }
+
+    //Paths of the other files with one of the given extensions, for languages that need their sources listed
+    pub fn files_with_extension<'a>(&'a self, extensions: &'a [&str]) -> impl Iterator<Item = &'a str> {
+        self.files.keys()
+            .filter(move |path| Path::new(path).extension().and_then(|x| x.to_str()).is_some_and(|x| extensions.contains(&x)))
+            .map(|x| x.as_str())
+    }
+
+    //Writes everything but the entry point into dir
+    pub fn write_files(&self, dir: &str) -> Result<(), String> {
+        for (path, content) in &self.files {
+            let full_path = Path::new(dir).join(path);
+
+            if let Some(parent) = full_path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| format!("Error {} when creating directory for '{}'", e, path))?;
+            }
+
+            std::fs::write(&full_path, content).map_err(|e| format!("Error {} when writing '{}'", e, path))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::{validate_path, Submission, MAX_SUBMISSION_FILES, MAX_SUBMISSION_SIZE};
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(validate_path("./lib/helper.py").unwrap(), "lib/helper.py");
+
+        assert!(validate_path("../game.py").is_err());
+        assert!(validate_path("lib/../../game.py").is_err());
+        assert!(validate_path("/etc/passwd").is_err());
+        assert!(validate_path("lib//helper.py").is_err());
+        assert!(validate_path("lib\\..\\helper.py").is_err());
+
+        let files = vec![
+            ("agent.py".to_string(), b"import helper".to_vec()),
+            ("helper.py".to_string(), vec![])
+        ];
+
+        let submission = Submission::from_files(files.clone(), "agent.py").unwrap();
+        assert_eq!(submission.entry, "import helper");
+        assert_eq!(submission.files.keys().collect::<Vec<_>>(), vec!["helper.py"]);
+
+        assert!(Submission::from_files(files, "main.py").is_err());
+    }
+
+    #[test]
+    fn test_zip_limits() {
+        let zip = |entries: usize, entry_size: usize| {
+            let mut writer = ZipWriter::new(Cursor::new(vec![]));
+            let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+            writer.start_file("agent.py", options).unwrap();
+            writer.write_all(b"import helper").unwrap();
+
+            for i in 0..entries {
+                writer.start_file(format!("data/{}.bin", i), options).unwrap();
+                writer.write_all(&vec![0; entry_size]).unwrap();
+            }
+
+            writer.finish().unwrap().into_inner()
+        };
+
+        let submission = Submission::from_zip(&zip(3, 1024), "agent.py").unwrap();
+        assert_eq!(submission.files.len(), 3);
+
+        //Each entry is under the limit on its own, together they inflate to 64MB from a few hundred KB
+        let bomb = zip(64, 1024 * 1024);
+        assert!(bomb.len() < MAX_SUBMISSION_SIZE / 8);
+        assert!(Submission::from_zip(&bomb, "agent.py").unwrap_err().contains("at most"));
+
+        assert!(Submission::from_zip(&zip(MAX_SUBMISSION_FILES, 0), "agent.py").unwrap_err().contains("files"));
+    }
+}
@@ -6,7 +6,7 @@ use sea_orm::{Database, EntityTrait, QueryFilter, ColumnTrait, DatabaseConnectio
 use util::DATABASE_URL;
 use std::{path::{Path, PathBuf}, sync::Arc, collections::HashSet};
 
-use crate::{games::{Game, nzoi_snake::NzoiSnake}, util::RUN_DIR, web::{api, game_reporter::GameReporter}, entities::agent};
+use crate::{games::{Game, nzoi_snake::NzoiSnake}, util::RUN_DIR, web::{api, config::{ServerConfig, SERVER_CONFIG}, game_reporter::GameReporter}, entities::agent};
 
 pub mod isolate;
 pub mod util;
@@ -122,10 +122,11 @@ fn main() {
 
         //Launch api on new thread
         let runner_copy = runner.clone();
+        let server_config = ServerConfig::load(SERVER_CONFIG).unwrap();
         
         std::thread::spawn(move || {
             async_std::task::block_on(async {
-                api::launch_and_run_api(runner_copy, reporter_inner, db_copy).await.unwrap();
+                api::launch_and_run_api(runner_copy, reporter_inner, db_copy, server_config).await.unwrap();
             });
         });
 
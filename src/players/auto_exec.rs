@@ -1,20 +1,23 @@
-use std::{sync::Arc, time::Duration};
+use std::{sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
 use colors_transform::{Hsl, Color};
-use deadpool::unmanaged::Pool;
+use deadpool::unmanaged::{Object, Pool};
 use gamedef::{game_interface::GameInterface, parser::parse_game_interface};
 use log::{debug, warn, info, error};
 use rand::Rng;
 use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait, QueryOrder, sea_query::{Func, SimpleExpr}, QuerySelect, ActiveValue, ActiveModelTrait, Value, DbErr};
 
 use crate::{
-    games::Game, isolate::sandbox::IsolateSandbox, langs::{get_all_languages, language::Language, files::ClientFiles}, util::{temp_file::{TempFile, random_file}, ActiveValueExtension, RUN_DIR}, entities::{agent, self}
+    games::Game, isolate::sandbox::IsolateSandbox, langs::{get_all_languages, language::{Language, PreparedProgram}, files::ClientFiles, python::Python, submission::Submission}, util::{temp_file::{TempFile, random_file}, ActiveValueExtension, RUN_DIR}, entities::{agent, fault, self}
 };
 
 use crate::entities::prelude::*;
 
 use super::reporting::Reporter;
 
+//The dummy opponent is prepared here on every start, and removed along with the runner
+const DUMMY_DIR: &str = "./dummy";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PlayerId(usize);
 
@@ -52,9 +55,35 @@ pub struct GameRunner<T: Game + 'static> {
     pub itf: GameInterface,
     pub languages: Vec<(Arc<dyn Language>, ClientFiles)>,
 
+    //Opponent for validation games: the Python template, which answers every call with a default value.
+    //None if it couldn't be prepared, agents then skip validation
+    dummy: Option<(Arc<dyn Language>, PreparedProgram)>,
+
     pub reporting: Reporter
 }
 
+//Kept out of RUN_DIR so that file cleanup doesn't delete it
+async fn prepare_dummy<T: Game>(itf: &GameInterface, sandboxes: Pool<IsolateSandbox>, game: &T) -> Result<(Arc<dyn Language>, PreparedProgram), String> {
+    //Whatever an earlier run left behind
+    std::fs::remove_dir_all(DUMMY_DIR).unwrap_or(());
+    std::fs::create_dir_all(DUMMY_DIR).map_err(|e| format!("Couldn't create {}: {}", DUMMY_DIR, e))?;
+
+    let language: Arc<dyn Language> = Arc::new(Python);
+    let mut dummy = PreparedProgram {
+        dir: DUMMY_DIR.into(),
+        src: None
+    };
+
+    let src = Submission::single(language.generate(itf).files["agent.py"].content.clone());
+
+    if let Err(e) = language.prepare(&src, &mut dummy, itf, sandboxes, &language.scale_limits(&game.limits()), "").await {
+        std::fs::remove_dir_all(DUMMY_DIR).unwrap_or(());
+        return Err(e);
+    }
+
+    Ok((language, dummy))
+}
+
 impl<T: Game + 'static> GameRunner<T> {
     pub async fn new(game: T, name: &str, num_sandboxes: usize, db: DatabaseConnection) -> Self {
         let itf_path = format!("res/games/{}.game", name);
@@ -73,6 +102,14 @@ impl<T: Game + 'static> GameRunner<T> {
             pool.add(IsolateSandbox::new(i as u32).await).await.unwrap();
         }
 
+        let dummy = match prepare_dummy(&itf, pool.clone(), &game).await {
+            Ok(x) => Some(x),
+            Err(e) => {
+                error!("Couldn't prepare the dummy opponent, agents won't get a validation game: {}", e);
+                None
+            }
+        };
+
         //Set all agents to not in_game
         Agent::update_many()
             .col_expr(agent::Column::InGame, SimpleExpr::Value(Value::Bool(Some(false))))
@@ -87,11 +124,13 @@ impl<T: Game + 'static> GameRunner<T> {
             itf,
             languages,
 
+            dummy,
+
             reporting: Reporter::new()
         }
     }
 
-    pub async fn add_player(&self, name: String, language: String, directory: String, source_file: Option<String>, owner_id: Option<i32>, partial: bool) -> Result<i32, DbErr> {
+    pub async fn add_player(&self, name: String, language: String, directory: String, source_file: Option<String>, owner_id: Option<i32>, partial: bool, variant: String, source_size: i64) -> Result<i32, DbErr> {
         let rgb = {
             let mut rand = rand::thread_rng();
             let hsl = Hsl::from(
@@ -109,6 +148,9 @@ impl<T: Game + 'static> GameRunner<T> {
             source_file: ActiveValue::Set(source_file),
             owner_id: ActiveValue::Set(owner_id),
             partial: ActiveValue::Set(partial),
+            variant: ActiveValue::Set(variant),
+            //Counted towards the owner's quota from the start, not once compiling is done
+            source_size: ActiveValue::Set(source_size),
             colour: ActiveValue::Set(rgb.to_css_hex_string().to_ascii_uppercase()),
             ..Default::default()
         };
@@ -121,6 +163,87 @@ impl<T: Game + 'static> GameRunner<T> {
         self.languages.iter().find(|(l, _)| l.id() == language).map(|x| &x.0)
     }
 
+    //Waits until there are num sandboxes free at once. Holding on to some of them while waiting could deadlock
+    async fn get_sandboxes(&self, num: usize) -> Vec<Object<IsolateSandbox>> {
+        loop {
+            let mut sandboxes = vec![];
+
+            for _ in 0..num {
+                match self.sandboxes.try_get() {
+                    Ok(sandbox) => sandboxes.push(sandbox),
+                    Err(_) => break
+                }
+            }
+
+            if sandboxes.len() == num {
+                return sandboxes;
+            }
+
+            drop(sandboxes);
+            async_std::task::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    //Plays a short game against dummy opponents, so that broken agents never make it into a real game.
+    //Returns a log of what went wrong otherwise
+    pub async fn validate_agent(&self, language: &Arc<dyn Language>, directory: &str, variant: &str) -> Result<(), String> {
+        let (dummy_language, dummy) = match &self.dummy {
+            Some(x) => x,
+            None => return Ok(())
+        };
+
+        let sandboxes = self.get_sandboxes(self.game.num_players()).await;
+        let dummy_dir = dummy.dir_as_string();
+
+        let mut agents = vec![];
+
+        for (i, mut sandbox) in sandboxes.into_iter().enumerate() {
+            sandbox.initialize().await;
+
+            let (language, directory, variant) = if i == 0 {
+                (language, directory, variant)
+            } else {
+                (dummy_language, dummy_dir.as_str(), "")
+            };
+
+            let limits = language.scale_limits(&self.game.limits());
+            let mut job = language.launch(directory, sandbox.as_ref(), &self.itf, &limits, variant);
+
+            job.add_post_exit(move |_| {
+                async_std::task::block_on(sandbox.cleanup());
+                drop(sandbox);
+            });
+
+            agents.push(job);
+        }
+
+        //Not announced to spectators
+        let reporter = Reporter::new().start_game(self.game.as_ref(), &[], &[]).await;
+        self.game.run(&mut agents, None, reporter).await;
+
+        let agent = &agents[0];
+        let faults = agent.faults();
+
+        if agent.get_error().is_none() && !faults.iter().any(|x| x.kind.is_fatal()) {
+            return Ok(());
+        }
+
+        let mut log = "Validation game failed\n".to_string();
+
+        for fault in faults {
+            log.push_str(&format!("{}\n", fault));
+        }
+
+        if let Some(err) = agent.get_error() {
+            log.push_str(&format!("Error: {}\n", err));
+        }
+
+        const MAX_READ: usize = 10 * 1024;
+        log.push_str(&format!("Stderr:\n{}", agent.read_stderr(Some(MAX_READ)).await));
+
+        Err(log)
+    }
+
     pub async fn run(&self) -> ! {
         loop {
             async_std::task::sleep(Duration::from_secs(1)).await;
@@ -173,13 +296,15 @@ impl<T: Game + 'static> GameRunner<T> {
 
             let mut agents = vec![];
             let mut ids = vec![];
+            let mut owners = vec![];
 
             for (mut sandbox, player) in sanboxes.into_iter().zip(players.iter()) {
                 sandbox.initialize().await;
                 let language = self.get_language(&player.language).unwrap();
 
                 //TODO: Free sandbox as soon as it can be freed?
-                let mut job = language.launch(&player.directory, sandbox.as_ref(), &self.itf);
+                let limits = language.scale_limits(&self.game.limits());
+                let mut job = language.launch(&player.directory, sandbox.as_ref(), &self.itf, &limits, &player.variant);
 
                 job.add_post_exit(move |_| {
                     async_std::task::block_on(sandbox.cleanup());
@@ -188,12 +313,14 @@ impl<T: Game + 'static> GameRunner<T> {
 
                 agents.push(job);
                 ids.push(player.id);
+                owners.push(player.owner_id);
             }
 
             let game_copy = self.game.clone();
             let db_copy = self.db.clone();
+            let policy = self.game.fault_policy();
 
-            let reporter = self.reporting.start_game(game_copy.as_ref(), &ids).await;
+            let reporter = self.reporting.start_game(game_copy.as_ref(), &ids, &owners).await;
 
             async_std::task::spawn(async move {
                 info!("Starting a game!");
@@ -202,6 +329,26 @@ impl<T: Game + 'static> GameRunner<T> {
                 let mut players: Vec<entities::agent::ActiveModel> = players.into_iter().map(|p| p.into()).collect();
 
                 for i in 0..agents.len() {
+                    let time_stats = agents[i].time_stats();
+                    players[i].num_moves = ActiveValue::Set(players[i].num_moves.get().unwrap() + time_stats.moves as i32);
+                    players[i].move_time = ActiveValue::Set(players[i].move_time.get().unwrap() + time_stats.total_s as f64);
+
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs() as i64).unwrap_or(0);
+                    let faults: Vec<_> = agents[i].faults().into_iter().map(|fault| fault::ActiveModel {
+                        agent_id: ActiveValue::Set(ids[i]),
+                        kind: ActiveValue::Set(fault.kind.as_str().to_string()),
+                        message: ActiveValue::Set(fault.message),
+                        forfeit: ActiveValue::Set(fault.forfeit),
+                        time: ActiveValue::Set(now),
+                        ..Default::default()
+                    }).collect();
+
+                    if !faults.is_empty() {
+                        if let Err(e) = Fault::insert_many(faults).exec(&db_copy).await {
+                            error!("Encountered error while saving faults! {}", e);
+                        }
+                    }
+
                     const MAX_READ: usize = 10 * 1024;
                     let stderr_contents = agents[i].read_stderr(Some(MAX_READ)).await;
 
@@ -221,9 +368,16 @@ impl<T: Game + 'static> GameRunner<T> {
                             error!("Encountered error while saving error! {}", e);
                         }
 
-                        players[i].removed = ActiveValue::Set(true);
+                        let failed_games = players[i].failed_games.get().unwrap() + 1;
+                        players[i].failed_games = ActiveValue::Set(failed_games);
+
+                        if failed_games as u32 >= policy.max_failed_games {
+                            players[i].removed = ActiveValue::Set(true);
 
-                        warn!("Player {} removed.\n{}", players[i].name.get().unwrap(), displayed_error);
+                            warn!("Player {} removed.\n{}", players[i].name.get().unwrap(), displayed_error);
+                        } else {
+                            warn!("Player {} forfeited a game.\n{}", players[i].name.get().unwrap(), displayed_error);
+                        }
                     } else {
                         if let Err(e) = async_std::fs::write(stderr_store, stderr_contents).await {
                             error!("Encountered error while saving stderr! {}", e);
@@ -320,3 +474,11 @@ impl<T: Game + 'static> GameRunner<T> {
         Ok(Agent::find_by_id(id).one(&self.db).await?.map(|x| x.rating as i32))
     }
 }
+
+impl<T: Game + 'static> Drop for GameRunner<T> {
+    fn drop(&mut self) {
+        if self.dummy.is_some() {
+            std::fs::remove_dir_all(DUMMY_DIR).unwrap_or(());
+        }
+    }
+}
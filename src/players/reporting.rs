@@ -7,9 +7,12 @@ use serde_json::{Value, json};
 
 use crate::games::Game;
 
+//Longer log messages from agents are cut short
+const MAX_LOG_LEN: usize = 1000;
+
 #[async_trait]
 pub trait StartCallback: Sync + Send {
-    async fn call(&mut self, id: usize, name: &str, players: &[i32]);
+    async fn call(&mut self, id: usize, name: &str, players: &[i32], owners: &[Option<i32>]);
 }
 
 #[async_trait]
@@ -22,10 +25,17 @@ pub trait UpdateCallback: Sync + Send  {
     async fn call(&mut self, id: usize, data: &Value);
 }
 
+//Messages an agent sends about itself, only meant for its owner
+#[async_trait]
+pub trait LogCallback: Sync + Send  {
+    async fn call(&mut self, id: usize, player: usize, message: &str);
+}
+
 struct CallbackHandler {
     on_start_game: Vec<Box<dyn StartCallback>>,
     on_end_game: Vec<Box<dyn EndCallback>>,
-    on_update: Vec<Box<dyn UpdateCallback>>
+    on_update: Vec<Box<dyn UpdateCallback>>,
+    on_log: Vec<Box<dyn LogCallback>>
 }
 
 impl CallbackHandler {
@@ -33,7 +43,8 @@ impl CallbackHandler {
         Self {
             on_start_game: vec![],
             on_end_game: vec![],
-            on_update: vec![]
+            on_update: vec![],
+            on_log: vec![]
         }
     }
 
@@ -49,9 +60,13 @@ impl CallbackHandler {
         self.on_update.push(callback);
     }
 
-    pub async fn start_game(&mut self, id: usize, name: &str, players: &[i32]) {
+    pub fn add_log_callback(&mut self, callback: Box<dyn LogCallback>) {
+        self.on_log.push(callback);
+    }
+
+    pub async fn start_game(&mut self, id: usize, name: &str, players: &[i32], owners: &[Option<i32>]) {
         for callback in &mut self.on_start_game {
-            callback.call(id, name, players).await;
+            callback.call(id, name, players, owners).await;
         }
     }
 
@@ -66,6 +81,12 @@ impl CallbackHandler {
             callback.call(id, data).await;
         }
     }
+
+    pub async fn log(&mut self, id: usize, player: usize, message: &str) {
+        for callback in &mut self.on_log {
+            callback.call(id, player, message).await;
+        }
+    }
 }
 
 pub struct GameReporter {
@@ -94,6 +115,29 @@ impl GameReporter {
 
         self.callbacks.lock().await.update_game(self.id, &val).await;
     }
+
+    //For the agent's callbacks, which can't borrow the reporter while the game uses it
+    pub fn agent_logger(&self, player: usize) -> AgentLogger {
+        AgentLogger {
+            callbacks: self.callbacks.clone(),
+            id: self.id,
+            player
+        }
+    }
+}
+
+pub struct AgentLogger {
+    callbacks: Arc<Mutex<CallbackHandler>>,
+    id: usize,
+    player: usize
+}
+
+impl AgentLogger {
+    pub async fn log(&mut self, message: &str) {
+        let message: String = message.chars().take(MAX_LOG_LEN).collect();
+
+        self.callbacks.lock().await.log(self.id, self.player, &message).await;
+    }
 }
 
 impl Drop for GameReporter {
@@ -119,10 +163,10 @@ impl Reporter {
         }
     }
 
-    pub async fn start_game<GameType: Game>(&self, game: &GameType, players: &[i32]) -> GameReporter {
+    pub async fn start_game<GameType: Game>(&self, game: &GameType, players: &[i32], owners: &[Option<i32>]) -> GameReporter {
         let id = self.id_counter.fetch_add(1, Ordering::AcqRel);
 
-        self.callbacks.lock().await.start_game(id, game.name(), players).await;
+        self.callbacks.lock().await.start_game(id, game.name(), players, owners).await;
 
         GameReporter::new(self.callbacks.clone(), id)
     }
@@ -138,4 +182,8 @@ impl Reporter {
     pub async fn add_update_callback(&self, callback: Box<dyn UpdateCallback>) {
         self.callbacks.lock().await.add_update_callback(callback);
     }
+
+    pub async fn add_log_callback(&self, callback: Box<dyn LogCallback>) {
+        self.callbacks.lock().await.add_log_callback(callback);
+    }
 }
\ No newline at end of file
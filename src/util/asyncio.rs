@@ -21,6 +21,10 @@ impl<R: async_std::io::Read + Unpin> AsyncReaderWrapper<R> {
         while offset < buf.len() {
             let read = self.read(&mut buf[offset..]).await?;
 
+            if read == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
             offset += read;
         }
 
@@ -56,12 +60,21 @@ impl<W: async_std::io::Write + Unpin> AsyncWriterWrapper<W> {
         while offset < buf.len() {
             let written = self.write(&buf[offset..]).await?;
 
+            if written == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+
             offset += written;
         }
 
         Ok(())
     }
 
+    //TLS streams hold on to what's written until they're flushed
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        poll_fn(|cx| Pin::new(&mut self.writer).poll_flush(cx)).await
+    }
+
     fn poll_write(&mut self, cx: &mut std::task::Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
         let pin = Pin::new(&mut self.writer);
 
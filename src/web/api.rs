@@ -3,16 +3,18 @@ use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 use async_std::{net::{TcpListener, TcpStream},path::Path, sync::Mutex,};
 use futures::AsyncReadExt;
 use log::{info, error, warn, debug};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use deadpool::unmanaged::Pool;
 use rand::Rng;
-use sea_orm::{DatabaseConnection, EntityTrait, ModelTrait, ActiveValue, ActiveModelTrait, QueryFilter, ColumnTrait, QueryOrder};
+use sea_orm::{DatabaseConnection, EntityTrait, ModelTrait, ActiveValue, ActiveModelTrait, QueryFilter, ColumnTrait, QueryOrder, QuerySelect, TransactionTrait};
 use serde_json::{json, Value, Map};
 
 use crate::{
     games::Game,
-    web::{http::{Method, Request, Response, Status}, web_errors::WebError}, langs::{language::{Language, PreparedProgram}, get_all_languages}, entities::{self, user, agent}, util::{temp_file::random_file, RUN_DIR}, players::auto_exec::GameRunner, cleanup_files,
+    web::{http::{HttpError, HttpLimits, HttpReader, Method, Request, Response, Status}, config::ServerConfig, rate_limit::{Client, RateLimiter, RouteGroup}, tls::{acceptor, https_redirect, reload_on_sighup, CertResolver, Connection}, web_errors::WebError}, langs::{language::{Language, PreparedProgram}, get_all_languages, submission::Submission}, entities::{self, user, agent, api_token, fault, session, invite}, util::{temp_file::random_file, RUN_DIR}, players::auto_exec::GameRunner, cleanup_files,
 };
 
-use super::{profile::{generate_password, get_num_agents}, web_errors::{HttpResult, decode_utf8, ValueCast, parse_json_as_object, HttpErrorMap}, game_reporter::SharedInner};
+use super::{api_token::{bearer_token, create_token, find_token, token_json, Scope, Scopes, MAX_TOKEN_NAME_LEN}, api_v2, invite::{create_invites, invite_json, invites_csv, normalise_code, parse_usernames, redeem_invite, validate_username, MAX_INVITES_PER_REQUEST, MAX_USERNAME_LEN, MIN_PASSWORD_LEN}, session::{clear_session_cookie, create_session, find_session, remove_expired_sessions, revoke_user_sessions, session_cookie}, role::Role, profile::{generate_password, hash_password, verify_password, get_num_agents, get_storage_used, STORAGE_QUOTA}, web_errors::{HttpResult, decode_utf8, ValueCast, parse_json_as_object, HttpErrorMap}, websocket, game_reporter::{spectate_websocket, SharedInner}};
 
 trait IgnoreResult {
     fn ignore(self);
@@ -22,6 +24,9 @@ impl<T, E> IgnoreResult for Result<T, E> {
     fn ignore(self) {}
 }
 
+//Each compile holds a sandbox, so too many at once would starve the games
+const MAX_CONCURRENT_COMPILES: usize = 4;
+
 #[derive(Clone)]
 pub struct PageInfo {
     title: String,
@@ -92,16 +97,19 @@ impl PageEngine {
 
 #[derive(Clone)]
 pub struct AppState {
-    executor: Arc<GameRunner<Box<dyn Game>>>,
-    super_secret_admin_password: String,
-    languages: Arc<Vec<Arc<dyn Language>>>,
+    pub(super) executor: Arc<GameRunner<Box<dyn Game>>>,
+    pub(super) languages: Arc<Vec<Arc<dyn Language>>>,
     reporter: Arc<Mutex<SharedInner>>,
-    db: DatabaseConnection,
+    pub(super) db: DatabaseConnection,
 
     page_engine: PageEngine,
+    http_limits: HttpLimits,
+    rate_limiter: Arc<RateLimiter>,
+    //Compiles waiting on a slot stay partial until they get one
+    compile_slots: Pool<()>,
 }
 
-async fn get_agent_leaderboard(state: AppState) -> HttpResult<String> {
+pub(super) async fn get_agent_leaderboard(db: &DatabaseConnection) -> HttpResult<Value> {
     let mut json = Vec::new();
 
     let data = entities::prelude::Agent::find()
@@ -109,7 +117,7 @@ async fn get_agent_leaderboard(state: AppState) -> HttpResult<String> {
         .filter(agent::Column::Partial.eq(false))
         .order_by_desc(agent::Column::Rating)
         .find_also_related(user::Entity)
-        .all(&state.db).await?;
+        .all(db).await?;
 
 
     for (agent, maybe_owner) in data {
@@ -129,7 +137,31 @@ async fn get_agent_leaderboard(state: AppState) -> HttpResult<String> {
         json.push(val);
     }
 
-    Ok(serde_json::to_string(&json)?)
+    Ok(Value::Array(json))
+}
+
+pub(super) fn get_languages_json(languages: &[Arc<dyn Language>]) -> Value {
+    let values: Vec<_> = languages.iter().map(|l| {
+        let variants: Vec<_> = l.variants().iter().map(|v| json!({
+            "id": v.id,
+            "name": v.name
+        })).collect();
+
+        json!({
+            "name": l.name(),
+            "id": l.id(),
+            "variants": variants
+        })
+    }).collect();
+
+    Value::Array(values)
+}
+
+pub(super) fn get_game_json(state: &AppState) -> Value {
+    json!({
+        "name": state.executor.game.name(),
+        "num_players": state.executor.game.num_players()
+    })
 }
 
 async fn get_all_profiles(state: AppState) -> HttpResult<String> {
@@ -139,7 +171,7 @@ async fn get_all_profiles(state: AppState) -> HttpResult<String> {
         let val = json!({
             "id": profile.id,
             "username": profile.username,
-            "password": profile.password,
+            "role": Role::of(&profile.role).as_str(),
 
             "num_agents_allowed": profile.num_agents_allowed
         });
@@ -226,38 +258,233 @@ async fn serve_file_to(path: &str) -> HttpResult<Response> {
     Ok(response)
 }
 
-fn authenticate_admin(req: &Request, state: &AppState) -> bool {
-    if let Some(auth) = req.cookies.get("admin") {
-        if *auth == state.super_secret_admin_password {
-            return true;
+//Who a request comes from, according to its session cookie or API token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub user_id: Option<i32>,
+    //None when not logged in
+    pub role: Option<Role>,
+    //Only set for API tokens, sessions can do everything their role allows
+    pub scopes: Option<Scopes>
+}
+
+impl Caller {
+    pub async fn from_request(req: &Request, db: &DatabaseConnection) -> HttpResult<Self> {
+        let mut scopes = None;
+
+        let user_id = match find_session(req, db).await? {
+            Some(session) => session.user_id,
+            None => match bearer_token(req) {
+                //A bad token is refused rather than treated as logged out, so scripts notice
+                Some(token) => match find_token(token, db).await? {
+                    Some(token) => {
+                        scopes = Some(Scopes::parse(&token.scopes));
+                        Some(token.user_id)
+                    },
+                    None => return Err(WebError::Unauthorized)
+                },
+                None => None
+            }
+        };
+
+        let user = match user_id {
+            Some(user_id) => user::Entity::find_by_id(user_id).one(db).await?,
+            None => None
+        };
+
+        Ok(Self {
+            user_id: user.as_ref().map(|x| x.id),
+            role: user.map(|x| Role::of(&x.role)),
+            scopes
+        })
+    }
+
+    pub fn is_user(&self, user_id: i32) -> bool {
+        self.user_id == Some(user_id)
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.role.is_some_and(|x| x >= role)
+    }
+
+    //Users can act on their own things, organisers and admins on those of the users they manage
+    pub fn can_manage(&self, owner: Option<&user::Model>) -> bool {
+        match owner {
+            Some(owner) => self.is_user(owner.id) || self.role.is_some_and(|x| x.can_manage(Role::of(&owner.role))),
+            //Agents without an owner were added by the server
+            None => self.role.is_some_and(|x| x.can_manage(Role::Contestant))
+        }
+    }
+
+    pub fn require_user(&self) -> HttpResult<()> {
+        if self.user_id.is_none() {
+            return Err(WebError::Unauthorized);
+        }
+
+        Ok(())
+    }
+
+    pub fn require_role(&self, role: Role) -> HttpResult<()> {
+        self.require_user()?;
+
+        if !self.has_role(role) {
+            return Err(WebError::Forbidden(format!("This needs the {} role", role)));
+        }
+
+        Ok(())
+    }
+
+    pub fn require_manage(&self, owner: Option<&user::Model>, what: &str) -> HttpResult<()> {
+        self.require_user()?;
+
+        if !self.can_manage(owner) {
+            return Err(WebError::Forbidden(format!("You can't change this {}", what)));
+        }
+
+        Ok(())
+    }
+
+    pub fn require_scope(&self, scope: Scope) -> HttpResult<()> {
+        match self.scopes {
+            Some(scopes) if !scopes.contains(scope) => Err(WebError::Forbidden(format!("This API token doesn't have the {} scope", scope))),
+            _ => Ok(())
         }
     }
 
-    false
+    //For everything API tokens aren't meant for, like accounts and administration
+    pub fn require_session(&self) -> HttpResult<()> {
+        match self.scopes {
+            Some(_) => Err(WebError::Forbidden("API tokens can't be used for this".to_string())),
+            None => Ok(())
+        }
+    }
 }
 
-fn is_user_authenticated(req: &Request, profile: &entities::user::Model) -> bool {
-    let user_id = match req.cookies.get("id") {
-        Some(id) => {
-            match id.parse::<i32>() {
-                Ok(id) => Some(id),
-                Err(_) => None
+pub(super) async fn find_user_by_id(id: i32, db: &DatabaseConnection) -> HttpResult<user::Model> {
+    match user::Entity::find_by_id(id).one(db).await? {
+        Some(user) => Ok(user),
+        None => Err(WebError::NotFound("User id not found".to_string()))
+    }
+}
+
+//Spectating doesn't need a login, a bad session or token just means watching anonymously
+async fn spectator_id(req: &Request, state: &AppState) -> Option<i32> {
+    Caller::from_request(req, &state.db).await.ok().and_then(|x| x.user_id)
+}
+
+async fn find_owner(owner_id: Option<i32>, db: &DatabaseConnection) -> HttpResult<Option<user::Model>> {
+    match owner_id {
+        Some(id) => Ok(user::Entity::find_by_id(id).one(db).await?),
+        None => Ok(None)
+    }
+}
+
+fn parse_role(role: &str) -> HttpResult<Role> {
+    Role::parse(role).ok_or(WebError::InvalidData(format!("Unknown role {}", role)))
+}
+
+//Checks the caller may use a POST route before it runs. Every route that changes something has to
+//be listed here, anything else is refused
+async fn authorize_post(req: &Request, caller: &Caller, db: &DatabaseConnection) -> HttpResult<()> {
+    let path: Vec<_> = req.path.path.iter().map(|x| x.as_str()).collect();
+
+    //API tokens only get to the agent routes their scopes cover
+    match path.as_slice() {
+        ["api", "add_agent" | "set_colour"] => caller.require_scope(Scope::Submit)?,
+        ["api", "delete_agent"] => caller.require_scope(Scope::Delete)?,
+        _ => caller.require_session()?
+    }
+
+    match path.as_slice() {
+        ["admin", "full_reset" | "agents_reset" | "ratings_reset"] => caller.require_role(Role::Admin),
+        ["admin", "file_cleanup"] => caller.require_role(Role::Organiser),
+        //Invites only ever make contestants, which every organiser can manage
+        ["admin", "create_invites" | "import_invites" | "delete_invite"] => caller.require_role(Role::Organiser),
+        ["admin", "new_profile"] => {
+            caller.require_role(Role::Organiser)?;
+
+            let role = match req.path.query.get("role") {
+                Some(role) => parse_role(role)?,
+                None => Role::Contestant
+            };
+
+            if !caller.role.is_some_and(|x| x.can_manage(role)) {
+                return Err(WebError::Forbidden(format!("You can't create {} accounts", role)));
             }
-        }
-        None => None
-    };
 
-    let user_password = req.cookies.get("password");
+            Ok(())
+        },
+        ["admin", route @ ("delete_profile" | "set_profile_agents" | "revoke_sessions" | "set_role")] => {
+            caller.require_role(Role::Organiser)?;
+
+            let user = find_user_by_id(req.path.parse_query("id")?, db).await?;
+            caller.require_manage(Some(&user), "profile")?;
+
+            if *route == "set_role" {
+                let role = parse_role(req.path.get("role")?)?;
+
+                if caller.is_user(user.id) || !caller.role.is_some_and(|x| x.can_manage(role)) {
+                    return Err(WebError::Forbidden(format!("You can't make this profile {}", role)));
+                }
+            }
+
+            Ok(())
+        },
+        ["admin", ..] => caller.require_role(Role::Admin),
+
+        ["api", "login" | "logout" | "register"] => Ok(()),
+
+        ["api", "revoke_all_sessions" | "create_token"] => caller.require_user(),
+        ["api", "revoke_token"] => authorize_token(caller, req.path.parse_query("token")?, db).await,
+        ["api", "revoke_session"] => authorize_session(caller, req.path.parse_query("session")?, db).await,
+
+        ["api", "reset_password"] => {
+            let user = find_user_by_id(req.path.parse_query("id")?, db).await?;
+            caller.require_manage(Some(&user), "profile")
+        },
+
+        ["api", "add_agent"] => authorize_submit(caller, req.path.parse_query("id")?, db).await,
+        ["api", "set_colour" | "delete_agent"] => authorize_agent(caller, req.path.parse_query("agent")?, db).await,
+
+        _ => Err(WebError::NotFound("Route not found".to_string()))
+    }
+}
+
+//The checks below are shared with the v2 routes
 
-    if user_id == None || user_password == None {
-        return false;
+pub(super) async fn authorize_token(caller: &Caller, token_id: i32, db: &DatabaseConnection) -> HttpResult<()> {
+    match api_token::Entity::find_by_id(token_id).one(db).await? {
+        Some(token) => caller.require_manage(find_owner(Some(token.user_id), db).await?.as_ref(), "API token"),
+        None => Err(WebError::NotFound("API token not found".to_string()))
     }
+}
+
+pub(super) async fn authorize_session(caller: &Caller, session_id: i32, db: &DatabaseConnection) -> HttpResult<()> {
+    match session::Entity::find_by_id(session_id).one(db).await? {
+        Some(session) => caller.require_manage(find_owner(session.user_id, db).await?.as_ref(), "session"),
+        None => Err(WebError::NotFound("Session not found".to_string()))
+    }
+}
 
-    if profile.id != user_id.unwrap() || profile.password != *user_password.unwrap() {
-        return false;
+//Spectators only get to look
+pub(super) async fn authorize_submit(caller: &Caller, user_id: i32, db: &DatabaseConnection) -> HttpResult<()> {
+    let user = find_user_by_id(user_id, db).await?;
+    caller.require_manage(Some(&user), "profile")?;
+
+    if Role::of(&user.role) < Role::Contestant {
+        return Err(WebError::Forbidden("Spectators can't submit agents".to_string()));
     }
 
-    true
+    Ok(())
+}
+
+pub(super) async fn authorize_agent(caller: &Caller, agent_id: i32, db: &DatabaseConnection) -> HttpResult<()> {
+    caller.require_role(Role::Contestant)?;
+
+    match agent::Entity::find_by_id(agent_id).one(db).await? {
+        Some(agent) => caller.require_manage(find_owner(agent.owner_id, db).await?.as_ref(), "agent"),
+        None => Err(WebError::NotFound("Agent not found".to_string()))
+    }
 }
 
 async fn get_user(req: &Request, state: &AppState) -> HttpResult<Option<entities::user::Model>> {
@@ -268,17 +495,58 @@ async fn get_user(req: &Request, state: &AppState) -> HttpResult<Option<entities
     Ok(user)
 }
 
+//Either a single "src", a "files" object of path to content or a base64 zip/tar "archive".
+//The last two also need the path of the "entry" point
+fn parse_submission(data: &Map<String, Value>) -> HttpResult<Submission> {
+    if let Some(files) = data.get("files") {
+        let entry = data.try_get("entry")?.try_as_str()?;
+
+        let files = files.try_as_object()?.iter()
+            .map(|(path, content)| Ok((path.clone(), content.try_as_str()?.as_bytes().to_vec())))
+            .collect::<HttpResult<Vec<_>>>()?;
+
+        Submission::from_files(files, entry).map_err(WebError::InvalidData)
+    } else if let Some(archive) = data.get("archive") {
+        let entry = data.try_get("entry")?.try_as_str()?;
+
+        let archive = BASE64_STANDARD.decode(archive.try_as_str()?)
+            .map_err(|e| WebError::InvalidData(format!("Archive isn't valid base64: {}", e)))?;
+
+        let format = match data.get("format") {
+            Some(format) => format.try_as_str()?,
+            None => "zip"
+        };
+
+        match format {
+            "zip" => Submission::from_zip(&archive, entry),
+            "tar" => Submission::from_tar(&archive, entry),
+            other => Err(format!("Unknown archive format {}", other))
+        }.map_err(WebError::InvalidData)
+    } else {
+        let src = data.try_get("src")?.try_as_str()?.to_string();
+
+        if src.len() > 30000 {
+            return Err(WebError::InvalidData(format!("Source code too long!")));
+        }
+
+        Ok(Submission::single(src))
+    }
+}
+
 async fn get_agent_data_as_json(agent: &agent::Model, include_error: bool, include_src: bool, db: &DatabaseConnection) -> HttpResult<serde_json::Value> {
     let mut data = json!({
         "id": agent.id,
         "name": agent.name,
         "language": agent.language,
+        "variant": agent.variant,
         "rating": agent.rating,
         "games_played": agent.num_games,
         "in_game": agent.in_game,
         "removed": agent.removed,
         "partial": agent.partial,
-        "colour": agent.colour
+        "colour": agent.colour,
+        "average_move_time": if agent.num_moves > 0 { agent.move_time / agent.num_moves as f64 } else { 0.0 },
+        "failed_games": agent.failed_games
     });
 
     if include_error {
@@ -290,6 +558,22 @@ async fn get_agent_data_as_json(agent: &agent::Model, include_error: bool, inclu
                 data.as_object_mut().unwrap().insert("error".to_string(), Value::String(error));
             }
         }
+
+        const MAX_FAULTS: u64 = 50;
+        let faults = fault::Entity::find()
+            .filter(fault::Column::AgentId.eq(agent.id))
+            .order_by_desc(fault::Column::Id)
+            .limit(MAX_FAULTS)
+            .all(db).await?;
+
+        let faults: Vec<_> = faults.into_iter().map(|x| json!({
+            "kind": x.kind,
+            "message": x.message,
+            "forfeit": x.forfeit,
+            "time": x.time
+        })).collect();
+
+        data.as_object_mut().unwrap().insert("faults".to_string(), json!(faults));
     }
 
     if include_src {
@@ -313,7 +597,7 @@ async fn get_agent_data_as_json(agent: &agent::Model, include_error: bool, inclu
     Ok(data)
 }
 
-async fn get_profile_data(req: &Request, state: &AppState) -> HttpResult<Response> {
+async fn get_profile_data(req: &Request, state: &AppState, caller: &Caller) -> HttpResult<Response> {
     let id = match req.path.query.get("id") {
         Some(id) => {
             match id.parse::<i32>() {
@@ -347,39 +631,145 @@ async fn get_profile_data(req: &Request, state: &AppState) -> HttpResult<Respons
     }
     let profile = profile.unwrap();
 
-    let logged = is_user_authenticated(req, &profile);
-    let authenticated = authenticate_admin(req, state) || logged;
+    let data = get_profile_json(&profile, caller, &state.db).await?;
+
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+    res.set_header("Content-Type", "application/json");
+    res.set_body(serde_json::to_string(&data)?.into_bytes());
+
+    Ok(res)
+}
 
-    let mut data = HashMap::new();
+//Agents and limits are only shown to whoever can manage the profile
+pub(super) async fn get_profile_json(profile: &user::Model, caller: &Caller, db: &DatabaseConnection) -> HttpResult<Value> {
+    let logged = caller.is_user(profile.id);
+    let authenticated = caller.can_manage(Some(profile));
 
-    data.insert("id", json!(profile.id));
-    data.insert("username", json!(profile.username));
-    data.insert("logged_in", json!(logged));
-    data.insert("privileged", json!(authenticated));
+    let mut data = Map::new();
+
+    data.insert("id".to_string(), json!(profile.id));
+    data.insert("username".to_string(), json!(profile.username));
+    data.insert("role".to_string(), json!(Role::of(&profile.role).as_str()));
+    data.insert("logged_in".to_string(), json!(logged));
+    data.insert("privileged".to_string(), json!(authenticated));
 
     if authenticated {
-        data.insert("max_agents", json!(profile.num_agents_allowed));
+        data.insert("max_agents".to_string(), json!(profile.num_agents_allowed));
 
         let mut agents = Vec::new();
 
-        let related = profile.find_related(entities::prelude::Agent).all(&state.db).await?;
+        let related = profile.find_related(entities::prelude::Agent).all(db).await?;
         
         for agent in related {
-            agents.push(get_agent_data_as_json(&agent, false, false, &state.db).await?);
+            agents.push(get_agent_data_as_json(&agent, false, false, db).await?);
         }
 
-        data.insert("agents", json!(agents));
+        data.insert("agents".to_string(), json!(agents));
     }
 
-    let mut res = Response::new();
-    res.set_status(Status::Ok);
-    res.set_header("Content-Type", "application/json");
-    res.set_body(serde_json::to_string(&data)?.into_bytes());
+    Ok(Value::Object(data))
+}
 
-    Ok(res)
+pub(super) async fn get_stats_json(profile: &user::Model, db: &DatabaseConnection) -> HttpResult<Value> {
+    let mut data = Map::new();
+
+    let agents = profile.find_related(agent::Entity).all(db).await?;
+    let best_rating = agents.iter().map(|x| x.rating).max_by(|a, b| a.total_cmp(b)).unwrap_or(0.0);
+
+    let active_agents = agents.iter().filter(|x| !x.partial && !x.removed).count();
+
+    let total_games_played: i32 = agents.iter().map(|x| x.num_games).sum();
+
+    data.insert("best_rating".to_string(), json!(best_rating as i32));
+    data.insert("active_agents".to_string(), json!(active_agents));
+    data.insert("total_games".to_string(), json!(total_games_played));
+
+    Ok(Value::Object(data))
+}
+
+//Error logs and source are hidden from anyone who can't manage the owner
+pub(super) async fn get_agent_json(agent_id: i32, mut send_error: bool, mut send_src: bool, caller: &Caller, db: &DatabaseConnection) -> HttpResult<Value> {
+    let agent = agent::Entity::find_by_id(agent_id).one(db).await?;
+
+    if agent.is_none() {
+        return Err(WebError::NotFound("Agent not found".to_string()));
+    }
+    let agent: agent::Model = agent.unwrap();
+
+    let owner = find_owner(agent.owner_id, db).await?;
+    if owner.is_some() && !caller.can_manage(owner.as_ref()) {
+        send_error = false;
+        send_src = false;
+    }
+
+    get_agent_data_as_json(&agent, send_error, send_src, db).await
+}
+
+pub(super) async fn get_tokens_json(user_id: i32, db: &DatabaseConnection) -> HttpResult<Value> {
+    let tokens = api_token::Entity::find()
+        .filter(api_token::Column::UserId.eq(user_id))
+        .order_by_desc(api_token::Column::Created)
+        .all(db)
+        .await?;
+
+    Ok(Value::Array(tokens.iter().map(token_json).collect()))
+}
+
+//Takes {"name", "scopes": [...]}, the token is only ever shown in this response
+pub(super) async fn new_token(data: &Map<String, Value>, user_id: i32, db: &DatabaseConnection) -> HttpResult<Value> {
+    let name = data.try_get("name")?.try_as_str()?.trim();
+
+    if name.is_empty() || name.len() > MAX_TOKEN_NAME_LEN {
+        return Err(WebError::InvalidData(format!("Token names need between 1 and {} characters", MAX_TOKEN_NAME_LEN)));
+    }
+
+    let scopes = data.try_get("scopes")?.try_as_array()?.iter()
+        .map(|x| x.try_as_str())
+        .collect::<HttpResult<Vec<_>>>()?;
+    let scopes = Scopes::from_names(scopes).map_err(WebError::InvalidData)?;
+
+    let (model, token) = create_token(db, user_id, name, scopes).await?;
+    info!("Created API token {} for user {}", model.prefix, user_id);
+
+    let mut data = token_json(&model);
+    data["token"] = json!(token);
+
+    Ok(data)
+}
+
+pub(super) async fn get_sessions_json(current: &session::Model, db: &DatabaseConnection) -> HttpResult<Value> {
+    let sessions = session::Entity::find()
+        .filter(session::Column::UserId.eq(current.user_id))
+        .order_by_desc(session::Column::Created)
+        .all(db)
+        .await?;
+
+    let sessions: Vec<_> = sessions.iter().map(|x| json!({
+        "id": x.id,
+        "created": x.created,
+        "expires": x.expires,
+        "current": x.id == current.id
+    })).collect();
+
+    Ok(Value::Array(sessions))
+}
+
+//Pages and files are public, API tokens need the read scope and can't use the rest
+fn authorize_get(req: &Request, caller: &Caller) -> HttpResult<()> {
+    let path: Vec<_> = req.path.path.iter().map(|x| x.as_str()).collect();
+
+    match path.as_slice() {
+        ["admin", ..] | ["api", "session" | "sessions" | "tokens"] => caller.require_session(),
+        ["api", ..] => caller.require_scope(Scope::Read),
+        _ => Ok(())
+    }
 }
 
 async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResult<Response> {
+    let caller = Caller::from_request(&req, &state.db).await?;
+    authorize_get(&req, &caller)?;
+
     if req.matches_path_exact(&[]) {
         let mut res = Response::new();
         res.set_status(Status::PermanentRedirect);
@@ -402,12 +792,16 @@ async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResu
             }
         }
     } else if req.matches_path(&["admin"]) {
-        if !authenticate_admin(&req, &state) {
-            Err(WebError::Unauthorized)
-        } else if req.matches_path_exact(&["admin", "verify"]) {
+        caller.require_role(Role::Organiser)?;
+
+        if req.matches_path_exact(&["admin", "verify"]) {
             let mut res = Response::new();
             res.set_status(Status::Ok);
-            
+            res.set_header("Content-Type", "application/json");
+            res.set_body(json!({
+                "role": caller.role.map(|x| x.as_str())
+            }).to_string().into_bytes());
+
             Ok(res)
         } else if req.matches_path_exact(&["admin", "profiles"]) {
             let mut res = Response::new();
@@ -415,6 +809,21 @@ async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResu
             res.set_header("Content-Type", "application/json");
             res.set_body(get_all_profiles(state).await?.into_bytes());
 
+            Ok(res)
+        } else if req.matches_path_exact(&["admin", "invites"]) {
+            let invites = invite::Entity::find().all(&state.db).await?;
+
+            invites_response(&invites, &state.db).await
+        } else if req.matches_path_exact(&["admin", "invites.csv"]) {
+            let users = get_users_by_id(&state.db).await?;
+            let invites = invite::Entity::find().all(&state.db).await?;
+
+            let mut res = Response::new();
+            res.set_status(Status::Ok);
+            res.set_header("Content-Type", "text/csv");
+            res.set_header("Content-Disposition", "attachment; filename=\"invites.csv\"");
+            res.set_body(invites_csv(&invites, &users).into_bytes());
+
             Ok(res)
         } else {
             Err(WebError::NotFound("Unknown admin route".to_string()))
@@ -427,109 +836,68 @@ async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResu
         let mut res = Response::new();
         res.set_status(Status::Ok);
         res.set_header("Content-Type", "application/json");
-        res.set_body(get_agent_leaderboard(state).await?.into_bytes());
+        res.set_body(get_agent_leaderboard(&state.db).await?.to_string().into_bytes());
 
         Ok(res)
     } else if req.matches_path_exact(&["api", "profile"]) {
-        get_profile_data(&req, &state).await
+        get_profile_data(&req, &state, &caller).await
     } else if req.matches_path_exact(&["api", "game"]) {
+        let mut res = Response::new();
+        res.set_status(Status::Ok);
+        res.set_header("Content-Type", "application/json");
+        res.set_body(get_game_json(&state).to_string().into_bytes());
+
+        Ok(res)
+    } else if req.matches_path_exact(&["api", "session"]) {
         let mut res = Response::new();
         res.set_status(Status::Ok);
         res.set_header("Content-Type", "application/json");
         res.set_body(json!({
-            "name": state.executor.game.name(),
-            "num_players": state.executor.game.num_players()
+            "logged_in": caller.user_id.is_some(),
+            "id": caller.user_id,
+            "role": caller.role.map(|x| x.as_str())
         }).to_string().into_bytes());
 
         Ok(res)
-    } else if req.matches_path_exact(&["api", "auth"]) {
-        //TODO: Extract this profile finding logic into a function
-        let id = match req.path.query.get("id") {
-            Some(id) => {
-                match id.parse::<i32>() {
-                    Ok(id) => Some(id),
-                    Err(_) => None
-                }
-            }
-            None => None
+    } else if req.matches_path_exact(&["api", "sessions"]) {
+        let current = match find_session(&req, &state.db).await? {
+            Some(x) => x,
+            None => return Err(WebError::Unauthorized)
         };
-    
-        let username = req.path.query.get("username");
-    
-        if username.is_none() && id.is_none() {
-            return Err(WebError::InvalidData("Could not find desired user (Missing parameters)".to_string()));
-        }
-    
-        let mut query = user::Entity::find();
-    
-        if let Some(id) = id {
-            query = query.filter(user::Column::Id.eq(id));
-        }
-    
-        if let Some(username) = username {
-            query = query.filter(user::Column::Username.eq(username));
-        }
-    
-        let profile = query.one(&state.db).await?;
-
-        if profile.is_none() {
-            return Err(WebError::NotFound("Couldn't find user matching id or username".to_string()));
-        }
-        let profile = profile.unwrap();
 
-        let password = req.path.get("password")?;
         let mut res = Response::new();
         res.set_status(Status::Ok);
-
         res.set_header("Content-Type", "application/json");
+        res.set_body(get_sessions_json(&current, &state.db).await?.to_string().into_bytes());
 
-        let correct = profile.password == *password;
+        Ok(res)
+    } else if req.matches_path_exact(&["api", "tokens"]) {
+        let user_id = match caller.user_id {
+            Some(x) => x,
+            None => return Err(WebError::Unauthorized)
+        };
 
-        res.set_body(json!({
-            "correct": correct
-        }).to_string().into_bytes());
+        let mut res = Response::new();
+        res.set_status(Status::Ok);
+        res.set_header("Content-Type", "application/json");
+        res.set_body(get_tokens_json(user_id, &state.db).await?.to_string().into_bytes());
 
         Ok(res)
     } else if req.matches_path_exact(&["api", "lang"]) {
-        let values: Vec<_> = state.languages.iter().map(|l| {
-            json!({
-                "name": l.name(),
-                "id": l.id()
-            })
-        }).collect();
-
-        let arr = Value::Array(values);
-
         let mut res = Response::new();
         res.set_status(Status::Ok);
         res.set_header("Content-Type", "application/json");
-        res.set_body(serde_json::to_string(&arr)?.into_bytes());
+        res.set_body(get_languages_json(&state.languages).to_string().into_bytes());
 
         Ok(res)
     } else if req.matches_path_exact(&["api", "agent"]) {
         info!("Querying agent!");
         let agent_id: i32 = req.path.parse_query("agent")?;
 
-        let mut send_error: bool = req.path.parse_query("error").unwrap_or(false);
-        let mut send_src: bool = req.path.parse_query("src").unwrap_or(false);
-
-        let agent = agent::Entity::find_by_id(agent_id).one(&state.db).await?;
-
-        if agent.is_none() {
-            return Err(WebError::NotFound("Agent not found".to_string()));
-        }
-        let agent: agent::Model = agent.unwrap();
-
-        if let Some(owner_id) = agent.owner_id {
-            if let Some(owner) = user::Entity::find_by_id(owner_id).one(&state.db).await? {
-                if !is_user_authenticated(&req, &owner) && !authenticate_admin(&req, &state) {
-                    send_error = false;
-                    send_src = false;
-                }
-            }
-        }
+        let send_error: bool = req.path.parse_query("error").unwrap_or(false);
+        let send_src: bool = req.path.parse_query("src").unwrap_or(false);
 
-        let data = get_agent_data_as_json(&agent, send_error, send_src, &state.db).await?;
+        let data = get_agent_json(agent_id, send_error, send_src, &caller, &state.db).await?;
 
         let mut res = Response::new();
         res.set_status(Status::Ok);
@@ -596,18 +964,7 @@ async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResu
         }
         let profile = profile.unwrap();
 
-        let mut data = Map::new();
-
-        let agents = profile.find_related(agent::Entity).all(&state.db).await?;
-        let best_rating = agents.iter().map(|x| x.rating).max_by(|a, b| a.total_cmp(b)).unwrap_or(0.0);
-
-        let active_agents = agents.iter().filter(|x| !x.partial && !x.removed).count();
-
-        let total_games_played: i32 = agents.iter().map(|x| x.num_games).sum();
-
-        data.insert("best_rating".to_string(), json!(best_rating as i32));
-        data.insert("active_agents".to_string(), json!(active_agents));
-        data.insert("total_games".to_string(), json!(total_games_played));
+        let data = get_stats_json(&profile, &state.db).await?;
 
         let mut response = Response::new();
         response.set_status(Status::Ok);
@@ -644,7 +1001,7 @@ async fn route_get(_addr: SocketAddr, req: Request, state: AppState) -> HttpResu
     }
 }
 
-async fn reset_password(req: &Request, state: &AppState) -> HttpResult<Response> {
+async fn reset_password(req: &Request, state: &AppState, caller: &Caller) -> HttpResult<Response> {
     let user = get_user(req, state).await?;
 
     if user.is_none() {
@@ -653,57 +1010,394 @@ async fn reset_password(req: &Request, state: &AppState) -> HttpResult<Response>
 
     let user = user.unwrap();
 
+    let is_self = caller.is_user(user.id);
+    let password = generate_password();
+
     let mut active: entities::user::ActiveModel = user.into();
-    active.password = ActiveValue::Set(generate_password());
+    active.password = ActiveValue::Set(hash_password(&password));
     let user = active.update(&state.db).await?;
 
+    //Whoever had the old password is logged out, users resetting their own get a new session
+    revoke_user_sessions(user.id, &state.db).await?;
+
     let mut res = Response::new();
     res.set_status(Status::Ok);
     res.set_header("Content-Type", "text/plain");
-    res.set_body(user.password.clone().into_bytes());
+    res.set_body(password.into_bytes());
+
+    if is_self {
+        let (session, token) = create_session(&state.db, user.id).await?;
+        res.add_cookie(session_cookie(&session, &token));
+    }
 
     Ok(res)
 }
 
-async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpResult<Response> {
-    if req.matches_path(&["admin"]) {
-        if !authenticate_admin(&req, &state) {
-            Err(WebError::Unauthorized)
-        } else if req.matches_path_exact(&["admin", "new_profile"]) {
-            let username = &req.path.get("username")?.replace("\n", "");
+//Checks a {"username", "password"} body and starts a session for it
+pub(super) async fn log_in(data: &Map<String, Value>, db: &DatabaseConnection) -> HttpResult<(user::Model, session::Model, String)> {
+    let username = data.try_get("username")?.try_as_str()?;
+    let password = data.try_get("password")?.try_as_str()?;
 
-            if username.len() > 50 {
-                return Err(WebError::InvalidData("username too long".to_string()));
-            }
+    let profile = user::Entity::find()
+        .filter(user::Column::Username.eq(username))
+        .one(db)
+        .await?;
 
-            let other = entities::prelude::User::find()
-                .filter(user::Column::Username.eq(username))
-                .one(&state.db)
-                .await?;
+    //Same answer for unknown users and wrong passwords
+    let profile = match profile {
+        Some(profile) if verify_password(password, &profile.password) => profile,
+        _ => return Err(WebError::Unauthorized)
+    };
 
-            //Check if username is already taken
-            if other.is_some() {
-                return Err(WebError::InvalidData("already taken".to_string()));
-            }
+    remove_expired_sessions(db).await?;
+    let (session, token) = create_session(db, profile.id).await?;
 
-            let num_agents_allowed = req.path.parse_query("agents")?;
+    Ok((profile, session, token))
+}
 
-            let profile = user::ActiveModel {
-                username: ActiveValue::Set(username.clone()),
-                password: ActiveValue::Set(generate_password()),
-                num_agents_allowed: ActiveValue::Set(num_agents_allowed),
-                ..Default::default()
-            };
+async fn login(req: &Request, state: &AppState) -> HttpResult<Response> {
+    let data = decode_utf8(req.body.clone())?;
+    let data = parse_json_as_object(&data)?;
 
-            user::Entity::insert(profile).exec(&state.db).await?;
+    let (profile, session, token) = log_in(&data, &state.db).await?;
 
-            let mut res = Response::new();
-            res.set_status(Status::Ok);
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+    res.set_header("Content-Type", "application/json");
+    res.add_cookie(session_cookie(&session, &token));
+    res.set_body(json!({
+        "id": profile.id
+    }).to_string().into_bytes());
 
-            Ok(res)
-        } else if req.matches_path_exact(&["admin", "delete_profile"]) {
-            let profile = get_user(&req, &state).await?;
-            if let Some(profile) = profile {
+    Ok(res)
+}
+
+//Redeeming an invite makes an active contestant account straight away
+async fn register(req: &Request, state: &AppState) -> HttpResult<Response> {
+    let data = decode_utf8(req.body.clone())?;
+    let data = parse_json_as_object(&data)?;
+
+    let code = normalise_code(data.try_get("code")?.try_as_str()?);
+    let username = data.try_get("username")?.try_as_str()?.trim();
+    let password = data.try_get("password")?.try_as_str()?;
+
+    validate_username(username).map_err(WebError::InvalidData)?;
+
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(WebError::InvalidData(format!("password must be at least {} characters", MIN_PASSWORD_LEN)));
+    }
+
+    let txn = state.db.begin().await?;
+
+    let invite = invite::Entity::find()
+        .filter(invite::Column::Code.eq(code))
+        .filter(invite::Column::Redeemed.is_null())
+        .one(&txn)
+        .await?;
+
+    let invite = match invite {
+        Some(invite) => invite,
+        None => return Err(WebError::InvalidData("invite code is invalid or has been used".to_string()))
+    };
+
+    if invite.username.as_ref().is_some_and(|x| x != username) {
+        return Err(WebError::InvalidData("this invite code is for a different username".to_string()));
+    }
+
+    let other = user::Entity::find()
+        .filter(user::Column::Username.eq(username))
+        .one(&txn)
+        .await?;
+
+    if other.is_some() {
+        return Err(WebError::InvalidData("already taken".to_string()));
+    }
+
+    let profile = user::ActiveModel {
+        username: ActiveValue::Set(username.to_string()),
+        password: ActiveValue::Set(hash_password(password)),
+        num_agents_allowed: ActiveValue::Set(invite.num_agents_allowed),
+        role: ActiveValue::Set(Role::Contestant.as_str().to_string()),
+        ..Default::default()
+    };
+
+    let id = user::Entity::insert(profile).exec(&txn).await?.last_insert_id;
+
+    //Someone else got there first, the account goes away with the transaction
+    if !redeem_invite(&txn, invite.id, id).await? {
+        return Err(WebError::InvalidData("invite code is invalid or has been used".to_string()));
+    }
+
+    txn.commit().await?;
+
+    info!("Registered {} with an invite code", username);
+
+    let (session, token) = create_session(&state.db, id).await?;
+
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+    res.set_header("Content-Type", "application/json");
+    res.add_cookie(session_cookie(&session, &token));
+    res.set_body(json!({
+        "id": id
+    }).to_string().into_bytes());
+
+    Ok(res)
+}
+
+async fn get_users_by_id(db: &DatabaseConnection) -> HttpResult<HashMap<i32, user::Model>> {
+    Ok(user::Entity::find().all(db).await?.into_iter().map(|x| (x.id, x)).collect())
+}
+
+async fn invites_response(invites: &[invite::Model], db: &DatabaseConnection) -> HttpResult<Response> {
+    let users = get_users_by_id(db).await?;
+    let invites: Vec<_> = invites.iter().map(|x| invite_json(x, &users)).collect();
+
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+    res.set_header("Content-Type", "application/json");
+    res.set_body(Value::Array(invites).to_string().into_bytes());
+
+    Ok(res)
+}
+
+pub(super) async fn end_session(req: &Request, db: &DatabaseConnection) -> HttpResult<()> {
+    if let Some(session) = find_session(req, db).await? {
+        session.delete(db).await?;
+    }
+
+    Ok(())
+}
+
+async fn logout(req: &Request, state: &AppState) -> HttpResult<Response> {
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+
+    end_session(req, &state.db).await?;
+
+    res.add_cookie(clear_session_cookie());
+
+    Ok(res)
+}
+
+async fn revoke_session(req: &Request, state: &AppState, caller: &Caller) -> HttpResult<Response> {
+    let mut res = Response::new();
+    res.set_status(Status::Ok);
+
+    if req.matches_path_exact(&["api", "revoke_all_sessions"]) {
+        if let Some(user_id) = caller.user_id {
+            revoke_user_sessions(user_id, &state.db).await?;
+        }
+
+        res.add_cookie(clear_session_cookie());
+        return Ok(res);
+    }
+
+    let session_id: i32 = req.path.parse_query("session")?;
+    session::Entity::delete_by_id(session_id).exec(&state.db).await?;
+
+    Ok(res)
+}
+
+pub(super) async fn set_agent_colour(agent_id: i32, (r, g, b): (u8, u8, u8), db: &DatabaseConnection) -> HttpResult<()> {
+    let agent = agent::Entity::find_by_id(agent_id).one(db).await?;
+
+    if agent.is_none() {
+        return Err(WebError::NotFound("Agent not found".to_string()));
+    }
+
+    let color = format!("#{:02X}{:02X}{:02X}", r, g, b);
+    println!("Color = {}", color);
+
+    let mut active: agent::ActiveModel = agent.unwrap().into();
+
+    active.colour = ActiveValue::Set(color);
+    active.update(db).await?;
+
+    Ok(())
+}
+
+pub(super) async fn delete_agent(agent_id: i32, db: &DatabaseConnection) -> HttpResult<()> {
+    let agent = agent::Entity::find_by_id(agent_id).one(db).await?;
+
+    if agent.is_none() {
+        return Err(WebError::NotFound("Agent not found".to_string()));
+    }
+
+    agent.unwrap().delete(db).await?;
+
+    Ok(())
+}
+
+//Adds the agent straight away and compiles and validates it in the background
+pub(super) async fn submit_agent(state: &AppState, profile: user::Model, data: &Map<String, Value>) -> HttpResult<i32> {
+    let num_agents = get_num_agents(&profile, &state.db).await;
+
+    if num_agents >= profile.num_agents_allowed as _ {
+        return Err(WebError::InvalidData(format!("You have already used {} out of your {} available agent slot(s)! You can delete some of your agents to free these up!", num_agents, profile.num_agents_allowed)));
+    }
+
+    let submission = parse_submission(data)?;
+    let language_id = data.try_get("lang")?.try_as_str()?;
+    let name = data.try_get("name")?.try_as_str()?;
+
+    let storage_used = get_storage_used(&profile, &state.db).await;
+
+    if storage_used + submission.size() as i64 > STORAGE_QUOTA {
+        return Err(WebError::InvalidData(format!("This agent would take your agents over your storage quota of {} KB ({} KB used)! You can delete some of your agents to free up space!", STORAGE_QUOTA / 1024, storage_used / 1024)));
+    }
+
+    let language = state.languages.iter().filter(|l| l.id() == language_id).next();
+    let language = match language {
+        Some(l) => l,
+        None => return Err(WebError::InvalidData(format!("Unknown language {}", language_id)))
+    }.clone();
+
+    let variant = match data.get("variant") {
+        Some(variant) => variant.try_as_str()?,
+        None => language.variants()[0].id
+    };
+
+    if !language.variants().iter().any(|v| v.id == variant) {
+        return Err(WebError::InvalidData(format!("Unknown variant {} for {}", variant, language.name())));
+    }
+    let variant = variant.to_string();
+
+    let in_use = agent::Entity::find()
+        .filter(agent::Column::Name.eq(name))
+        .one(&state.db)
+        .await?.is_some();
+
+    if in_use {
+        return Err(WebError::InvalidData(format!("Agent name already used!")));
+    }
+
+    let mut program = PreparedProgram::new();
+    let src_file = random_file(RUN_DIR, ".src");
+
+    async_std::fs::write(&src_file, &submission.entry).await?;
+
+    let id = state.executor.add_player(
+        name.to_string(), 
+        language_id.to_string(), 
+        program.dir_as_string(),
+        Some(src_file),
+        Some(profile.id),
+        true,
+        variant.clone(),
+        submission.size() as i64
+    ).await?;
+
+    let executor = state.executor.clone();
+    let itf = state.executor.itf.clone();
+    let db = state.db.clone();
+    let limits = language.scale_limits(&state.executor.game.limits());
+    let compile_slots = state.compile_slots.clone();
+    async_std::task::spawn(async move {
+        let _slot = compile_slots.get().await.unwrap();
+
+        //Agents that compile still have to get through a validation game
+        let result = match language.prepare(&submission, &mut program, &itf, executor.sandboxes.clone(), &limits, &variant).await {
+            Ok(()) => Ok(executor.validate_agent(&language, &program.dir_as_string(), &variant).await),
+            Err(e) => Err(e)
+        };
+
+        let mut agent: agent::ActiveModel = match agent::Entity::find_by_id(id).one(&db).await {
+            Ok(Some(x)) => x,
+            Ok(None) => {
+                error!("Couldn't find agent that needed to be compiled!");
+                return;
+            },
+            Err(e) => {
+                error!("Encountered error while finding agent that needed to be compiled! {}", e);
+                return;
+            }
+        }.into();
+
+        match result {
+            Ok(Ok(())) => {
+                agent.partial = ActiveValue::Set(false)
+            },
+            Ok(Err(log)) => {
+                let error_file = random_file(RUN_DIR, ".validation-error");
+
+                if let Err(e) = async_std::fs::write(&error_file, log).await {
+                    error!("Encountered error while writing validation log! {}", e);
+                }
+
+                agent.partial = ActiveValue::Set(false);
+                agent.removed = ActiveValue::Set(true);
+                agent.error_file = ActiveValue::Set(Some(error_file));
+            },
+            Err(e) => {
+                let error_file = random_file(RUN_DIR, ".compile-error");
+                
+                if let Err(e) = async_std::fs::write(&error_file, e).await {
+                    error!("Encountered error while writing compile error! {}", e);
+                }
+
+                agent.removed = ActiveValue::Set(true);
+                agent.error_file = ActiveValue::Set(Some(error_file));
+            }
+        }
+
+        if let Err(e) = agent.update(&db).await {
+            error!("Encountered error while updating agent! {}", e);
+        }
+    });
+
+    Ok(id)
+}
+
+async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpResult<Response> {
+    let caller = Caller::from_request(&req, &state.db).await?;
+    authorize_post(&req, &caller, &state.db).await?;
+
+    if req.matches_path(&["admin"]) {
+        if req.matches_path_exact(&["admin", "new_profile"]) {
+            let username = &req.path.get("username")?.replace("\n", "");
+
+            if username.len() > 50 {
+                return Err(WebError::InvalidData("username too long".to_string()));
+            }
+
+            let other = entities::prelude::User::find()
+                .filter(user::Column::Username.eq(username))
+                .one(&state.db)
+                .await?;
+
+            //Check if username is already taken
+            if other.is_some() {
+                return Err(WebError::InvalidData("already taken".to_string()));
+            }
+
+            let num_agents_allowed = req.path.parse_query("agents")?;
+            let role = match req.path.query.get("role") {
+                Some(role) => parse_role(role)?,
+                None => Role::Contestant
+            };
+            let password = generate_password();
+
+            let profile = user::ActiveModel {
+                username: ActiveValue::Set(username.clone()),
+                password: ActiveValue::Set(hash_password(&password)),
+                num_agents_allowed: ActiveValue::Set(num_agents_allowed),
+                role: ActiveValue::Set(role.as_str().to_string()),
+                ..Default::default()
+            };
+
+            user::Entity::insert(profile).exec(&state.db).await?;
+
+            //The only time the password can be seen
+            let mut res = Response::new();
+            res.set_status(Status::Ok);
+            res.set_header("Content-Type", "text/plain");
+            res.set_body(password.into_bytes());
+
+            Ok(res)
+        } else if req.matches_path_exact(&["admin", "delete_profile"]) {
+            let profile = get_user(&req, &state).await?;
+            if let Some(profile) = profile {
                 info!("Deleting profile: id: {:?}, username: {:?}", profile.id, profile.username);
 
                 agent::Entity::delete_many()
@@ -711,6 +1405,8 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
                     .exec(&state.db)
                     .await?;
 
+                revoke_user_sessions(profile.id, &state.db).await?;
+
                 let profile: user::ActiveModel = profile.into();
                 user::Entity::delete(profile).exec(&state.db).await?;
 
@@ -732,6 +1428,32 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
                 let mut res = Response::new();
                 res.set_status(Status::Ok);
                     
+                Ok(res)
+            } else {
+                Err(WebError::NotFound("User id not found".to_string()))
+            }
+        } else if req.matches_path_exact(&["admin", "set_role"]) {
+            let profile = find_user_by_id(req.path.parse_query("id")?, &state.db).await?;
+            let role = parse_role(req.path.get("role")?)?;
+
+            info!("Setting role of {} to {}", profile.username, role);
+
+            let mut profile: user::ActiveModel = profile.into();
+            profile.role = ActiveValue::Set(role.as_str().to_string());
+            profile.update(&state.db).await?;
+
+            let mut res = Response::new();
+            res.set_status(Status::Ok);
+
+            Ok(res)
+        } else if req.matches_path_exact(&["admin", "revoke_sessions"]) {
+            let profile = get_user(&req, &state).await?;
+            if let Some(profile) = profile {
+                revoke_user_sessions(profile.id, &state.db).await?;
+
+                let mut res = Response::new();
+                res.set_status(Status::Ok);
+
                 Ok(res)
             } else {
                 Err(WebError::NotFound("User id not found".to_string()))
@@ -739,8 +1461,25 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
         } else if req.matches_path_exact(&["admin", "full_reset"]) {
             warn!("Doing full reset!");
 
+            //Admin accounts stay so that someone can still log in afterwards
+            let admins: Vec<_> = user::Entity::find()
+                .filter(user::Column::Role.eq(Role::Admin.as_str()))
+                .all(&state.db)
+                .await?
+                .into_iter()
+                .map(|x| x.id)
+                .collect();
+
             entities::agent::Entity::delete_many().exec(&state.db).await?;
-            entities::user::Entity::delete_many().exec(&state.db).await?;
+            entities::session::Entity::delete_many()
+                .filter(session::Column::UserId.is_not_in(admins.clone()))
+                .exec(&state.db)
+                .await?;
+            entities::user::Entity::delete_many()
+                .filter(user::Column::Id.is_not_in(admins))
+                .exec(&state.db)
+                .await?;
+            entities::invite::Entity::delete_many().exec(&state.db).await?;
 
             let mut res = Response::new();
             res.set_status(Status::Ok);
@@ -766,6 +1505,67 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
                 .exec(&state.db)
                 .await?;
 
+            let mut res = Response::new();
+            res.set_status(Status::Ok);
+            Ok(res)
+        } else if req.matches_path_exact(&["admin", "create_invites"]) {
+            let count: usize = req.path.parse_query("count")?;
+            let num_agents_allowed = req.path.parse_query("agents")?;
+
+            if count == 0 || count > MAX_INVITES_PER_REQUEST {
+                return Err(WebError::InvalidData(format!("can create between 1 and {} invites at once", MAX_INVITES_PER_REQUEST)));
+            }
+
+            let invites = create_invites(&state.db, vec![None; count], num_agents_allowed, caller.user_id).await?;
+            info!("Created {} invites", invites.len());
+
+            invites_response(&invites, &state.db).await
+        } else if req.matches_path_exact(&["admin", "import_invites"]) {
+            let num_agents_allowed = req.path.parse_query("agents")?;
+            let usernames = parse_usernames(&decode_utf8(req.body.clone())?).map_err(WebError::InvalidData)?;
+
+            if usernames.is_empty() || usernames.len() > MAX_INVITES_PER_REQUEST {
+                return Err(WebError::InvalidData(format!("can import between 1 and {} usernames at once", MAX_INVITES_PER_REQUEST)));
+            }
+
+            //Names that are taken or already waiting on an invite would make the code useless
+            let taken: Vec<_> = user::Entity::find()
+                .filter(user::Column::Username.is_in(usernames.clone()))
+                .all(&state.db)
+                .await?
+                .into_iter()
+                .map(|x| x.username)
+                .chain(invite::Entity::find()
+                    .filter(invite::Column::Username.is_in(usernames.clone()))
+                    .filter(invite::Column::Redeemed.is_null())
+                    .all(&state.db)
+                    .await?
+                    .into_iter()
+                    .filter_map(|x| x.username))
+                .collect();
+
+            if !taken.is_empty() {
+                return Err(WebError::InvalidData(format!("already taken: {}", taken.join(", "))));
+            }
+
+            let invites = create_invites(&state.db, usernames.into_iter().map(Some).collect(), num_agents_allowed, caller.user_id).await?;
+            info!("Imported {} invites", invites.len());
+
+            invites_response(&invites, &state.db).await
+        } else if req.matches_path_exact(&["admin", "delete_invite"]) {
+            let invite_id: i32 = req.path.parse_query("id")?;
+
+            let invite = match invite::Entity::find_by_id(invite_id).one(&state.db).await? {
+                Some(invite) => invite,
+                None => return Err(WebError::NotFound("Invite not found".to_string()))
+            };
+
+            if invite.redeemed.is_some() {
+                return Err(WebError::InvalidData("invite has already been redeemed".to_string()));
+            }
+
+            invite.delete(&state.db).await?;
+
             let mut res = Response::new();
             res.set_status(Status::Ok);
             Ok(res)
@@ -779,7 +1579,33 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
             Err(WebError::NotFound("Admin route not found".to_string()))
         }
     } else if req.matches_path_exact(&["api", "reset_password"]) {
-        reset_password(&req, &state).await
+        reset_password(&req, &state, &caller).await
+    } else if req.matches_path_exact(&["api", "login"]) {
+        login(&req, &state).await
+    } else if req.matches_path_exact(&["api", "logout"]) {
+        logout(&req, &state).await
+    } else if req.matches_path_exact(&["api", "register"]) {
+        register(&req, &state).await
+    } else if req.matches_path_exact(&["api", "create_token"]) {
+        let data = parse_json_as_object(&decode_utf8(req.body.clone())?)?;
+        let user_id = caller.user_id.ok_or(WebError::Unauthorized)?;
+
+        let mut res = Response::new();
+        res.set_status(Status::Ok);
+        res.set_header("Content-Type", "application/json");
+        res.set_body(new_token(&data, user_id, &state.db).await?.to_string().into_bytes());
+
+        Ok(res)
+    } else if req.matches_path_exact(&["api", "revoke_token"]) {
+        let token_id: i32 = req.path.parse_query("token")?;
+        api_token::Entity::delete_by_id(token_id).exec(&state.db).await?;
+
+        let mut res = Response::new();
+        res.set_status(Status::Ok);
+
+        Ok(res)
+    } else if req.matches_path_exact(&["api", "revoke_session"]) || req.matches_path_exact(&["api", "revoke_all_sessions"]) {
+        revoke_session(&req, &state, &caller).await
     } else if req.matches_path_exact(&["api", "add_agent"]) {
         let profile = get_user(&req, &state).await?;
 
@@ -788,56 +1614,11 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
         }
         let profile = profile.unwrap();
 
-        if !is_user_authenticated(&req, &profile) {
-            return Err(WebError::Unauthorized);
-        }
-
-        let num_agents = get_num_agents(&profile, &state.db).await;
-
-        if num_agents >= profile.num_agents_allowed as _ {
-            return Err(WebError::InvalidData(format!("You have already used {} out of your {} available agent slot(s)! You can delete some of your agents to free these up!", num_agents, profile.num_agents_allowed)));
-        }
-
         let data = decode_utf8(req.body.clone())?;
 
         let data = parse_json_as_object(&data)?;
 
-        let src = data.try_get("src")?.try_as_str()?.to_string();
-        let language_id = data.try_get("lang")?.try_as_str()?;
-        let name = data.try_get("name")?.try_as_str()?;
-
-        if src.len() > 30000 {
-            return Err(WebError::InvalidData(format!("Source code too long!")));
-        }
-
-        let language = state.languages.iter().filter(|l| l.id() == language_id).next();
-        let language = match language {
-            Some(l) => l,
-            None => return Err(WebError::InvalidData(format!("Unknown language {}", language_id)))
-        }.clone();
-
-        let in_use = agent::Entity::find()
-            .filter(agent::Column::Name.eq(name))
-            .one(&state.db)
-            .await?.is_some();
-
-        if in_use {
-            return Err(WebError::InvalidData(format!("Agent name already used!")));
-        }
-
-        let mut program = PreparedProgram::new();
-        let src_file = random_file(RUN_DIR, ".src");
-
-        async_std::fs::write(&src_file, &src).await?;
-
-        let id = state.executor.add_player(
-            name.to_string(), 
-            language_id.to_string(), 
-            program.dir_as_string(),
-            Some(src_file),
-            Some(profile.id),
-            true
-        ).await?;
+        let id = submit_agent(&state, profile, &data).await?;
 
         let mut res = Response::new();
         res.set_status(Status::Ok);
@@ -846,101 +1627,24 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
             "agent_id": id
         }))?.into_bytes());
 
-        let itf = state.executor.itf.clone();
-        let db = state.db.clone();
-        async_std::task::spawn(async move {
-            let result = language.prepare(&src, &mut program, &itf, state.executor.sandboxes.clone()).await;
-            let mut agent: agent::ActiveModel = match agent::Entity::find_by_id(id).one(&db).await {
-                Ok(Some(x)) => x,
-                Ok(None) => {
-                    error!("Couldn't find agent that needed to be compiled!");
-                    return;
-                },
-                Err(e) => {
-                    error!("Encountered error while finding agent that needed to be compiled! {}", e);
-                    return;
-                }
-            }.into();
-
-            match result {
-                Ok(()) => {
-                    agent.partial = ActiveValue::Set(false)
-                },
-                Err(e) => {
-                    let error_file = random_file(RUN_DIR, ".compile-error");
-                    
-                    if let Err(e) = async_std::fs::write(&error_file, e).await {
-                        error!("Encountered error while writing compile error! {}", e);
-                    }
-
-                    agent.removed = ActiveValue::Set(true);
-                    agent.error_file = ActiveValue::Set(Some(error_file));
-                }
-            }
-
-            if let Err(e) = agent.update(&db).await {
-                error!("Encountered error while updating agent! {}", e);
-            }
-        });
-
         Ok(res)
     } else if req.matches_path_exact(&["api", "set_colour"]) {
-        let profile = get_user(&req, &state).await?;
-
-        if profile.is_none() {
-            return Err(WebError::InvalidData("User id not found".to_string()));
-        }
-        let profile = profile.unwrap();
-
-        if !is_user_authenticated(&req, &profile) {
-            return Err(WebError::Unauthorized);
-        }
-
         let agent_id: i32 = req.path.parse_query("agent")?;
 
         let r: u8 = req.path.parse_query("r")?;
         let g: u8 = req.path.parse_query("g")?;
         let b: u8 = req.path.parse_query("b")?;
 
-        let agent = agent::Entity::find_by_id(agent_id).one(&state.db).await?;
-
-        if agent.is_none() {
-            return Err(WebError::NotFound("Agent not found".to_string()));
-        }
-
-        let color = format!("#{:02X}{:02X}{:02X}", r, g, b);
-        println!("Color = {}", color);
-
-        let mut active: agent::ActiveModel = agent.unwrap().into();
-
-        active.colour = ActiveValue::Set(color);
-        active.update(&state.db).await?;
+        set_agent_colour(agent_id, (r, g, b), &state.db).await?;
 
         let mut res = Response::new();
         res.set_status(Status::Ok);
 
         Ok(res)
     } else if req.matches_path_exact(&["api", "delete_agent"]) {
-        let profile = get_user(&req, &state).await?;
-
-        if profile.is_none() {
-            return Err(WebError::NotFound("Profile not found".to_string()));
-        }
-        let profile = profile.unwrap();
-
-        if !is_user_authenticated(&req, &profile) {
-            return Err(WebError::Unauthorized);
-        }
-
         let agent_id: i32 = req.path.parse_query("agent")?;
 
-        let agent = agent::Entity::find_by_id(agent_id).one(&state.db).await?;
-
-        if agent.is_none() {
-            return Err(WebError::NotFound("Agent not found".to_string()));
-        }
-
-        agent.unwrap().delete(&state.db).await?;
+        delete_agent(agent_id, &state.db).await?;
 
         let mut res = Response::new();
         res.set_status(Status::Ok);
@@ -951,43 +1655,124 @@ async fn route_post(_addr: SocketAddr, req: Request, state: AppState) -> HttpRes
     }
 }
 
-async fn handle_conn(mut stream: TcpStream, addr: SocketAddr, state: AppState) {
-    let request = match Request::parse_async(&mut stream).await {
-        Ok(request) => request,
-        Err(e) => {
-            println!("Error parsing request from {}: {}", addr, e);
+//Every request counts against its IP. Ones that start compiles count against the user as well, and
+//logins against the account they're for
+async fn check_rate_limits(req: &Request, addr: SocketAddr, state: &AppState) -> HttpResult<()> {
+    let group = RouteGroup::of(req);
 
-            Response::basic_error(Status::BadRequest, &format!("Error parsing request: {}", e))
-                .write_async(&mut stream)
-                .await
-                .ignore();
+    state.rate_limiter.check(group, Client::Ip(addr.ip()))?;
 
-            return;
-        }
-    };
+    match group {
+        RouteGroup::Submit => {
+            if let Some(user_id) = Caller::from_request(req, &state.db).await?.user_id {
+                state.rate_limiter.check(group, Client::User(user_id))?;
+            }
+        },
+        RouteGroup::Auth => {
+            //A malformed body is left for the route to turn down
+            let body = serde_json::from_slice::<Value>(&req.body).ok();
+            let username = body.as_ref().and_then(|x| x.get("username")).and_then(|x| x.as_str()).map(|x| x.trim());
+
+            //Names too long to exist don't get a bucket
+            if let Some(username) = username.filter(|x| x.len() <= MAX_USERNAME_LEN) {
+                state.rate_limiter.check(group, Client::Username(username.to_string(), addr.ip()))?;
+            }
+        },
+        RouteGroup::Other => {}
+    }
 
-    info!("Received request [{} {} {}]", addr, request.method, request.path);
+    Ok(())
+}
 
-    if request.method == Method::Get && request.matches_path_exact(&["bruh"]) {
-        let mut inner = state.reporter.lock().await;
+async fn handle_conn(mut stream: Connection, addr: SocketAddr, state: AppState) {
+    let mut reader = HttpReader::new(state.http_limits);
+    let secure = stream.is_secure();
 
-        inner.handle_stream(stream, &request).await;
-    } else {
-        let result = match request.method {
-            Method::Get => route_get(addr, request, state).await,
-            Method::Post => route_post(addr, request, state).await,
+    loop {
+        let request = match reader.read_request(&mut stream).await {
+            Ok(Some(request)) => request,
+            //Closed, or left idle, between requests
+            Ok(None) => return,
+            Err(HttpError::Io(_)) => return,
+            Err(e) => {
+                println!("Error parsing request from {}: {}", addr, e);
+
+                let status = match e {
+                    HttpError::Http(status, _) => status,
+                    _ => Status::BadRequest
+                };
 
-            _ => Err(WebError::InvalidMethod)
+                //Whatever is left of a bad request can't be told apart from the next one
+                let mut response = Response::basic_error(status, &format!("Error parsing request: {}", e));
+                response.set_header("Connection", "close");
+                response.write_async(&mut stream).await.ignore();
+
+                return;
+            }
         };
 
-        match result {
-            Ok(res) => res.write_async(&mut stream).await.ignore(),
+        info!("Received request [{} {} {}]", addr, request.method, request.path);
+
+        let keep_alive = request.keep_alive();
+        let is_v2 = request.matches_path(&api_v2::PREFIX);
+
+        let result = match check_rate_limits(&request, addr, &state).await {
+            Err(e) => Err(e),
+            Ok(()) if request.method == Method::Get && request.matches_path_exact(&["bruh"]) => {
+                let viewer = spectator_id(&request, &state).await;
+                let mut inner = state.reporter.lock().await;
+
+                //The event stream keeps the connection for itself
+                inner.handle_stream(stream, &request, viewer).await;
+
+                return;
+            },
+            Ok(()) if request.matches_path_exact(&["spectate"]) => {
+                let response = websocket::handshake(&request);
+
+                if response.status.get_code() != 101 {
+                    Ok(response)
+                } else {
+                    //The same goes for a WebSocket once it's switched over
+                    let viewer = spectator_id(&request, &state).await;
+
+                    if response.write_async(&mut stream).await.is_ok() {
+                        spectate_websocket(state.reporter.clone(), stream, reader.into_buffered(), viewer).await;
+                    }
+
+                    return;
+                }
+            },
+            Ok(()) if is_v2 => api_v2::route(request, state.clone()).await,
+            Ok(()) => match request.method {
+                Method::Get => route_get(addr, request, state.clone()).await,
+                Method::Post => route_post(addr, request, state.clone()).await,
+
+                _ => Err(WebError::InvalidMethod)
+            }
+        };
+
+        let mut response = match result {
+            Ok(res) => res,
             Err(res) => {
-                let response = res.into_response();
+                let response = if is_v2 { res.into_json_response() } else { res.into_response() };
                 info!("Request [{}] was unsuccesful ({})", addr, response.status);
-                response.write_async(&mut stream).await.ignore();
+                response
+            }
+        };
+
+        response.set_header("Connection", if keep_alive { "keep-alive" } else { "close" });
+
+        //Session cookies must never be sent back over plain HTTP once TLS is in use
+        if secure {
+            for cookie in response.cookies.iter_mut() {
+                cookie.push_str("; Secure");
             }
         }
+
+        if response.write_async(&mut stream).await.is_err() || !keep_alive {
+            return;
+        }
     }
 }
 
@@ -1008,28 +1793,278 @@ fn generate_admin_password() -> String {
         .collect()
 }
 
-pub async fn launch_and_run_api(executor: Arc<GameRunner<Box<dyn Game>>>, reporter: Arc<Mutex<SharedInner>>, db: DatabaseConnection) -> std::io::Result<()> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+//Without any admin account nobody could log in to make one, so the first start makes one
+async fn create_first_admin(db: &DatabaseConnection) -> HttpResult<()> {
+    let has_admin = user::Entity::find()
+        .filter(user::Column::Role.eq(Role::Admin.as_str()))
+        .one(db)
+        .await?
+        .is_some();
+
+    if has_admin {
+        return Ok(());
+    }
+
+    let taken = user::Entity::find()
+        .filter(user::Column::Username.eq("admin"))
+        .one(db)
+        .await?
+        .is_some();
+
+    if taken {
+        warn!("There is no admin account and the username 'admin' is taken, set a role in the database by hand");
+        return Ok(());
+    }
+
+    let password = generate_admin_password();
+
+    let profile = user::ActiveModel {
+        username: ActiveValue::Set("admin".to_string()),
+        password: ActiveValue::Set(hash_password(&password)),
+        num_agents_allowed: ActiveValue::Set(0),
+        role: ActiveValue::Set(Role::Admin.as_str().to_string()),
+        ..Default::default()
+    };
+
+    user::Entity::insert(profile).exec(db).await?;
+
+    println!("Created account 'admin' with password: {}", password);
+    std::fs::write("./admin_password.txt", password.as_bytes())?;
+
+    Ok(())
+}
+
+//Plain HTTP connections only ever get sent to the HTTPS port
+async fn redirect_conn(mut stream: TcpStream, https_port: u16, limits: HttpLimits) {
+    let mut reader = HttpReader::new(limits);
+
+    if let Ok(Some(request)) = reader.read_request(&mut stream).await {
+        let mut response = https_redirect(&request, https_port);
+        response.set_header("Connection", "close");
+        response.write_async(&mut stream).await.ignore();
+    }
+}
+
+async fn run_redirect(listener: TcpListener, https_port: u16, limits: HttpLimits) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                async_std::task::spawn(redirect_conn(stream, https_port, limits));
+            },
+            Err(e) => error!("Couldn't accept connection for HTTPS redirect: {}", e)
+        }
+    }
+}
+
+pub async fn launch_and_run_api(executor: Arc<GameRunner<Box<dyn Game>>>, reporter: Arc<Mutex<SharedInner>>, db: DatabaseConnection, config: ServerConfig) -> std::io::Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+
+    let acceptor = match &config.tls {
+        Some(tls) => {
+            let resolver = Arc::new(CertResolver::load(&tls.cert, &tls.key).map_err(std::io::Error::other)?);
+            reload_on_sighup(resolver.clone())?;
+
+            Some(acceptor(resolver).map_err(std::io::Error::other)?)
+        },
+        None => None
+    };
 
     let listener = TcpListener::bind(addr).await?;
 
+    create_first_admin(&db).await.unwrap();
+
+    let compile_slots = Pool::new(MAX_CONCURRENT_COMPILES);
+
+    for _ in 0..MAX_CONCURRENT_COMPILES {
+        compile_slots.add(()).await.unwrap();
+    }
+
     let state = AppState {
         executor,
-        super_secret_admin_password: generate_admin_password(),
         languages: Arc::new(get_all_languages()),
         reporter,
         db,
-        page_engine: PageEngine::load()
+        page_engine: PageEngine::load(),
+        http_limits: config.http_limits,
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limits)),
+        compile_slots
     };
 
-    println!("Admin password: {}", state.super_secret_admin_password);
-    std::fs::write("./admin_password.txt", state.super_secret_admin_password.as_bytes()).unwrap();
+    if let Some(redirect_port) = config.tls.as_ref().and_then(|x| x.redirect_port) {
+        let redirect_listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], redirect_port))).await?;
+        async_std::task::spawn(run_redirect(redirect_listener, config.port, state.http_limits));
+
+        println!("Redirecting port {} to HTTPS", redirect_port);
+    }
 
-    println!("Listening on {}", addr);
+    println!("Listening on {}{}", addr, if acceptor.is_some() { " with TLS" } else { "" });
 
     loop {
         let (stream, addr) = listener.accept().await?;
+        let state = state.clone();
+
+        match &acceptor {
+            Some(acceptor) => {
+                let acceptor = acceptor.clone();
+
+                async_std::task::spawn(async move {
+                    //Clients that never finish the handshake shouldn't hold on to a task
+                    match async_std::future::timeout(state.http_limits.idle_timeout, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => handle_conn(Connection::Tls(Box::new(stream)), addr, state).await,
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", addr)
+                    }
+                });
+            },
+            None => {
+                async_std::task::spawn(handle_conn(Connection::Plain(stream), addr, state));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ActiveValue, Database, DatabaseConnection, EntityTrait};
+
+    use crate::{entities::{agent, user}, web::{api_token::{create_token, Scopes}, http::{Method, Request, RequestPath}, role::Role, session::{create_session, SESSION_COOKIE}, web_errors::WebError}};
+
+    use super::{authorize_post, Caller};
 
-        async_std::task::spawn(handle_conn(stream, addr, state.clone()));
+    //Returns the user's id and a session token for them
+    async fn add_user(db: &DatabaseConnection, username: &str, role: Role) -> (i32, String) {
+        let profile = user::ActiveModel {
+            username: ActiveValue::Set(username.to_string()),
+            password: ActiveValue::Set(String::new()),
+            num_agents_allowed: ActiveValue::Set(1),
+            role: ActiveValue::Set(role.as_str().to_string()),
+            ..Default::default()
+        };
+
+        let id = user::Entity::insert(profile).exec(db).await.unwrap().last_insert_id;
+
+        let (session, token) = create_session(db, id).await.unwrap();
+
+        //Only the cookie holds the token itself
+        assert_ne!(session.hash, token);
+
+        (id, token)
+    }
+
+    async fn authorize(db: &DatabaseConnection, route: &str, token: Option<&str>) -> Result<(), WebError> {
+        let cookies: HashMap<_, _> = token.iter().map(|x| (SESSION_COOKIE.to_string(), x.to_string())).collect();
+        let req = Request::new(Method::Post, RequestPath::parse(route).unwrap(), HashMap::new(), vec![], cookies);
+
+        let caller = Caller::from_request(&req, db).await?;
+        authorize_post(&req, &caller, db).await
+    }
+
+    #[test]
+    fn test_foreign_user() {
+        pollster::block_on(async {
+            let db = Database::connect("sqlite::memory:").await.unwrap();
+            Migrator::up(&db, None).await.unwrap();
+
+            let (owner, as_owner) = add_user(&db, "owner", Role::Contestant).await;
+            let (_, as_other) = add_user(&db, "other", Role::Contestant).await;
+            let (spectator, as_spectator) = add_user(&db, "spectator", Role::Spectator).await;
+            let (organiser, as_organiser) = add_user(&db, "organiser", Role::Organiser).await;
+            let (_, as_admin) = add_user(&db, "admin", Role::Admin).await;
+
+            let agent = agent::ActiveModel {
+                name: ActiveValue::Set("agent".to_string()),
+                language: ActiveValue::Set("python3".to_string()),
+                directory: ActiveValue::Set(String::new()),
+                owner_id: ActiveValue::Set(Some(owner)),
+                ..Default::default()
+            };
+            let agent = agent::Entity::insert(agent).exec(&db).await.unwrap().last_insert_id;
+
+            let (owner_session, _) = create_session(&db, owner).await.unwrap();
+
+            let owned_routes = [
+                format!("/api/reset_password?id={owner}"),
+                format!("/api/add_agent?id={owner}"),
+                format!("/api/set_colour?id={owner}&agent={agent}&r=0&g=0&b=0"),
+                format!("/api/delete_agent?id={owner}&agent={agent}"),
+                format!("/api/revoke_session?session={}", owner_session.id)
+            ];
+
+            let organiser_routes = [
+                "/admin/new_profile?username=new&agents=1".to_string(),
+                format!("/admin/delete_profile?id={owner}"),
+                format!("/admin/set_profile_agents?id={owner}&agents=2"),
+                format!("/admin/set_role?id={owner}&role=spectator"),
+                format!("/admin/revoke_sessions?id={owner}"),
+                "/admin/create_invites?count=5&agents=1".to_string(),
+                "/admin/import_invites?agents=1".to_string(),
+                "/admin/delete_invite?id=1".to_string(),
+                "/admin/file_cleanup".to_string()
+            ];
+
+            let admin_routes = [
+                "/admin/full_reset".to_string(),
+                "/admin/agents_reset".to_string(),
+                "/admin/ratings_reset".to_string()
+            ];
+
+            for route in owned_routes.iter().chain(&organiser_routes).chain(&admin_routes) {
+                assert!(matches!(authorize(&db, route, Some(&as_other)).await, Err(WebError::Forbidden(_))), "{}", route);
+                assert!(matches!(authorize(&db, route, Some(&as_spectator)).await, Err(WebError::Forbidden(_))), "{}", route);
+                assert!(matches!(authorize(&db, route, None).await, Err(WebError::Unauthorized)), "{}", route);
+                assert!(authorize(&db, route, Some(&as_admin)).await.is_ok(), "{}", route);
+            }
+
+            for route in &owned_routes {
+                assert!(authorize(&db, route, Some(&as_owner)).await.is_ok(), "{}", route);
+            }
+
+            for route in owned_routes.iter().chain(&organiser_routes) {
+                assert!(authorize(&db, route, Some(&as_organiser)).await.is_ok(), "{}", route);
+            }
+
+            for route in organiser_routes.iter().chain(&admin_routes) {
+                assert!(matches!(authorize(&db, route, Some(&as_owner)).await, Err(WebError::Forbidden(_))), "{}", route);
+            }
+
+            for route in &admin_routes {
+                assert!(matches!(authorize(&db, route, Some(&as_organiser)).await, Err(WebError::Forbidden(_))), "{}", route);
+            }
+
+            //Organisers can't hand out their own role or touch accounts at their level
+            assert!(authorize(&db, "/admin/new_profile?username=new&agents=1&role=organiser", Some(&as_organiser)).await.is_err());
+            assert!(authorize(&db, &format!("/admin/set_role?id={spectator}&role=admin"), Some(&as_organiser)).await.is_err());
+            assert!(authorize(&db, &format!("/admin/set_role?id={organiser}&role=admin"), Some(&as_organiser)).await.is_err());
+
+            //Spectators can look after their account, but not submit
+            assert!(authorize(&db, &format!("/api/reset_password?id={spectator}"), Some(&as_spectator)).await.is_ok());
+            assert!(matches!(authorize(&db, &format!("/api/add_agent?id={spectator}"), Some(&as_spectator)).await, Err(WebError::Forbidden(_))));
+
+            //API tokens only reach the agent routes their scopes cover
+            let (_, submitter) = create_token(&db, owner, "ci", Scopes::from_names(["submit"]).unwrap()).await.unwrap();
+            let with_token = |route: &str| {
+                let headers = HashMap::from([("Authorization".to_string(), format!("Bearer {}", submitter))]);
+                Request::new(Method::Post, RequestPath::parse(route).unwrap(), headers, vec![], HashMap::new())
+            };
+
+            for (route, allowed) in [
+                (format!("/api/add_agent?id={owner}"), true),
+                (format!("/api/set_colour?id={owner}&agent={agent}&r=0&g=0&b=0"), true),
+                (format!("/api/delete_agent?id={owner}&agent={agent}"), false),
+                (format!("/api/reset_password?id={owner}"), false),
+                ("/api/create_token".to_string(), false)
+            ] {
+                let req = with_token(&route);
+                let caller = Caller::from_request(&req, &db).await.unwrap();
+                assert_eq!(authorize_post(&req, &caller, &db).await.is_ok(), allowed, "{}", route);
+            }
+
+            assert!(authorize(&db, "/api/login", None).await.is_ok());
+            assert!(authorize(&db, "/api/register", None).await.is_ok());
+            assert!(matches!(authorize(&db, "/api/unknown", Some(&as_admin)).await, Err(WebError::NotFound(_))));
+        });
     }
 }
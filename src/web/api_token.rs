@@ -0,0 +1,196 @@
+use std::{fmt::Display, time::{SystemTime, UNIX_EPOCH}};
+
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, sea_query::Expr};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::entities::api_token;
+
+use super::http::Request;
+
+const TOKEN_START: &str = "sais_";
+const TOKEN_LEN: usize = 40;
+//Enough of the token to tell it apart in a list, without making it guessable
+const PREFIX_LEN: usize = TOKEN_START.len() + 6;
+
+pub const MAX_TOKEN_NAME_LEN: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    //Everything a logged out visitor can see, plus the owner's compile errors and source
+    Read,
+    //Adding agents and changing their colour
+    Submit,
+    Delete
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Read, Scope::Submit, Scope::Delete];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Submit => "submit",
+            Scope::Delete => "delete"
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.as_str() == scope)
+    }
+
+    fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Scopes(u8);
+
+impl Scopes {
+    pub fn contains(&self, scope: Scope) -> bool {
+        self.0 & scope.bit() != 0
+    }
+
+    pub fn list(&self) -> Vec<Scope> {
+        Scope::ALL.into_iter().filter(|x| self.contains(*x)).collect()
+    }
+
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut scopes = Scopes::default();
+
+        for name in names {
+            match Scope::parse(name.trim()) {
+                Some(scope) => scopes.0 |= scope.bit(),
+                None => return Err(format!("Unknown scope {}", name))
+            }
+        }
+
+        if scopes.0 == 0 {
+            return Err("A token needs at least one scope".to_string());
+        }
+
+        Ok(scopes)
+    }
+
+    //Stored comma separated
+    pub fn parse(scopes: &str) -> Self {
+        Self::from_names(scopes.split(',')).unwrap_or_default()
+    }
+}
+
+impl Display for Scopes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = self.list().iter().map(|x| x.as_str()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs() as i64).unwrap_or(0)
+}
+
+fn generate_token() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect();
+
+    format!("{}{}", TOKEN_START, random)
+}
+
+//Tokens are long and random, so a fast hash is enough and lets them be looked up by it
+pub(super) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+//The token itself is only returned here, only its hash is kept
+pub async fn create_token(db: &DatabaseConnection, user_id: i32, name: &str, scopes: Scopes) -> Result<(api_token::Model, String), DbErr> {
+    let token = generate_token();
+
+    let model = api_token::ActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        name: ActiveValue::Set(name.to_string()),
+        prefix: ActiveValue::Set(token[..PREFIX_LEN].to_string()),
+        hash: ActiveValue::Set(hash_token(&token)),
+        scopes: ActiveValue::Set(scopes.to_string()),
+        created: ActiveValue::Set(now()),
+        ..Default::default()
+    };
+
+    let id = api_token::Entity::insert(model).exec(db).await?.last_insert_id;
+
+    let model = api_token::Entity::find_by_id(id).one(db).await?
+        .ok_or(DbErr::RecordNotFound("Token that was just created".to_string()))?;
+
+    Ok((model, token))
+}
+
+//The token from an "Authorization: Bearer" header, None if there isn't one
+pub fn bearer_token(req: &Request) -> Option<&str> {
+    req.header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|x| x.trim())
+}
+
+pub async fn find_token(token: &str, db: &DatabaseConnection) -> Result<Option<api_token::Model>, DbErr> {
+    let model = api_token::Entity::find()
+        .filter(api_token::Column::Hash.eq(hash_token(token)))
+        .one(db)
+        .await?;
+
+    if let Some(model) = &model {
+        api_token::Entity::update_many()
+            .col_expr(api_token::Column::LastUsed, Expr::value(now()))
+            .filter(api_token::Column::Id.eq(model.id))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(model)
+}
+
+pub fn token_json(token: &api_token::Model) -> Value {
+    let scopes: Vec<_> = Scopes::parse(&token.scopes).list().iter().map(|x| x.as_str()).collect();
+
+    json!({
+        "id": token.id,
+        "name": token.name,
+        "prefix": token.prefix,
+        "scopes": scopes,
+        "created": token.created,
+        "last_used": token.last_used
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_token, hash_token, Scope, Scopes, PREFIX_LEN};
+
+    #[test]
+    fn test_token_scopes() {
+        let scopes = Scopes::from_names(["submit", "read"]).unwrap();
+
+        assert!(scopes.contains(Scope::Read) && scopes.contains(Scope::Submit));
+        assert!(!scopes.contains(Scope::Delete));
+        assert_eq!(scopes.to_string(), "read,submit");
+        assert_eq!(Scopes::parse(&scopes.to_string()), scopes);
+
+        assert!(Scopes::from_names(["admin"]).is_err());
+        assert!(Scopes::from_names([]).is_err());
+
+        let token = generate_token();
+        assert!(token.len() > PREFIX_LEN);
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+        assert_eq!(hash_token("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
@@ -0,0 +1,771 @@
+use log::info;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde_json::{json, Map, Value};
+
+use crate::entities::{api_token, session};
+
+use super::{
+    api::{authorize_agent, authorize_session, authorize_submit, authorize_token, delete_agent, end_session, find_user_by_id, get_agent_json, get_agent_leaderboard, get_game_json, get_languages_json, get_profile_json, get_sessions_json, get_stats_json, get_tokens_json, log_in, new_token, set_agent_colour, submit_agent, AppState, Caller},
+    api_token::Scope,
+    http::{Request, Response, Status},
+    session::{clear_session_cookie, find_session, session_cookie, SESSION_COOKIE},
+    web_errors::{decode_utf8, parse_json_as_object, HttpErrorMap, HttpResult, ValueCast, WebError}
+};
+
+pub const PREFIX: [&str; 2] = ["api", "v2"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    OpenApi,
+    Game,
+    Languages,
+    Leaderboard,
+
+    Login,
+    CurrentSession,
+    Logout,
+    Sessions,
+    RevokeSession,
+
+    User,
+    UserStats,
+    SubmitAgent,
+
+    Agent,
+    UpdateAgent,
+    DeleteAgent,
+
+    Tokens,
+    CreateToken,
+    RevokeToken
+}
+
+struct QueryParam {
+    name: &'static str,
+    schema: &'static str,
+    description: &'static str
+}
+
+//Every v2 route, the OpenAPI document is generated from this so the two can't drift apart
+struct Route {
+    method: &'static str,
+    //Relative to /api/v2, "{id}" matches an integer id
+    path: &'static str,
+    endpoint: Endpoint,
+    summary: &'static str,
+    //Needs a session, used for the document, `authorize` does the checking
+    auth: bool,
+    //What an API token needs to use the route, None for routes only sessions can use
+    scope: Option<Scope>,
+    query: &'static [QueryParam],
+    body: Option<&'static str>,
+    status: Status,
+    //Schema of the "data" field of a successful response, None for responses without a body
+    data: Option<&'static str>
+}
+
+const ROUTES: &[Route] = &[
+    Route { method: "get", path: "/openapi.json", endpoint: Endpoint::OpenApi, summary: "This document", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: None },
+    Route { method: "get", path: "/game", endpoint: Endpoint::Game, summary: "The game being played", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("Game") },
+    Route { method: "get", path: "/languages", endpoint: Endpoint::Languages, summary: "Languages agents can be written in", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("Language[]") },
+    Route { method: "get", path: "/leaderboard", endpoint: Endpoint::Leaderboard, summary: "Active agents by rating", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("LeaderboardEntry[]") },
+
+    Route { method: "post", path: "/sessions", endpoint: Endpoint::Login, summary: "Log in, the session cookie is set on the response", auth: false, scope: None, query: &[], body: Some("Login"), status: Status::Created, data: Some("NewSession") },
+    Route { method: "get", path: "/sessions", endpoint: Endpoint::Sessions, summary: "Sessions of the logged in user", auth: true, scope: None, query: &[], body: None, status: Status::Ok, data: Some("Session[]") },
+    Route { method: "delete", path: "/sessions/{id}", endpoint: Endpoint::RevokeSession, summary: "Revoke a session", auth: true, scope: None, query: &[], body: None, status: Status::NoContent, data: None },
+    Route { method: "get", path: "/session", endpoint: Endpoint::CurrentSession, summary: "Who is logged in", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("CurrentSession") },
+    Route { method: "delete", path: "/session", endpoint: Endpoint::Logout, summary: "Log out", auth: false, scope: None, query: &[], body: None, status: Status::NoContent, data: None },
+
+    Route { method: "get", path: "/users/{id}", endpoint: Endpoint::User, summary: "A user, agents are only included for whoever can manage them", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("User") },
+    Route { method: "get", path: "/users/{id}/stats", endpoint: Endpoint::UserStats, summary: "Totals over a user's agents", auth: false, scope: Some(Scope::Read), query: &[], body: None, status: Status::Ok, data: Some("Stats") },
+    Route { method: "post", path: "/users/{id}/agents", endpoint: Endpoint::SubmitAgent, summary: "Submit an agent, it is compiled and validated in the background", auth: true, scope: Some(Scope::Submit), query: &[], body: Some("AgentSubmission"), status: Status::Created, data: Some("Created") },
+
+    Route {
+        method: "get", path: "/agents/{id}", endpoint: Endpoint::Agent, summary: "An agent", auth: false, scope: Some(Scope::Read),
+        query: &[
+            QueryParam { name: "error", schema: "boolean", description: "Include compile errors and faults, only for whoever can manage the agent" },
+            QueryParam { name: "src", schema: "boolean", description: "Include the source, only for whoever can manage the agent" }
+        ],
+        body: None, status: Status::Ok, data: Some("Agent")
+    },
+    Route { method: "patch", path: "/agents/{id}", endpoint: Endpoint::UpdateAgent, summary: "Change an agent's colour", auth: true, scope: Some(Scope::Submit), query: &[], body: Some("AgentUpdate"), status: Status::Ok, data: Some("Agent") },
+    Route { method: "delete", path: "/agents/{id}", endpoint: Endpoint::DeleteAgent, summary: "Delete an agent", auth: true, scope: Some(Scope::Delete), query: &[], body: None, status: Status::NoContent, data: None },
+
+    Route { method: "get", path: "/tokens", endpoint: Endpoint::Tokens, summary: "API tokens of the logged in user", auth: true, scope: None, query: &[], body: None, status: Status::Ok, data: Some("Token[]") },
+    Route { method: "post", path: "/tokens", endpoint: Endpoint::CreateToken, summary: "Create an API token, the token is only ever in this response", auth: true, scope: None, query: &[], body: Some("NewToken"), status: Status::Created, data: Some("CreatedToken") },
+    Route { method: "delete", path: "/tokens/{id}", endpoint: Endpoint::RevokeToken, summary: "Revoke an API token", auth: true, scope: None, query: &[], body: None, status: Status::NoContent, data: None }
+];
+
+//The id in the path if the template has one
+fn match_path(template: &str, path: &[String]) -> Option<Option<String>> {
+    let template: Vec<_> = template.split('/').filter(|x| !x.is_empty()).collect();
+
+    if template.len() != path.len() {
+        return None;
+    }
+
+    let mut id = None;
+
+    for (part, segment) in template.iter().zip(path) {
+        if *part == "{id}" {
+            id = Some(segment.clone());
+        } else if part != segment {
+            return None;
+        }
+    }
+
+    Some(id)
+}
+
+//Same rules as the v1 routes, unknown combinations are refused
+async fn authorize(route: &Route, id: Option<i32>, caller: &Caller, db: &DatabaseConnection) -> HttpResult<()> {
+    match route.scope {
+        Some(scope) => caller.require_scope(scope)?,
+        None => caller.require_session()?
+    }
+
+    match (route.endpoint, id) {
+        (Endpoint::OpenApi | Endpoint::Game | Endpoint::Languages | Endpoint::Leaderboard, _) => Ok(()),
+        (Endpoint::Login | Endpoint::CurrentSession | Endpoint::Logout, _) => Ok(()),
+        (Endpoint::User | Endpoint::UserStats | Endpoint::Agent, Some(_)) => Ok(()),
+
+        (Endpoint::Sessions | Endpoint::Tokens | Endpoint::CreateToken, _) => caller.require_user(),
+        (Endpoint::RevokeToken, Some(id)) => authorize_token(caller, id, db).await,
+        (Endpoint::RevokeSession, Some(id)) => authorize_session(caller, id, db).await,
+
+        (Endpoint::SubmitAgent, Some(id)) => authorize_submit(caller, id, db).await,
+        (Endpoint::UpdateAgent | Endpoint::DeleteAgent, Some(id)) => authorize_agent(caller, id, db).await,
+
+        _ => Err(WebError::NotFound("Route not found".to_string()))
+    }
+}
+
+fn data_response(status: Status, data: Value) -> Response {
+    let mut res = Response::new();
+    res.set_status(status);
+    res.set_header("Content-Type", "application/json");
+    res.set_body(json!({
+        "data": data
+    }).to_string().into_bytes());
+
+    res
+}
+
+fn no_content() -> Response {
+    let mut res = Response::new();
+    res.set_status(Status::NoContent);
+
+    res
+}
+
+fn json_body(req: &Request) -> HttpResult<Map<String, Value>> {
+    parse_json_as_object(&decode_utf8(req.body.clone())?)
+}
+
+fn parse_colour(colour: &str) -> HttpResult<(u8, u8, u8)> {
+    let invalid = || WebError::InvalidData(format!("Colour '{}' isn't of the form #RRGGBB", colour));
+
+    let hex = colour.strip_prefix('#').ok_or_else(invalid)?;
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+async fn handle(endpoint: Endpoint, id: Option<i32>, req: &Request, caller: &Caller, state: &AppState) -> HttpResult<Response> {
+    match (endpoint, id) {
+        (Endpoint::OpenApi, _) => {
+            let mut res = Response::new();
+            res.set_status(Status::Ok);
+            res.set_header("Content-Type", "application/json");
+            res.set_body(openapi_document().to_string().into_bytes());
+
+            Ok(res)
+        },
+        (Endpoint::Game, _) => Ok(data_response(Status::Ok, get_game_json(state))),
+        (Endpoint::Languages, _) => Ok(data_response(Status::Ok, get_languages_json(&state.languages))),
+        (Endpoint::Leaderboard, _) => Ok(data_response(Status::Ok, get_agent_leaderboard(&state.db).await?)),
+
+        (Endpoint::Login, _) => {
+            let (profile, session, token) = log_in(&json_body(req)?, &state.db).await?;
+
+            let mut res = data_response(Status::Created, json!({
+                "user_id": profile.id,
+                "session_id": session.id,
+                "expires": session.expires
+            }));
+            res.add_cookie(session_cookie(&session, &token));
+
+            Ok(res)
+        },
+        (Endpoint::CurrentSession, _) => Ok(data_response(Status::Ok, json!({
+            "logged_in": caller.user_id.is_some(),
+            "id": caller.user_id,
+            "role": caller.role.map(|x| x.as_str())
+        }))),
+        (Endpoint::Logout, _) => {
+            end_session(req, &state.db).await?;
+
+            let mut res = no_content();
+            res.add_cookie(clear_session_cookie());
+
+            Ok(res)
+        },
+        (Endpoint::Sessions, _) => match find_session(req, &state.db).await? {
+            Some(current) => Ok(data_response(Status::Ok, get_sessions_json(&current, &state.db).await?)),
+            None => Err(WebError::Unauthorized)
+        },
+        (Endpoint::RevokeSession, Some(id)) => {
+            session::Entity::delete_by_id(id).exec(&state.db).await?;
+
+            Ok(no_content())
+        },
+
+        (Endpoint::User, Some(id)) => {
+            let profile = find_user_by_id(id, &state.db).await?;
+
+            Ok(data_response(Status::Ok, get_profile_json(&profile, caller, &state.db).await?))
+        },
+        (Endpoint::UserStats, Some(id)) => {
+            let profile = find_user_by_id(id, &state.db).await?;
+
+            Ok(data_response(Status::Ok, get_stats_json(&profile, &state.db).await?))
+        },
+        (Endpoint::SubmitAgent, Some(id)) => {
+            let profile = find_user_by_id(id, &state.db).await?;
+            let agent_id = submit_agent(state, profile, &json_body(req)?).await?;
+
+            let mut res = data_response(Status::Created, json!({
+                "id": agent_id
+            }));
+            res.set_header("Location", &format!("/{}/agents/{}", PREFIX.join("/"), agent_id));
+
+            Ok(res)
+        },
+
+        (Endpoint::Agent, Some(id)) => {
+            let send_error: bool = req.path.parse_query("error").unwrap_or(false);
+            let send_src: bool = req.path.parse_query("src").unwrap_or(false);
+
+            Ok(data_response(Status::Ok, get_agent_json(id, send_error, send_src, caller, &state.db).await?))
+        },
+        (Endpoint::UpdateAgent, Some(id)) => {
+            let body = json_body(req)?;
+            let colour = parse_colour(body.try_get("colour")?.try_as_str()?)?;
+
+            set_agent_colour(id, colour, &state.db).await?;
+
+            Ok(data_response(Status::Ok, get_agent_json(id, false, false, caller, &state.db).await?))
+        },
+        (Endpoint::DeleteAgent, Some(id)) => {
+            delete_agent(id, &state.db).await?;
+
+            Ok(no_content())
+        },
+
+        (Endpoint::Tokens, _) => {
+            let user_id = caller.user_id.ok_or(WebError::Unauthorized)?;
+
+            Ok(data_response(Status::Ok, get_tokens_json(user_id, &state.db).await?))
+        },
+        (Endpoint::CreateToken, _) => {
+            let user_id = caller.user_id.ok_or(WebError::Unauthorized)?;
+
+            Ok(data_response(Status::Created, new_token(&json_body(req)?, user_id, &state.db).await?))
+        },
+        (Endpoint::RevokeToken, Some(id)) => {
+            api_token::Entity::delete_by_id(id).exec(&state.db).await?;
+
+            Ok(no_content())
+        },
+
+        _ => Err(WebError::NotFound("Route not found".to_string()))
+    }
+}
+
+//Everything under /api/v2, errors are turned into the JSON envelope by the caller
+pub async fn route(req: Request, state: AppState) -> HttpResult<Response> {
+    let path = &req.path.path[PREFIX.len()..];
+    let mut path_exists = false;
+
+    for route in ROUTES {
+        let id = match match_path(route.path, path) {
+            Some(id) => id,
+            None => continue
+        };
+
+        if !route.method.eq_ignore_ascii_case(req.method.get_name()) {
+            path_exists = true;
+            continue;
+        }
+
+        let id = match id {
+            Some(id) => Some(id.parse().map_err(|_| WebError::NotFound(format!("'{}' isn't an id", id)))?),
+            None => None
+        };
+
+        let caller = Caller::from_request(&req, &state.db).await?;
+        authorize(route, id, &caller, &state.db).await?;
+
+        info!("v2 {} {}", route.method, route.path);
+
+        return handle(route.endpoint, id, &req, &caller, &state).await;
+    }
+
+    if path_exists {
+        Err(WebError::InvalidMethod)
+    } else {
+        Err(WebError::NotFound("Route not found".to_string()))
+    }
+}
+
+//"Name[]" is an array of Name
+fn schema_ref(name: &str) -> Value {
+    match name.strip_suffix("[]") {
+        Some(item) => json!({ "type": "array", "items": schema_ref(item) }),
+        None => json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+}
+
+fn schemas() -> Value {
+    json!({
+        "Error": {
+            "type": "object",
+            "required": ["error"],
+            "properties": {
+                "error": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {
+                        "code": {
+                            "type": "string",
+                            "enum": ["invalid_data", "missing_parameter", "not_found", "method_not_allowed", "internal_error", "unauthorized", "forbidden", "rate_limited"]
+                        },
+                        "message": { "type": "string" }
+                    }
+                }
+            }
+        },
+        "Game": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "num_players": { "type": "integer" }
+            }
+        },
+        "Language": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "variants": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "id": { "type": "string" },
+                            "name": { "type": "string" }
+                        }
+                    }
+                }
+            }
+        },
+        "LeaderboardEntry": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+                "rating": { "type": "integer" },
+                "colour": { "type": "string" },
+                "games_played": { "type": "integer" },
+                "owner_id": { "type": "integer" },
+                "owner": { "type": "string" }
+            }
+        },
+        "Login": {
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": { "type": "string" },
+                "password": { "type": "string" }
+            }
+        },
+        "NewSession": {
+            "type": "object",
+            "properties": {
+                "user_id": { "type": "integer" },
+                "session_id": { "type": "integer" },
+                "expires": { "type": "integer", "description": "Unix time in seconds" }
+            }
+        },
+        "Session": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "created": { "type": "integer", "description": "Unix time in seconds" },
+                "expires": { "type": "integer", "description": "Unix time in seconds" },
+                "current": { "type": "boolean" }
+            }
+        },
+        "CurrentSession": {
+            "type": "object",
+            "properties": {
+                "logged_in": { "type": "boolean" },
+                "id": { "type": "integer", "nullable": true },
+                "role": { "type": "string", "nullable": true, "enum": ["spectator", "contestant", "organiser", "admin"] }
+            }
+        },
+        "User": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "username": { "type": "string" },
+                "role": { "type": "string", "enum": ["spectator", "contestant", "organiser", "admin"] },
+                "logged_in": { "type": "boolean", "description": "Whether this is the caller's own account" },
+                "privileged": { "type": "boolean", "description": "Whether the caller can manage this account" },
+                "max_agents": { "type": "integer" },
+                "agents": { "type": "array", "items": schema_ref("Agent") }
+            }
+        },
+        "Stats": {
+            "type": "object",
+            "properties": {
+                "best_rating": { "type": "integer" },
+                "active_agents": { "type": "integer" },
+                "total_games": { "type": "integer" }
+            }
+        },
+        "Agent": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+                "language": { "type": "string" },
+                "variant": { "type": "string" },
+                "rating": { "type": "number" },
+                "games_played": { "type": "integer" },
+                "in_game": { "type": "boolean" },
+                "removed": { "type": "boolean" },
+                "partial": { "type": "boolean", "description": "Still being compiled and validated" },
+                "colour": { "type": "string" },
+                "average_move_time": { "type": "number" },
+                "failed_games": { "type": "integer" },
+                "owner_id": { "type": "integer" },
+                "owner": { "type": "string" },
+                "error": { "type": "string" },
+                "faults": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "kind": { "type": "string" },
+                            "message": { "type": "string" },
+                            "forfeit": { "type": "boolean" },
+                            "time": { "type": "integer" }
+                        }
+                    }
+                },
+                "src": { "type": "string" }
+            }
+        },
+        "AgentSubmission": {
+            "type": "object",
+            "required": ["name", "lang"],
+            "description": "Exactly one of src, files or archive",
+            "properties": {
+                "name": { "type": "string" },
+                "lang": { "type": "string" },
+                "variant": { "type": "string", "description": "Defaults to the language's first variant" },
+                "src": { "type": "string" },
+                "files": { "type": "object", "additionalProperties": { "type": "string" }, "description": "Path to content" },
+                "archive": { "type": "string", "format": "byte" },
+                "format": { "type": "string", "enum": ["zip", "tar"] },
+                "entry": { "type": "string", "description": "Path of the entry point in files or archive" }
+            }
+        },
+        "AgentUpdate": {
+            "type": "object",
+            "required": ["colour"],
+            "properties": {
+                "colour": { "type": "string", "pattern": "^#[0-9A-Fa-f]{6}$" }
+            }
+        },
+        "Created": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" }
+            }
+        },
+        "Token": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string" },
+                "prefix": { "type": "string", "description": "Start of the token, to tell tokens apart" },
+                "scopes": { "type": "array", "items": schema_ref("Scope") },
+                "created": { "type": "integer", "description": "Unix time in seconds" },
+                "last_used": { "type": "integer", "nullable": true, "description": "Unix time in seconds" }
+            }
+        },
+        "NewToken": {
+            "type": "object",
+            "required": ["name", "scopes"],
+            "properties": {
+                "name": { "type": "string" },
+                "scopes": { "type": "array", "items": schema_ref("Scope"), "minItems": 1 }
+            }
+        },
+        "CreatedToken": {
+            "allOf": [
+                schema_ref("Token"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "token": { "type": "string", "description": "Send as 'Authorization: Bearer <token>'" }
+                    }
+                }
+            ]
+        },
+        "Scope": {
+            "type": "string",
+            "enum": Scope::ALL.iter().map(|x| x.as_str()).collect::<Vec<_>>()
+        }
+    })
+}
+
+fn operation(route: &Route) -> Value {
+    let mut parameters = Vec::new();
+
+    if route.path.contains("{id}") {
+        parameters.push(json!({
+            "name": "id",
+            "in": "path",
+            "required": true,
+            "schema": { "type": "integer" }
+        }));
+    }
+
+    for param in route.query {
+        parameters.push(json!({
+            "name": param.name,
+            "in": "query",
+            "required": false,
+            "description": param.description,
+            "schema": { "type": param.schema }
+        }));
+    }
+
+    let success = match (route.endpoint, route.data) {
+        (Endpoint::OpenApi, _) => json!({
+            "description": "OpenAPI document",
+            "content": { "application/json": { "schema": { "type": "object" } } }
+        }),
+        (_, Some(data)) => json!({
+            "description": route.status.get_message(),
+            "content": {
+                "application/json": {
+                    "schema": {
+                        "type": "object",
+                        "required": ["data"],
+                        "properties": { "data": schema_ref(data) }
+                    }
+                }
+            }
+        }),
+        (_, None) => json!({ "description": route.status.get_message() })
+    };
+
+    let mut responses = Map::new();
+    responses.insert(route.status.get_code().to_string(), success);
+    responses.insert("429".to_string(), json!({ "$ref": "#/components/responses/RateLimited" }));
+    responses.insert("default".to_string(), json!({ "$ref": "#/components/responses/Error" }));
+
+    let mut op = json!({
+        "summary": route.summary,
+        "operationId": format!("{:?}", route.endpoint),
+        "parameters": parameters,
+        "responses": responses
+    });
+
+    if let Some(body) = route.body {
+        op["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema_ref(body) } }
+        });
+    }
+
+    if let Some(scope) = route.scope {
+        op["description"] = json!(format!("API tokens need the {} scope", scope));
+    }
+
+    if route.auth {
+        op["security"] = match route.scope {
+            Some(_) => json!([{ "session": [] }, { "token": [] }]),
+            None => json!([{ "session": [] }])
+        };
+    }
+
+    op
+}
+
+pub fn openapi_document() -> Value {
+    let mut paths = Map::new();
+
+    for route in ROUTES {
+        let item = paths.entry(route.path).or_insert_with(|| json!({}));
+        item[route.method] = operation(route);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "SAIS API",
+            "version": "2.0.0"
+        },
+        "servers": [{ "url": format!("/{}", PREFIX.join("/")) }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "responses": {
+                "Error": {
+                    "description": "Any error",
+                    "content": { "application/json": { "schema": schema_ref("Error") } }
+                },
+                "RateLimited": {
+                    "description": "Too many requests from this address or user",
+                    "headers": {
+                        "Retry-After": {
+                            "description": "Seconds until the request can be retried",
+                            "schema": { "type": "integer" }
+                        }
+                    },
+                    "content": { "application/json": { "schema": schema_ref("Error") } }
+                }
+            },
+            "securitySchemes": {
+                "session": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": SESSION_COOKIE
+                },
+                "token": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "API token created on the profile page"
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ActiveValue, Database, EntityTrait};
+
+    use crate::{entities::{agent, user}, web::{api_token::{create_token, Scopes}, http::{Method, Request, RequestPath}, session::create_session, web_errors::WebError}};
+
+    use super::{authorize, match_path, openapi_document, parse_colour, Caller, Scope, ROUTES};
+
+    #[test]
+    fn test_routes_and_document() {
+        let path = |x: &str| x.split('/').filter(|x| !x.is_empty()).map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(match_path("/agents/{id}", &path("/agents/12")), Some(Some("12".to_string())));
+        assert_eq!(match_path("/leaderboard", &path("/leaderboard")), Some(None));
+        assert_eq!(match_path("/agents/{id}", &path("/agents/12/x")), None);
+
+        //No two routes can answer the same request
+        for (i, a) in ROUTES.iter().enumerate() {
+            for b in &ROUTES[i + 1..] {
+                assert!(a.method != b.method || a.path != b.path, "{} {}", a.method, a.path);
+            }
+        }
+
+        let document = openapi_document();
+
+        for route in ROUTES {
+            let op = &document["paths"][route.path][route.method];
+
+            assert!(op.is_object(), "{} {}", route.method, route.path);
+            assert_eq!(op["security"].is_array(), route.auth, "{} {}", route.method, route.path);
+
+            if let Some(data) = route.data {
+                let name = data.trim_end_matches("[]");
+                assert!(document["components"]["schemas"][name].is_object(), "{}", name);
+            }
+        }
+
+        assert_eq!(parse_colour("#1A2b3C").unwrap(), (0x1A, 0x2B, 0x3C));
+        assert!(parse_colour("1A2B3C").is_err());
+        assert!(parse_colour("#1A2B3").is_err());
+        assert!(parse_colour("#1A2B3G").is_err());
+    }
+
+    //Routes the document marks as needing a session have to turn away anonymous callers,
+    //and API tokens only get to the routes of their scopes
+    #[test]
+    fn test_route_auth() {
+        pollster::block_on(async {
+            let db = Database::connect("sqlite::memory:").await.unwrap();
+            Migrator::up(&db, None).await.unwrap();
+
+            let profile = user::ActiveModel {
+                username: ActiveValue::Set("owner".to_string()),
+                password: ActiveValue::Set(String::new()),
+                num_agents_allowed: ActiveValue::Set(1),
+                role: ActiveValue::Set("contestant".to_string()),
+                ..Default::default()
+            };
+            let owner = user::Entity::insert(profile).exec(&db).await.unwrap().last_insert_id;
+
+            let agent = agent::ActiveModel {
+                name: ActiveValue::Set("agent".to_string()),
+                language: ActiveValue::Set("python3".to_string()),
+                directory: ActiveValue::Set(String::new()),
+                owner_id: ActiveValue::Set(Some(owner)),
+                ..Default::default()
+            };
+            agent::Entity::insert(agent).exec(&db).await.unwrap();
+            create_session(&db, owner).await.unwrap();
+            let (_, token) = create_token(&db, owner, "ci", Scopes::from_names(["read"]).unwrap()).await.unwrap();
+
+            let req = Request::new(Method::Get, RequestPath::parse("/").unwrap(), HashMap::new(), vec![], HashMap::new());
+            let anonymous = Caller::from_request(&req, &db).await.unwrap();
+
+            let headers = HashMap::from([("Authorization".to_string(), format!("Bearer {}", token))]);
+            let req = Request::new(Method::Get, RequestPath::parse("/").unwrap(), headers, vec![], HashMap::new());
+            let reader = Caller::from_request(&req, &db).await.unwrap();
+            assert_eq!(reader.user_id, Some(owner));
+
+            let headers = HashMap::from([("Authorization".to_string(), "Bearer sais_wrong".to_string())]);
+            let req = Request::new(Method::Get, RequestPath::parse("/").unwrap(), headers, vec![], HashMap::new());
+            assert!(matches!(Caller::from_request(&req, &db).await, Err(WebError::Unauthorized)));
+
+            for route in ROUTES {
+                let id = route.path.contains("{id}").then_some(1);
+                let result = authorize(route, id, &anonymous, &db).await;
+
+                if route.auth {
+                    assert!(matches!(result, Err(WebError::Unauthorized)), "{} {}", route.method, route.path);
+                } else {
+                    assert!(result.is_ok(), "{} {}", route.method, route.path);
+                }
+
+                let result = authorize(route, id, &reader, &db).await;
+
+                if route.scope == Some(Scope::Read) {
+                    assert!(result.is_ok(), "{} {}", route.method, route.path);
+                } else {
+                    assert!(matches!(result, Err(WebError::Forbidden(_))), "{} {}", route.method, route.path);
+                }
+            }
+        });
+    }
+}
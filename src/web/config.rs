@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::{http::HttpLimits, rate_limit::RateLimits};
+
+pub const SERVER_CONFIG: &str = "res/configs/server.json";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub port: u16,
+    //Serves HTTPS on `port` instead of plain HTTP when set
+    pub tls: Option<TlsConfig>,
+    //Each group left out keeps its default
+    pub rate_limits: RateLimits,
+    //As with rate limits, anything left out keeps its default
+    pub http_limits: HttpLimits
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 8080,
+            tls: None,
+            rate_limits: RateLimits::default(),
+            http_limits: HttpLimits::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    //PEM files, the certificate file can hold the whole chain
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    //Plain HTTP on this port is redirected to HTTPS
+    pub redirect_port: Option<u16>
+}
+
+impl ServerConfig {
+    //Without a config file the defaults are used
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+
+        serde_json::from_str(&file).map_err(|e| format!("Invalid server config {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ServerConfig;
+
+    #[test]
+    fn test_parse_config() {
+        let config: ServerConfig = serde_json::from_str(r#"{
+            "rate_limits": { "auth": { "burst": 3, "per_minute": 6 } },
+            "http_limits": { "max_headers": 20, "idle_timeout": 5 }
+        }"#).unwrap();
+
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.rate_limits.auth.burst, 3);
+        assert_eq!(config.rate_limits.submit.burst, 5);
+        assert_eq!(config.http_limits.max_headers, 20);
+        assert_eq!(config.http_limits.max_line_len, 8 * 1024);
+        assert_eq!(config.http_limits.idle_timeout, Duration::from_secs(5));
+
+        assert!(serde_json::from_str::<ServerConfig>(r#"{ "http_limits": { "max_header": 20 } }"#).is_err());
+        assert!(serde_json::from_str::<ServerConfig>(r#"{ "rate_limits": { "auth": { "burst": 3 } } }"#).is_err());
+    }
+}
@@ -1,24 +1,31 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use async_std::{sync::Mutex, net::TcpStream, io::WriteExt};
+use async_std::sync::Mutex;
 use async_trait::async_trait;
+use futures::{io::WriteHalf, AsyncReadExt, AsyncWriteExt};
 use log::{error, info};
+use serde::Deserialize;
 use serde_json::{Value, json};
 
 use crate::{
     games::Game,
     players::{
         auto_exec::GameRunner,
-        reporting::{EndCallback, StartCallback, UpdateCallback},
+        reporting::{EndCallback, LogCallback, StartCallback, UpdateCallback},
     },
 };
 
-use super::{http::{Request, Response, Status}, web_errors::WebError};
+use super::{http::{write_chunk, Request, Response, Status}, tls::Connection, web_errors::WebError, websocket::{write_message, Message, MessageReader, WsError, CLOSE_NORMAL, CLOSE_UNSUPPORTED}};
+
+//A WebSocket client that's quiet this long gets pinged, and dropped if it stays quiet as long again
+const PING_INTERVAL: Duration = Duration::from_secs(20);
 
 #[derive(Debug)]
 struct GameRecord {
     kind: String,
     players: Vec<i32>,
+    //Who each agent belongs to, the only ones to see its logs
+    owners: Vec<Option<i32>>,
     history: Vec<String>,
 
     spectators: Vec<Arc<Mutex<Spectator>>>
@@ -30,23 +37,35 @@ unsafe impl Sync for GameRecord {}
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
 pub enum GameConnectRequest {
     Any,
-    WithPlayer(i32)
+    WithPlayer(i32),
+    Game(usize)
 }
 
 impl GameConnectRequest {
-    fn matches(&self, game: &GameRecord) -> bool {
+    fn matches(&self, id: usize, game: &GameRecord) -> bool {
         match self {
             Self::Any => true,
-            Self::WithPlayer(x) => game.players.contains(&x)
+            Self::WithPlayer(x) => game.players.contains(&x),
+            Self::Game(x) => *x == id
         }
     }
 }
 
+#[derive(Debug)]
+enum Transport {
+    //Server-sent events, the response ends along with the game
+    EventStream(Connection),
+    //Stays open between games, the read half belongs to the connection's task
+    WebSocket(WriteHalf<Connection>)
+}
+
 #[derive(Debug)]
 struct Spectator {
-    stream: TcpStream,
-    game_request: GameConnectRequest,
+    transport: Transport,
+    game_request: Option<GameConnectRequest>,
     curr_game: Option<usize>,
+    //The logged in user watching, if any
+    viewer: Option<i32>,
 
     error: bool
 }
@@ -60,17 +79,31 @@ impl Spectator {
 
         let s = packet.to_string();
 
-        let mut bytes = "data: ".as_bytes().to_vec();
-        bytes.extend(s.into_bytes());
-        bytes.extend("\n\n".as_bytes());
+        match &mut self.transport {
+            Transport::EventStream(stream) => {
+                let mut bytes = "data: ".as_bytes().to_vec();
+                bytes.extend(s.into_bytes());
+                bytes.extend("\n\n".as_bytes());
 
-        self.stream.write_all(&bytes).await?;
+                write_chunk(stream, &bytes).await?;
+            }
+            Transport::WebSocket(stream) => write_message(stream, &Message::Text(s)).await?
+        }
 
         Ok(())
     }
 
-    pub async fn connect_to_game(&mut self, game: &GameRecord)  -> Result<(), std::io::Error> {
+    //Pings, pongs and closes, event streams have no use for them
+    async fn send_message(&mut self, message: &Message) -> Result<(), std::io::Error> {
+        match &mut self.transport {
+            Transport::EventStream(_) => Ok(()),
+            Transport::WebSocket(stream) => write_message(stream, message).await
+        }
+    }
+
+    pub async fn connect_to_game(&mut self, id: usize, game: &GameRecord)  -> Result<(), std::io::Error> {
         let data = json!({
+            "id": id,
             "kind": game.kind,
             "players": game.players,
             "history": game.history
@@ -86,6 +119,13 @@ impl Spectator {
     pub async fn update_game(&mut self, data: &Value) -> Result<(), std::io::Error> {
         self.send_packet("upd", data).await
     }
+
+    pub async fn agent_log(&mut self, player: usize, message: &str) -> Result<(), std::io::Error> {
+        self.send_packet("log", &json!({
+            "player": player,
+            "message": message
+        })).await
+    }
 }
 
 pub struct SharedInner {
@@ -108,7 +148,7 @@ impl SharedInner {
         }
     }
 
-    pub async fn handle_stream(&mut self, mut stream: TcpStream, request: &Request) {
+    pub async fn handle_stream(&mut self, mut stream: Connection, request: &Request, viewer: Option<i32>) {
         let data = match request.path.get("req").map(|x| urlencoding::decode(&x)) {
             Ok(Ok(x)) => x,
             Ok(Err(e)) => {
@@ -139,9 +179,10 @@ impl SharedInner {
         };
 
         let spectator = Arc::new(Mutex::new(Spectator {
-            stream,
-            game_request: req,
+            transport: Transport::EventStream(stream),
+            game_request: Some(req),
             curr_game: None,
+            viewer,
             error: false
         }));
 
@@ -154,24 +195,61 @@ impl SharedInner {
         response.set_header("Content-Type", "text/event-stream");
         response.set_header("Cache-Control", "no-cache");
         response.set_header("Connection", "keep-alive");
+        response.set_chunked();
+
+        if let Transport::EventStream(stream) = &mut lock.transport {
+            let _ = response.write_head_async(stream).await;
+        }
 
-        let _ = response.write_async(&mut lock.stream).await;
+        if let Err(e) = self.join_game(&spectator, &mut lock).await {
+            error!("Error while connecting to game {:?}", e);
+            lock.error = true;
+        }
+    }
 
-        for (id, game) in &mut self.games {
-            if lock.game_request.matches(game) {
-                if let Err(e) = lock.connect_to_game(game).await {
-                    let response = WebError::InternalServerError(format!("Error while conncting to game {:?}", e)).into_response();
-                    let _ = response.write_async(&mut lock.stream).await;
-                    return;
-                }
+    //Joins the first running game the spectator asked for, false if there isn't one
+    async fn join_game(&mut self, spectator: &Arc<Mutex<Spectator>>, lock: &mut Spectator) -> Result<bool, std::io::Error> {
+        let request = match lock.game_request {
+            Some(x) => x,
+            None => return Ok(false)
+        };
 
+        for (id, game) in &mut self.games {
+            if request.matches(*id, game) {
+                lock.connect_to_game(*id, game).await?;
                 lock.curr_game = Some(*id);
 
                 game.spectators.push(spectator.clone());
 
-                break;
+                return Ok(true);
             }
         }
+
+        Ok(false)
+    }
+
+    //Leaves the current game for whatever is asked for next, None just leaves
+    async fn subscribe(&mut self, spectator: &Arc<Mutex<Spectator>>, request: Option<GameConnectRequest>) -> Result<bool, std::io::Error> {
+        let mut lock = spectator.lock().await;
+
+        if let Some(game) = lock.curr_game.take().and_then(|id| self.games.get_mut(&id)) {
+            game.spectators.retain(|x| !Arc::ptr_eq(x, spectator));
+        }
+
+        lock.game_request = request;
+
+        self.join_game(spectator, &mut lock).await
+    }
+
+    fn games_json(&self) -> Value {
+        let mut ids: Vec<_> = self.games.keys().collect();
+        ids.sort();
+
+        ids.into_iter().map(|id| json!({
+            "id": id,
+            "kind": self.games[id].kind,
+            "players": self.games[id].players
+        })).collect()
     }
 
     async fn check_streams(&mut self) {
@@ -182,12 +260,13 @@ impl SharedInner {
         }
     }
 
-    async fn start_game(&mut self, id: usize, name: &str, players: &[i32]) {
+    async fn start_game(&mut self, id: usize, name: &str, players: &[i32], owners: &[Option<i32>]) {
         self.games.insert(
             id,
             GameRecord {
                 kind: name.to_string(),
                 players: players.to_vec(),
+                owners: owners.to_vec(),
                 history: vec![],
                 spectators: vec![]
             },
@@ -205,9 +284,9 @@ impl SharedInner {
                 continue;
             }
 
-            if lock.game_request.matches(&self.games.get(&id).unwrap()) && lock.curr_game.is_none() {
+            if lock.game_request.is_some_and(|x| x.matches(id, &self.games[&id])) && lock.curr_game.is_none() {
                 println!("Connecting to game!");
-                if let Err(e) = lock.connect_to_game(self.games.get(&id).unwrap()).await {
+                if let Err(e) = lock.connect_to_game(id, self.games.get(&id).unwrap()).await {
                     error!("WS Error {:?}", e);
                     lock.error = true;
                 }
@@ -238,8 +317,12 @@ impl SharedInner {
                 }
 
                 lock.curr_game = None;
-                let _ = lock.stream.shutdown(std::net::Shutdown::Both);
-                lock.error = true;
+
+                //WebSockets stay around for the next game they asked for
+                if let Transport::EventStream(stream) = &lock.transport {
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                    lock.error = true;
+                }
             }
         }
 
@@ -264,6 +347,144 @@ impl SharedInner {
             }
         }
     }
+
+    //Not kept in the history, so later spectators that aren't the owner can't see it
+    async fn agent_log(&mut self, id: usize, player: usize, message: &str) {
+        if let Some(record) = self.games.get(&id) {
+            let owner = match record.owners.get(player) {
+                Some(Some(x)) => *x,
+                _ => return
+            };
+
+            for spectator in &record.spectators {
+                let mut lock = spectator.lock().await;
+
+                if lock.error || lock.viewer != Some(owner) {
+                    continue;
+                }
+
+                if let Err(e) = lock.agent_log(player, message).await {
+                    error!("WS Error: {:?}", e);
+                    lock.error = true;
+                }
+            }
+        }
+    }
+}
+
+//What WebSocket spectators send, as {"cmd": "subscribe", "game": 4} and so on
+#[derive(Deserialize, Debug)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Command {
+    //Answered with the running games
+    List,
+    Subscribe { game: usize },
+    //Every game the agent plays, or every game there is without one
+    Follow { agent: Option<i32> },
+    Unsubscribe
+}
+
+async fn run_command(reporter: &Mutex<SharedInner>, spectator: &Arc<Mutex<Spectator>>, text: &str) -> Result<(), std::io::Error> {
+    let command = match serde_json::from_str::<Command>(text) {
+        Ok(x) => x,
+        Err(e) => return spectator.lock().await.send_packet("error", &json!(format!("Invalid command: {}", e))).await
+    };
+
+    let mut inner = reporter.lock().await;
+
+    match command {
+        Command::List => {
+            let games = inner.games_json();
+            spectator.lock().await.send_packet("games", &games).await
+        }
+        Command::Subscribe { game } if !inner.games.contains_key(&game) => {
+            spectator.lock().await.send_packet("error", &json!(format!("Game {} isn't running", game))).await
+        }
+        Command::Subscribe { game } => inner.subscribe(spectator, Some(GameConnectRequest::Game(game))).await.map(|_| ()),
+        Command::Follow { agent } => {
+            let request = agent.map(GameConnectRequest::WithPlayer).unwrap_or(GameConnectRequest::Any);
+            inner.subscribe(spectator, Some(request)).await.map(|_| ())
+        }
+        Command::Unsubscribe => inner.subscribe(spectator, None).await.map(|_| ())
+    }
+}
+
+//Runs a WebSocket after its handshake, until the client leaves
+pub async fn spectate_websocket(reporter: Arc<Mutex<SharedInner>>, stream: Connection, buffered: Vec<u8>, viewer: Option<i32>) {
+    let (mut read_half, write_half) = stream.split();
+
+    let spectator = Arc::new(Mutex::new(Spectator {
+        transport: Transport::WebSocket(write_half),
+        game_request: None,
+        curr_game: None,
+        viewer,
+        error: false
+    }));
+
+    {
+        let mut inner = reporter.lock().await;
+        inner.spectators.push(spectator.clone());
+
+        let games = inner.games_json();
+        let mut lock = spectator.lock().await;
+
+        if lock.send_packet("games", &games).await.is_err() {
+            lock.error = true;
+            return;
+        }
+    }
+
+    let mut reader = MessageReader::new(buffered);
+    let mut pinged = false;
+
+    let close_code = loop {
+        let message = match async_std::future::timeout(PING_INTERVAL, reader.read_message(&mut read_half)).await {
+            Ok(Ok(message)) => message,
+            Ok(Err(WsError::Close(code, reason))) => {
+                info!("Closing WebSocket: {}", reason);
+                break Some(code);
+            }
+            Ok(Err(WsError::Io(_))) => break None,
+            //Nothing back since the last ping
+            Err(_) if pinged => break None,
+            Err(_) => {
+                pinged = true;
+
+                if spectator.lock().await.send_message(&Message::Ping(vec![])).await.is_err() {
+                    break None;
+                }
+
+                continue;
+            }
+        };
+
+        //Anything at all shows the client is still there
+        pinged = false;
+
+        let result = match message {
+            Message::Text(text) => run_command(&reporter, &spectator, &text).await,
+            Message::Binary(_) => break Some(CLOSE_UNSUPPORTED),
+            Message::Ping(data) => spectator.lock().await.send_message(&Message::Pong(data)).await,
+            Message::Pong(_) => Ok(()),
+            Message::Close(_) => break Some(CLOSE_NORMAL)
+        };
+
+        if result.is_err() {
+            break None;
+        }
+    };
+
+    let mut lock = spectator.lock().await;
+
+    if let Some(code) = close_code {
+        let _ = lock.send_message(&Message::Close(Some(code))).await;
+    }
+
+    if let Transport::WebSocket(stream) = &mut lock.transport {
+        let _ = stream.close().await;
+    }
+
+    lock.error = true;
 }
 
 pub struct GameReporter {
@@ -276,8 +497,8 @@ unsafe impl Sync for GameReporter {}
 struct ReporterStartCallback(Arc<Mutex<SharedInner>>);
 #[async_trait]
 impl StartCallback for ReporterStartCallback {
-    async fn call(&mut self, id: usize, name: &str, players: &[i32]) {
-        self.0.lock().await.start_game(id, name, players).await;
+    async fn call(&mut self, id: usize, name: &str, players: &[i32], owners: &[Option<i32>]) {
+        self.0.lock().await.start_game(id, name, players, owners).await;
     }
 }
 
@@ -297,6 +518,14 @@ impl UpdateCallback for ReporterUpdateCallback {
     }
 }
 
+struct ReporterLogCallback(Arc<Mutex<SharedInner>>);
+#[async_trait]
+impl LogCallback for ReporterLogCallback {
+    async fn call(&mut self, id: usize, player: usize, message: &str) {
+        self.0.lock().await.agent_log(id, player, message).await;
+    }
+}
+
 impl GameReporter {
     pub async fn new<T: Game>(executor: &GameRunner<T>) -> Self {
         let res = Self {
@@ -321,6 +550,12 @@ impl GameReporter {
             .add_update_callback(Box::new(ReporterUpdateCallback(copy)))
             .await;
 
+        let copy = res.inner.clone();
+        executor
+            .reporting
+            .add_log_callback(Box::new(ReporterLogCallback(copy)))
+            .await;
+
         res
     }
 
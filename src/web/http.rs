@@ -1,5 +1,6 @@
-use std::{collections::HashMap, fmt, str::FromStr, string::FromUtf8Error};
+use std::{collections::HashMap, fmt, str::FromStr, string::FromUtf8Error, time::Duration};
 
+use serde::{Deserialize, Deserializer};
 use url_encor::Encoder;
 
 use crate::util::asyncio::{AsyncReaderWrapper, AsyncWriterWrapper};
@@ -391,6 +392,112 @@ impl RequestPath {
     }
 }
 
+const MAX_BLANK_LINES: usize = 8;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpLimits {
+    pub max_headers: usize,
+    //Longest request line, header or chunk size line, in bytes
+    pub max_line_len: usize,
+    pub max_body_size: usize,
+    //How long a connection can sit between requests before it's closed, in seconds in the config
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub idle_timeout: Duration,
+    //How long a request can take to arrive once it's started, so slow clients can't hold on to connections
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub request_timeout: Duration,
+}
+
+fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+}
+
+impl Default for HttpLimits {
+    fn default() -> Self {
+        Self {
+            max_headers: 100,
+            max_line_len: 8 * 1024,
+            //Agent submissions are sent as JSON, so this has to fit a whole storage quota
+            max_body_size: 32 * 1024 * 1024,
+            idle_timeout: Duration::from_secs(30),
+            //Long enough to upload a body of the largest size on a slow connection
+            request_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    fn parse(version: &str) -> Result<Self, HttpError> {
+        match version {
+            "HTTP/1.0" => Ok(Version::Http10),
+            "HTTP/1.1" => Ok(Version::Http11),
+            _ if version.starts_with("HTTP/") => Err(HttpError::Http(Status::HttpVersionNotSupported, None)),
+            _ => Err(HttpError::Http(Status::BadRequest, Some("Invalid HTTP version".to_string()))),
+        }
+    }
+}
+
+fn bad_request(message: &str) -> HttpError {
+    HttpError::Http(Status::BadRequest, Some(message.to_string()))
+}
+
+//Header names are tokens, see RFC 9110 section 5.6.2
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+fn parse_header(line: &[u8]) -> Result<(String, String), HttpError> {
+    if line.starts_with(b" ") || line.starts_with(b"\t") {
+        return Err(bad_request("Folded headers aren't supported"));
+    }
+
+    let line = std::str::from_utf8(line).map_err(|_| bad_request("Header isn't valid UTF-8"))?;
+    let (name, value) = line.split_once(':').ok_or_else(|| bad_request("Invalid header"))?;
+
+    if name.is_empty() || !name.chars().all(is_token_char) {
+        return Err(bad_request("Invalid header name"));
+    }
+
+    let value = value.trim_matches([' ', '\t']);
+
+    if value.chars().any(|x| x.is_control() && x != '\t') {
+        return Err(bad_request("Invalid header value"));
+    }
+
+    Ok((name.to_ascii_lowercase(), value.to_string()))
+}
+
+//Case-insensitive, since names from the parser are lowercase but hand built maps aren't
+fn find_header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a String> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+//"a=1; b=2" into its pairs. Values can hold '=' themselves, pairs without one are skipped
+fn parse_cookies(header: &str) -> HashMap<String, String> {
+    header
+        .split(';')
+        .filter_map(|cookie| cookie.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+enum Framing {
+    Empty,
+    Length(usize),
+    Chunked,
+    UntilClose,
+}
+
 #[derive(Debug, Clone)]
 struct HttpMessage {
     first_line: String,
@@ -398,103 +505,363 @@ struct HttpMessage {
     body: Vec<u8>,
 }
 
-impl HttpMessage {
-    pub fn new(first_line: String, headers: HashMap<String, String>, body: Vec<u8>) -> Self {
+//Reads messages off one connection. Anything read past the end of a message is kept for the next,
+//so pipelined requests aren't lost
+pub struct HttpReader {
+    limits: HttpLimits,
+    buf: Vec<u8>,
+    buf_pos: usize,
+    buf_end: usize,
+}
+
+impl HttpReader {
+    pub fn new(limits: HttpLimits) -> Self {
         Self {
-            first_line,
-            headers,
-            body,
+            limits,
+            buf: vec![0; 4096],
+            buf_pos: 0,
+            buf_end: 0,
         }
     }
 
-    pub async fn parse_async<T: async_std::io::Read + Unpin>(
+    //False if the stream has ended
+    async fn fill<T: async_std::io::Read + Unpin>(&mut self, stream: &mut T) -> Result<bool, HttpError> {
+        if self.buf_pos == self.buf_end {
+            self.buf_pos = 0;
+            self.buf_end = AsyncReaderWrapper::new(stream).read(&mut self.buf).await?;
+        }
+
+        Ok(self.buf_end > 0)
+    }
+
+    //A line without its line ending, None if the stream ended before it started
+    async fn read_line<T: async_std::io::Read + Unpin>(
+        &mut self,
         stream: &mut T,
-    ) -> Result<Self, HttpError> {
-        let mut stream = AsyncReaderWrapper::new(stream);
+        too_long: Status,
+    ) -> Result<Option<Vec<u8>>, HttpError> {
+        let mut line = Vec::new();
+
+        loop {
+            if !self.fill(stream).await? {
+                if line.is_empty() {
+                    return Ok(None);
+                }
+
+                return Err(bad_request("Connection closed mid line"));
+            }
+
+            let available = &self.buf[self.buf_pos..self.buf_end];
+
+            let (taken, done) = match available.iter().position(|x| *x == b'\n') {
+                Some(idx) => (&available[..idx], true),
+                None => (available, false),
+            };
+
+            if line.len() + taken.len() > self.limits.max_line_len {
+                return Err(HttpError::Http(too_long, Some("Line too long".to_string())));
+            }
+
+            line.extend_from_slice(taken);
+            self.buf_pos += taken.len() + done as usize;
+
+            if done {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    async fn read_exact<T: async_std::io::Read + Unpin>(
+        &mut self,
+        stream: &mut T,
+        body: &mut Vec<u8>,
+        mut len: usize,
+    ) -> Result<(), HttpError> {
+        while len > 0 {
+            if !self.fill(stream).await? {
+                return Err(bad_request("Connection closed mid body"));
+            }
+
+            let taken = (self.buf_end - self.buf_pos).min(len);
+
+            body.extend_from_slice(&self.buf[self.buf_pos..self.buf_pos + taken]);
+            self.buf_pos += taken;
+            len -= taken;
+        }
+
+        Ok(())
+    }
+
+    async fn read_chunked<T: async_std::io::Read + Unpin>(&mut self, stream: &mut T) -> Result<Vec<u8>, HttpError> {
+        let mut body = Vec::new();
+
+        loop {
+            let line = self
+                .read_line(stream, Status::BadRequest)
+                .await?
+                .ok_or_else(|| bad_request("Connection closed mid body"))?;
+
+            //Chunk extensions come after a ';' and are ignored
+            let size = line.split(|x| *x == b';').next().unwrap_or(&[]);
+            let size = std::str::from_utf8(size).unwrap_or("").trim_matches([' ', '\t']);
+
+            if size.is_empty() || !size.chars().all(|x| x.is_ascii_hexdigit()) {
+                return Err(bad_request("Invalid chunk size"));
+            }
 
-        let mut buf = [0u8; 4096];
-        let mut buf_pos = 0;
-        let mut buf_end = 0;
+            let size = usize::from_str_radix(size, 16).map_err(|_| HttpError::Http(Status::PayloadTooLarge, None))?;
 
-        let mut lines = Vec::new();
+            if size == 0 {
+                break;
+            }
 
-        let mut curr_line = String::new();
+            if body.len().saturating_add(size) > self.limits.max_body_size {
+                return Err(HttpError::Http(Status::PayloadTooLarge, None));
+            }
+
+            self.read_exact(stream, &mut body, size).await?;
+
+            match self.read_line(stream, Status::BadRequest).await? {
+                Some(line) if line.is_empty() => {}
+                _ => return Err(bad_request("Chunk is longer than its size")),
+            }
+        }
+
+        //Trailers aren't used, but still have to be read off the stream
+        let mut trailers = 0;
 
         loop {
-            if buf_pos == buf_end {
-                buf_pos = 0;
-                buf_end = stream.read(&mut buf).await?;
+            let line = self
+                .read_line(stream, Status::RequestHeaderFieldsTooLarge)
+                .await?
+                .ok_or_else(|| bad_request("Connection closed mid trailers"))?;
+
+            if line.is_empty() {
+                break;
             }
 
-            if buf_end == 0 {
-                return Err(HttpError::Http(
-                    Status::BadRequest,
-                    Some("Empty request".to_string()),
-                ));
+            trailers += 1;
+
+            if trailers > self.limits.max_headers {
+                return Err(HttpError::Http(Status::RequestHeaderFieldsTooLarge, None));
             }
 
-            let c = buf[buf_pos];
-            buf_pos += 1;
+            parse_header(&line)?;
+        }
 
-            if c == b'\r' {
-                continue;
+        Ok(body)
+    }
+
+    fn framing(&self, headers: &HashMap<String, String>, is_request: bool) -> Result<Framing, HttpError> {
+        let content_length = find_header(headers, "Content-Length");
+
+        if let Some(encoding) = find_header(headers, "Transfer-Encoding") {
+            //Both together is how requests get smuggled past proxies
+            if content_length.is_some() {
+                return Err(bad_request("Both Content-Length and Transfer-Encoding are set"));
+            }
+
+            let codings: Vec<_> = encoding.split(',').map(|x| x.trim().to_ascii_lowercase()).collect();
+
+            return match codings.last().map(|x| x.as_str()) {
+                Some("chunked") if codings.len() == 1 => Ok(Framing::Chunked),
+                Some("chunked") => Err(HttpError::Http(Status::NotImplemented, Some("Only chunked transfer encoding is supported".to_string()))),
+                _ => Err(bad_request("Transfer-Encoding doesn't end with chunked")),
+            };
+        }
+
+        if let Some(content_length) = content_length {
+            //Repeated headers are joined with commas, which is fine as long as they agree
+            let mut lengths = content_length.split(',').map(|x| x.trim());
+            let first = lengths.next().unwrap_or("");
+
+            if first.is_empty() || !first.chars().all(|x| x.is_ascii_digit()) || lengths.any(|x| x != first) {
+                return Err(bad_request("Invalid Content-Length"));
+            }
+
+            let length = first.parse::<usize>().map_err(|_| HttpError::Http(Status::PayloadTooLarge, None))?;
+
+            if length > self.limits.max_body_size {
+                return Err(HttpError::Http(Status::PayloadTooLarge, None));
             }
 
-            if c == b'\n' {
-                if curr_line.is_empty() {
+            return Ok(Framing::Length(length));
+        }
+
+        if is_request {
+            Ok(Framing::Empty)
+        } else {
+            Ok(Framing::UntilClose)
+        }
+    }
+
+    //None if the stream ended, or sat idle for too long, before a message started
+    async fn read_message<T: async_std::io::Read + Unpin>(
+        &mut self,
+        stream: &mut T,
+        is_request: bool,
+    ) -> Result<Option<HttpMessage>, HttpError> {
+        match async_std::future::timeout(self.limits.idle_timeout, self.fill(stream)).await {
+            Ok(res) => res?,
+            Err(_) => return Ok(None),
+        };
+
+        //Whatever was buffered is lost on a timeout, but the connection is closed after one anyway
+        match async_std::future::timeout(self.limits.request_timeout, self.read_started_message(stream, is_request)).await {
+            Ok(res) => res,
+            Err(_) => Err(HttpError::Http(Status::RequestTimeout, None)),
+        }
+    }
+
+    async fn read_started_message<T: async_std::io::Read + Unpin>(
+        &mut self,
+        stream: &mut T,
+        is_request: bool,
+    ) -> Result<Option<HttpMessage>, HttpError> {
+        //Stray line endings between kept alive messages are allowed
+        let mut first_line = Vec::new();
+
+        for _ in 0..MAX_BLANK_LINES {
+            match self.read_line(stream, Status::UriTooLong).await? {
+                Some(line) if line.is_empty() => continue,
+                Some(line) => {
+                    first_line = line;
                     break;
                 }
-
-                lines.push(curr_line);
-                curr_line = String::new();
-            } else {
-                curr_line.push(c as char);
+                None => return Ok(None),
             }
         }
 
-        let mut headers = HashMap::new();
+        if first_line.is_empty() {
+            return Err(bad_request("Empty request"));
+        }
 
-        for line in lines.iter().skip(1) {
-            let colon_idx = line.find(':').ok_or(HttpError::Http(
-                Status::BadRequest,
-                Some("Invalid header".to_string()),
-            ))?;
+        let first_line = String::from_utf8(first_line).map_err(|_| bad_request("First line isn't valid UTF-8"))?;
 
-            headers.insert(
-                line[..colon_idx].to_string(),
-                line[colon_idx + 1..].trim().to_string(),
-            );
+        let mut headers: HashMap<String, String> = HashMap::new();
+        let mut num_headers = 0;
+
+        loop {
+            let line = self
+                .read_line(stream, Status::RequestHeaderFieldsTooLarge)
+                .await?
+                .ok_or_else(|| bad_request("Connection closed mid headers"))?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            num_headers += 1;
+
+            if num_headers > self.limits.max_headers {
+                return Err(HttpError::Http(Status::RequestHeaderFieldsTooLarge, Some("Too many headers".to_string())));
+            }
+
+            let (name, value) = parse_header(&line)?;
+
+            headers
+                .entry(name)
+                .and_modify(|x| {
+                    x.push_str(", ");
+                    x.push_str(&value);
+                })
+                .or_insert(value);
         }
 
-        let mut body = Vec::new();
+        //The server never sends HEAD requests, so responses to them don't need handling
+        let bodiless = !is_request
+            && first_line
+                .split(' ')
+                .nth(1)
+                .is_some_and(|x| x.starts_with('1') || x == "204" || x == "304");
+
+        let body = match self.framing(&headers, is_request)? {
+            _ if bodiless => Vec::new(),
+            Framing::Empty => Vec::new(),
+            Framing::Length(length) => {
+                let mut body = Vec::with_capacity(length.min(self.buf.len()));
+                self.read_exact(stream, &mut body, length).await?;
+                body
+            }
+            Framing::Chunked => self.read_chunked(stream).await?,
+            Framing::UntilClose => {
+                let mut body = Vec::new();
 
-        if let Some(content_length) = headers.get("Content-Length") {
-            let content_length = content_length.parse::<usize>().map_err(|_| {
-                HttpError::Http(
-                    Status::BadRequest,
-                    Some("Invalid Content-Length".to_string()),
-                )
-            })?;
+                while self.fill(stream).await? {
+                    if body.len() + self.buf_end - self.buf_pos > self.limits.max_body_size {
+                        return Err(HttpError::Http(Status::PayloadTooLarge, None));
+                    }
 
-            body.resize(content_length, 0);
+                    body.extend_from_slice(&self.buf[self.buf_pos..self.buf_end]);
+                    self.buf_pos = self.buf_end;
+                }
+
+                body
+            }
+        };
 
-            //Read remaining from buffer
-            let body_in_buf = (buf_end - buf_pos).min(content_length);
+        Ok(Some(HttpMessage {
+            first_line,
+            headers,
+            body,
+        }))
+    }
 
-            body[..body_in_buf].copy_from_slice(&buf[buf_pos..buf_pos + body_in_buf]);
+    //None once the client is done with the connection
+    pub async fn read_request<T: async_std::io::Read + Unpin>(
+        &mut self,
+        stream: &mut T,
+    ) -> Result<Option<Request>, HttpError> {
+        match self.read_message(stream, true).await? {
+            Some(message) => Request::from_message(message).map(Some),
+            None => Ok(None),
+        }
+    }
 
-            //Read remaining from stream
-            stream.read_exact(&mut body[body_in_buf..]).await?;
+    pub async fn read_response<T: async_std::io::Read + Unpin>(
+        &mut self,
+        stream: &mut T,
+    ) -> Result<Option<Response>, HttpError> {
+        match self.read_message(stream, false).await? {
+            Some(message) => Response::from_message(message).map(Some),
+            None => Ok(None),
         }
+    }
 
-        Ok(Self::new(lines[0].to_string(), headers, body))
+    //Bytes read past the last message, for connections that switch protocols
+    pub fn into_buffered(self) -> Vec<u8> {
+        self.buf[self.buf_pos..self.buf_end].to_vec()
     }
 }
 
+//An empty chunk ends the body
+pub async fn write_chunk<T: async_std::io::Write + Unpin>(stream: &mut T, data: &[u8]) -> Result<(), HttpError> {
+    let mut stream = AsyncWriterWrapper::new(stream);
+
+    if data.is_empty() {
+        stream.write_all(b"0\r\n\r\n").await?;
+    } else {
+        let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(b"\r\n");
+
+        stream.write_all(&chunk).await?;
+    }
+
+    stream.flush().await?;
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct Request {
     pub method: Method,
     pub path: RequestPath,
+    pub version: Version,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
     pub cookies: HashMap<String, String>,
@@ -511,17 +878,24 @@ impl Request {
         Self {
             method,
             path,
+            version: Version::Http11,
             headers,
             body,
             cookies,
         }
     }
 
+    //Reads a single request, use a HttpReader to read more than one off a connection
     pub async fn parse_async<T: async_std::io::Read + Unpin>(
         stream: &mut T,
     ) -> Result<Self, HttpError> {
-        let message = HttpMessage::parse_async(stream).await?;
+        HttpReader::new(HttpLimits::default())
+            .read_request(stream)
+            .await?
+            .ok_or_else(|| bad_request("Empty request"))
+    }
 
+    fn from_message(message: HttpMessage) -> Result<Self, HttpError> {
         let request_line = message.first_line.split(' ').collect::<Vec<_>>();
 
         if request_line.len() != 3 {
@@ -545,39 +919,33 @@ impl Request {
         };
 
         let path = RequestPath::parse(request_line[1])?;
+        let version = Version::parse(request_line[2])?;
 
-        let cookies = message
-            .headers
-            .get("Cookie")
-            .map(|cookie_header| {
-                let mut cookies = HashMap::new();
-
-                for cookie in cookie_header.split(';') {
-                    let mut cookie_parts = cookie.split('=');
-
-                    let key = match cookie_parts.next() {
-                        Some(key) => key,
-                        None => continue,
-                    };
-                    let value = match cookie_parts.next() {
-                        Some(value) => value,
-                        None => continue,
-                    };
-
-                    cookies.insert(key.trim().to_string(), value.trim().to_string());
-                }
+        let cookies = find_header(&message.headers, "Cookie")
+            .map(|cookie_header| parse_cookies(cookie_header))
+            .unwrap_or_default();
 
-                cookies
-            })
-            .unwrap_or(HashMap::new());
+        let mut request = Self::new(method, path, message.headers, message.body, cookies);
+        request.version = version;
 
-        Ok(Self::new(
-            method,
-            path,
-            message.headers,
-            message.body,
-            cookies,
-        ))
+        Ok(request)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&String> {
+        find_header(&self.headers, name)
+    }
+
+    //HTTP/1.1 connections stay open unless asked not to, HTTP/1.0 ones only when asked to
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.header("Connection")
+                .is_some_and(|x| x.split(',').any(|x| x.trim().eq_ignore_ascii_case(option)))
+        };
+
+        match self.version {
+            Version::Http10 => has_option("keep-alive"),
+            Version::Http11 => !has_option("close"),
+        }
     }
 
     pub async fn write_async<T: async_std::io::Write + Unpin>(
@@ -651,26 +1019,7 @@ impl Request {
     }
 
     pub fn get_cookies(&self) -> HashMap<String, String> {
-        let mut cookies = HashMap::new();
-
-        if let Some(cookie_header) = self.headers.get("Cookie") {
-            for cookie in cookie_header.split(';') {
-                let mut cookie_parts = cookie.split('=');
-
-                let key = match cookie_parts.next() {
-                    Some(key) => key,
-                    None => continue,
-                };
-                let value = match cookie_parts.next() {
-                    Some(value) => value,
-                    None => continue,
-                };
-
-                cookies.insert(key.trim().to_string(), value.trim().to_string());
-            }
-        }
-
-        cookies
+        self.header("Cookie").map(|x| parse_cookies(x)).unwrap_or_default()
     }
 }
 
@@ -678,7 +1027,10 @@ impl Request {
 pub struct Response {
     pub status: Status,
     pub headers: HashMap<String, String>,
+    //Set-Cookie is the one header that can appear more than once
+    pub cookies: Vec<String>,
     pub body: Vec<u8>,
+    pub chunked: bool,
 }
 
 impl Response {
@@ -686,7 +1038,9 @@ impl Response {
         Self {
             status: Status::Ok,
             headers: HashMap::new(),
+            cookies: Vec::new(),
             body: Vec::new(),
+            chunked: false,
         }
     }
 
@@ -718,11 +1072,34 @@ impl Response {
     pub fn set_body(&mut self, body: Vec<u8>) {
         self.body = body;
 
-        self.set_header("Content-Length", &self.body.len().to_string());
+        if !self.chunked {
+            self.set_header("Content-Length", &self.body.len().to_string());
+        }
     }
 
-    pub async fn write_async<T: async_std::io::Write + Unpin>(
-        self,
+    pub fn add_cookie(&mut self, cookie: String) {
+        self.cookies.push(cookie);
+    }
+
+    pub fn header(&self, name: &str) -> Option<&String> {
+        find_header(&self.headers, name)
+    }
+
+    //Sends the body with chunked transfer encoding. Streamed responses write their head with
+    //write_head_async and follow it with write_chunk
+    pub fn set_chunked(&mut self) {
+        self.chunked = true;
+        self.headers.retain(|key, _| !key.eq_ignore_ascii_case("Content-Length"));
+    }
+
+    fn has_body(&self) -> bool {
+        let code = self.status.get_code();
+
+        !(100..200).contains(&code) && code != 204 && code != 304
+    }
+
+    pub async fn write_head_async<T: async_std::io::Write + Unpin>(
+        &self,
         stream: &mut T,
     ) -> Result<(), HttpError> {
         let mut stream = AsyncWriterWrapper::new(stream);
@@ -744,31 +1121,80 @@ impl Response {
                 .await?;
         }
 
+        for cookie in self.cookies.iter() {
+            stream
+                .write_all(format!("Set-Cookie: {}\r\n", cookie).as_bytes())
+                .await?;
+        }
+
+        //Without a length or chunking a kept alive connection couldn't tell where the body ends
+        if self.chunked {
+            stream.write_all(b"Transfer-Encoding: chunked\r\n").await?;
+        } else if self.has_body() && self.header("Content-Length").is_none() {
+            stream
+                .write_all(format!("Content-Length: {}\r\n", self.body.len()).as_bytes())
+                .await?;
+        }
+
         stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+
+        Ok(())
+    }
+
+    pub async fn write_async<T: async_std::io::Write + Unpin>(
+        self,
+        stream: &mut T,
+    ) -> Result<(), HttpError> {
+        self.write_head_async(stream).await?;
 
-        stream.write_all(&self.body).await?;
+        if !self.has_body() {
+            return Ok(());
+        }
+
+        if self.chunked {
+            if !self.body.is_empty() {
+                write_chunk(stream, &self.body).await?;
+            }
+
+            write_chunk(stream, &[]).await?;
+        } else {
+            let mut stream = AsyncWriterWrapper::new(stream);
+
+            stream.write_all(&self.body).await?;
+            stream.flush().await?;
+        }
 
         Ok(())
     }
 
+    //Reads a single response, use a HttpReader to read more than one off a connection
     pub async fn parse_async<T: async_std::io::Read + Unpin>(
         stream: &mut T,
     ) -> Result<Self, HttpError> {
-        let message = HttpMessage::parse_async(stream).await?;
+        HttpReader::new(HttpLimits::default())
+            .read_response(stream)
+            .await?
+            .ok_or_else(|| bad_request("Empty response"))
+    }
 
+    fn from_message(message: HttpMessage) -> Result<Self, HttpError> {
+        //The reason phrase can have spaces in it
         let status_line = message
             .first_line
-            .split(' ')
+            .splitn(3, ' ')
             .map(|x| x.trim().to_string())
             .collect::<Vec<_>>();
 
-        if status_line.len() != 3 {
+        if status_line.len() < 2 {
             return Err(HttpError::Http(
                 Status::BadRequest,
                 Some("Invalid status line".to_string()),
             ));
         }
 
+        Version::parse(&status_line[0])?;
+
         let status = &status_line[1];
         let status = u16::from_str_radix(&status, 10).map_err(|_| {
             HttpError::Http(
@@ -850,7 +1276,292 @@ impl Response {
         Ok(Self {
             status,
             headers: message.headers,
+            cookies: Vec::new(),
             body: message.body,
+            chunked: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::Pin, task::{Context, Poll}, time::Duration};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{HttpError, HttpLimits, HttpReader, Method, Request, Response, Status, Version};
+
+    //Hands out at most `step` bytes per read, like a slow connection would
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl async_std::io::Read for Trickle<'_> {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+            let len = self.step.min(buf.len()).min(self.data.len());
+
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    fn small_limits() -> HttpLimits {
+        HttpLimits {
+            max_headers: 4,
+            max_line_len: 64,
+            max_body_size: 32,
+            idle_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+
+    //Sends its data and then goes quiet without closing
+    struct Stall<'a> {
+        data: &'a [u8],
+    }
+
+    impl async_std::io::Read for Stall<'_> {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+            if self.data.is_empty() {
+                return Poll::Pending;
+            }
+
+            let len = buf.len().min(self.data.len());
+
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    fn read_all(data: &[u8], step: usize, limits: HttpLimits) -> Result<Vec<Request>, HttpError> {
+        pollster::block_on(async {
+            let mut stream = Trickle { data, step };
+            let mut reader = HttpReader::new(limits);
+            let mut requests = Vec::new();
+
+            while let Some(request) = reader.read_request(&mut stream).await? {
+                requests.push(request);
+            }
+
+            Ok(requests)
+        })
+    }
+
+    fn read_one(data: &[u8]) -> Result<Request, HttpError> {
+        read_all(data, 4096, small_limits())?.pop().ok_or(HttpError::Other("No request".to_string()))
+    }
+
+    fn write_response(response: Response) -> Vec<u8> {
+        let mut out = Vec::new();
+        pollster::block_on(response.write_async(&mut out)).unwrap();
+
+        out
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let data = "POST /api/add_agent?id=3 HTTP/1.1\r\nhOsT: localhost\r\ncontent-LENGTH: 5\r\nCookie: a=1;session = xyz ; flag; b64=YQ==\r\nX-Name: Zoë 🐍\r\n\r\nhello";
+
+        for step in 1..20 {
+            let requests = read_all(data.as_bytes(), step, HttpLimits::default()).unwrap();
+            assert_eq!(requests.len(), 1);
+
+            let request = &requests[0];
+            assert_eq!(request.method, Method::Post);
+            assert_eq!(request.path.path, vec!["api", "add_agent"]);
+            assert_eq!(request.path.query["id"], "3");
+            assert_eq!(request.version, Version::Http11);
+            assert_eq!(request.header("Host").unwrap(), "localhost");
+            assert_eq!(request.header("x-name").unwrap(), "Zoë 🐍");
+            assert_eq!(request.cookies["session"], "xyz");
+            assert_eq!(request.cookies["b64"], "YQ==");
+            assert!(!request.cookies.contains_key("flag"));
+            assert_eq!(request.cookies, request.get_cookies());
+            assert_eq!(request.body, b"hello");
+        }
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let data = "GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi\r\nGET /c HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n";
+
+        for step in [1, 3, 4096] {
+            let requests = read_all(data.as_bytes(), step, HttpLimits::default()).unwrap();
+
+            let paths: Vec<_> = requests.iter().map(|x| x.path.path.join("/")).collect();
+            assert_eq!(paths, vec!["a", "b", "c"]);
+            assert_eq!(requests[1].body, b"hi");
+            assert!(requests.iter().all(|x| x.keep_alive()));
+        }
+
+        assert!(!read_one(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap().keep_alive());
+        assert!(!read_one(b"GET / HTTP/1.0\r\n\r\n").unwrap().keep_alive());
+
+        //Nothing sent at all is a closed connection rather than an error
+        assert!(read_all(b"", 1, HttpLimits::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_chunked() {
+        let data = "POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n5;name=value\r\nhello\r\nB\r\n, world 123\r\n0\r\nX-Trailer: yes\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+
+        for step in [1, 2, 7, 4096] {
+            let requests = read_all(data.as_bytes(), step, HttpLimits::default()).unwrap();
+
+            assert_eq!(requests[0].body, b"hello, world 123");
+            assert_eq!(requests[1].path.path, vec!["next"]);
+        }
+
+        let mut response = Response::new();
+        response.set_chunked();
+        response.set_body(b"streamed body".to_vec());
+
+        let written = write_response(response);
+        let text = String::from_utf8_lossy(&written);
+        assert!(text.contains("Transfer-Encoding: chunked\r\n") && !text.contains("Content-Length"));
+
+        let parsed = pollster::block_on(Response::parse_async(&mut Trickle { data: &written, step: 3 })).unwrap();
+        assert_eq!(parsed.body, b"streamed body");
+
+        //Every response needs framing for the connection to be reused
+        let text = String::from_utf8(write_response(Response::ok())).unwrap();
+        assert!(text.contains("Content-Length: 0\r\n"));
+
+        let mut response = Response::new();
+        response.set_status(Status::NoContent);
+        assert!(!String::from_utf8(write_response(response)).unwrap().contains("Content-Length"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(5));
+        let long_header = format!("GET / HTTP/1.1\r\nA: {}\r\n\r\n", "b".repeat(64));
+        let long_path = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(64));
+
+        assert!(read_one(format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(4)).as_bytes()).is_ok());
+        assert!(matches!(read_one(many_headers.as_bytes()), Err(HttpError::Http(Status::RequestHeaderFieldsTooLarge, _))));
+        assert!(matches!(read_one(long_header.as_bytes()), Err(HttpError::Http(Status::RequestHeaderFieldsTooLarge, _))));
+        assert!(matches!(read_one(long_path.as_bytes()), Err(HttpError::Http(Status::UriTooLong, _))));
+
+        assert!(read_one(b"POST / HTTP/1.1\r\nContent-Length: 32\r\n\r\n12345678901234567890123456789012").is_ok());
+        assert!(matches!(read_one(b"POST / HTTP/1.1\r\nContent-Length: 33\r\n\r\n"), Err(HttpError::Http(Status::PayloadTooLarge, _))));
+        assert!(matches!(read_one(b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n"), Err(HttpError::Http(Status::PayloadTooLarge, _))));
+        assert!(matches!(
+            read_one(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n1234567890123456\r\n11\r\n"),
+            Err(HttpError::Http(Status::PayloadTooLarge, _))
+        ));
+        assert!(matches!(
+            read_one(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffffffffff\r\n"),
+            Err(HttpError::Http(Status::PayloadTooLarge, _))
+        ));
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let limits = HttpLimits {
+            idle_timeout: Duration::from_millis(50),
+            request_timeout: Duration::from_millis(100),
+            ..small_limits()
+        };
+
+        let read = |data: &[u8]| pollster::block_on(async {
+            HttpReader::new(limits).read_request(&mut Stall { data }).await
+        });
+
+        //Idle connections are closed quietly, ones stuck mid request get a 408
+        assert!(matches!(read(b""), Ok(None)));
+        assert!(matches!(read(b"GET / HT"), Err(HttpError::Http(Status::RequestTimeout, _))));
+        assert!(matches!(read(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhi"), Err(HttpError::Http(Status::RequestTimeout, _))));
+        assert!(read(b"GET / HTTP/1.1\r\n\r\n").is_ok());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bad_requests: [&[u8]; 14] = [
+            b"GET / HTTP/1.1\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"POST / HTTP/1.1\r\nContent-Length: +2\r\n\r\nhi",
+            b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi!",
+            b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n+5\r\nhello\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhello\r\n0\r\n\r\n",
+            b"GET / HTTP/1.1\r\nA: b\r\n folded\r\n\r\n",
+            b"GET / HTTP/1.1\r\nA : b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nA: b\rc\r\n\r\n",
+            b"GET / HTTP/1.1\r\nA: \xff\xfe\r\n\r\n",
+            b"GET / HTTP/1.1\r\nA: b\r\n",
+            b"GET /\r\n\r\n",
+            b"GET / FTP/1.1\r\n\r\n",
+        ];
+
+        for data in bad_requests {
+            assert!(
+                matches!(read_one(data), Err(HttpError::Http(Status::BadRequest, _))),
+                "{}",
+                String::from_utf8_lossy(data)
+            );
+        }
+
+        assert!(matches!(read_one(b"GET / HTTP/2.0\r\n\r\n"), Err(HttpError::Http(Status::HttpVersionNotSupported, _))));
+        assert!(matches!(
+            read_one(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            Err(HttpError::Http(Status::NotImplemented, _))
+        ));
+    }
+
+    //Mangled and random input has to come back as an error, never a panic or a request over the limits
+    #[test]
+    fn test_fuzz_parser() {
+        let seeds: [&[u8]; 4] = [
+            b"GET /api/profile?id=1 HTTP/1.1\r\nHost: a\r\nCookie: session=abc\r\n\r\n",
+            b"POST /api/login HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nwiki\r\n0\r\nA: b\r\n\r\n",
+            b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+        ];
+        let alphabet = b"\r\n :;,-0123456789abcdefABCDEF/?=&\t\xff\x00";
+
+        let mut rng = StdRng::seed_from_u64(47);
+        let limits = small_limits();
+
+        for i in 0..20000 {
+            let mut data = seeds[i % seeds.len()].to_vec();
+
+            for _ in 0..rng.gen_range(1..4) {
+                let pos = rng.gen_range(0..data.len());
+                let byte = if rng.gen_bool(0.8) { alphabet[rng.gen_range(0..alphabet.len())] } else { rng.gen() };
+
+                match rng.gen_range(0..5) {
+                    0 => data[pos] = byte,
+                    1 => data.insert(pos, byte),
+                    2 => drop(data.remove(pos)),
+                    3 => data.truncate(pos),
+                    _ => {
+                        let end = rng.gen_range(pos..data.len());
+                        let copy = data[pos..end].to_vec();
+                        data.splice(pos..pos, copy);
+                    }
+                }
+
+                if data.is_empty() {
+                    break;
+                }
+            }
+
+            if i % 10 == 0 {
+                data = (0..rng.gen_range(0..200)).map(|_| rng.gen()).collect();
+            }
+
+            if let Ok(requests) = read_all(&data, rng.gen_range(1..16), limits) {
+                for request in requests {
+                    assert!(request.body.len() <= limits.max_body_size);
+                    assert!(request.headers.len() <= limits.max_headers);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+
+use rand::seq::SliceRandom;
+use sea_orm::{sea_query::Expr, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use serde_json::{json, Value};
+
+use crate::entities::{invite, user};
+
+//Letters and digits that can't be mistaken for each other when read off a sheet of paper
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_GROUPS: usize = 3;
+const CODE_GROUP_LEN: usize = 4;
+
+pub const MAX_INVITES_PER_REQUEST: usize = 500;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_USERNAME_LEN: usize = 50;
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs() as i64).unwrap_or(0)
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..CODE_GROUPS)
+        .map(|_| (0..CODE_GROUP_LEN).map(|_| *CODE_ALPHABET.choose(&mut rng).unwrap() as char).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+//Codes are typed in by hand, so case, spaces and missing dashes don't matter
+pub fn normalise_code(code: &str) -> String {
+    let chars: Vec<char> = code.chars()
+        .filter(|x| x.is_ascii_alphanumeric())
+        .map(|x| x.to_ascii_uppercase())
+        .collect();
+
+    chars.chunks(CODE_GROUP_LEN)
+        .map(|x| x.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub fn validate_username(username: &str) -> Result<(), String> {
+    if username.is_empty() {
+        Err("username is empty".to_string())
+    } else if username.len() > MAX_USERNAME_LEN {
+        Err("username too long".to_string())
+    } else if username.chars().any(|x| x.is_control()) {
+        Err("username contains control characters".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+//One invite per entry, entries with a username can only be redeemed under that name
+pub async fn create_invites(db: &DatabaseConnection, usernames: Vec<Option<String>>, num_agents_allowed: i32, created_by: Option<i32>) -> Result<Vec<invite::Model>, DbErr> {
+    let created = now();
+    let mut invites = Vec::new();
+
+    for username in usernames {
+        let invite = invite::ActiveModel {
+            code: ActiveValue::Set(generate_code()),
+            num_agents_allowed: ActiveValue::Set(num_agents_allowed),
+            username: ActiveValue::Set(username),
+            created_by: ActiveValue::Set(created_by),
+            created: ActiveValue::Set(created),
+            ..Default::default()
+        };
+
+        let id = invite::Entity::insert(invite).exec(db).await?.last_insert_id;
+
+        invites.push(invite::Entity::find_by_id(id).one(db).await?
+            .ok_or(DbErr::RecordNotFound("Invite that was just created".to_string()))?);
+    }
+
+    Ok(invites)
+}
+
+//False if the invite was redeemed in the meantime
+pub async fn redeem_invite<C: ConnectionTrait>(db: &C, invite_id: i32, user_id: i32) -> Result<bool, DbErr> {
+    let res = invite::Entity::update_many()
+        .col_expr(invite::Column::RedeemedBy, Expr::value(user_id))
+        .col_expr(invite::Column::Redeemed, Expr::value(now()))
+        .filter(invite::Column::Id.eq(invite_id))
+        .filter(invite::Column::Redeemed.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(res.rows_affected == 1)
+}
+
+//Usernames from the first column of a CSV file, with or without a "username" header
+pub fn parse_usernames(csv: &str) -> Result<Vec<String>, String> {
+    let mut usernames: Vec<String> = Vec::new();
+
+    for (i, line) in csv.lines().enumerate() {
+        let field = line.split(',').next().unwrap_or("").trim().trim_matches('"').trim();
+
+        if field.is_empty() || (i == 0 && field.eq_ignore_ascii_case("username")) {
+            continue;
+        }
+
+        validate_username(field).map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+        if usernames.iter().any(|x| x == field) {
+            return Err(format!("line {}: {} is listed twice", i + 1, field));
+        }
+
+        usernames.push(field.to_string());
+    }
+
+    Ok(usernames)
+}
+
+pub fn invite_json(invite: &invite::Model, users: &HashMap<i32, user::Model>) -> Value {
+    json!({
+        "id": invite.id,
+        "code": invite.code,
+        "num_agents_allowed": invite.num_agents_allowed,
+        "username": invite.username,
+        "created": invite.created,
+        "redeemed": invite.redeemed,
+        "redeemed_by": invite.redeemed_by.and_then(|x| users.get(&x)).map(|x| x.username.clone())
+    })
+}
+
+fn csv_field(field: &str) -> String {
+    //Spreadsheets run cells starting with these as formulas
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+pub fn invites_csv(invites: &[invite::Model], users: &HashMap<i32, user::Model>) -> String {
+    let mut csv = String::from("code,username,agents,created,redeemed_by,redeemed\n");
+
+    for invite in invites {
+        let redeemed_by = invite.redeemed_by.and_then(|x| users.get(&x)).map(|x| x.username.as_str()).unwrap_or("");
+
+        let fields = [
+            csv_field(&invite.code),
+            csv_field(invite.username.as_deref().unwrap_or("")),
+            invite.num_agents_allowed.to_string(),
+            invite.created.to_string(),
+            csv_field(redeemed_by),
+            invite.redeemed.map(|x| x.to_string()).unwrap_or_default()
+        ];
+
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::entities::invite;
+
+    use super::{generate_code, invites_csv, normalise_code, parse_usernames};
+
+    #[test]
+    fn test_invite_codes() {
+        let code = generate_code();
+
+        assert_eq!(code.len(), 14);
+        assert_eq!(normalise_code(&code), code);
+        assert_eq!(normalise_code(&code.to_lowercase().replace('-', " ")), code);
+    }
+
+    #[test]
+    fn test_invite_csv() {
+        assert_eq!(parse_usernames("Username\nalice\n\n bob ,team 1\n\"carol\"\n").unwrap(), vec!["alice", "bob", "carol"]);
+        assert!(parse_usernames("alice\nalice\n").is_err());
+
+        let invite = invite::Model {
+            id: 1,
+            code: "ABCD-EFGH-JKLM".to_string(),
+            num_agents_allowed: 3,
+            username: Some("=cmd, \"x\"".to_string()),
+            created_by: None,
+            redeemed_by: None,
+            created: 100,
+            redeemed: None
+        };
+
+        assert_eq!(
+            invites_csv(&[invite], &HashMap::new()),
+            "code,username,agents,created,redeemed_by,redeemed\nABCD-EFGH-JKLM,\"'=cmd, \"\"x\"\"\",3,100,,\n"
+        );
+    }
+}
@@ -1,5 +1,14 @@
 pub mod api;
+pub mod api_token;
+pub mod api_v2;
+pub mod config;
 pub mod http;
+pub mod invite;
 pub mod profile;
+pub mod rate_limit;
+pub mod role;
+pub mod session;
+pub mod tls;
 pub mod web_errors;
+pub mod websocket;
 pub mod game_reporter;
\ No newline at end of file
@@ -1,3 +1,4 @@
+use argon2::{password_hash::{rand_core::OsRng, SaltString}, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use lazy_static::lazy_static;
 use rand::seq::SliceRandom;
 use sea_orm::{DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, ColumnTrait};
@@ -26,6 +27,20 @@ pub fn generate_password() -> String {
     password
 }
 
+//Only the hash is stored, the password itself is shown once when it is generated
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default().hash_password(password.as_bytes(), &salt).unwrap().to_string()
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false
+    }
+}
+
 pub async fn get_num_agents(profile: &user::Model, db: &DatabaseConnection) -> u64 {
     agent::Entity::find()
         .filter(agent::Column::OwnerId.eq(profile.id))
@@ -33,6 +48,19 @@ pub async fn get_num_agents(profile: &user::Model, db: &DatabaseConnection) -> u
         .await.unwrap()
 }
 
+//Total size of the submissions a user's agents can take up
+pub const STORAGE_QUOTA: i64 = 16 * 1024 * 1024;
+
+pub async fn get_storage_used(profile: &user::Model, db: &DatabaseConnection) -> i64 {
+    agent::Entity::find()
+        .filter(agent::Column::OwnerId.eq(profile.id))
+        .all(db)
+        .await.unwrap()
+        .iter()
+        .map(|x| x.source_size)
+        .sum()
+}
+
 pub struct Profile {
     pub id: u32,
     pub username: String,
@@ -61,4 +89,21 @@ impl Profile {
 
 pub struct AgentInfo {
     pub id: PlayerId
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{hash_password, verify_password};
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("correct-horse-battery-staple");
+
+        assert!(hash.starts_with("$argon2"));
+        assert_ne!(hash, hash_password("correct-horse-battery-staple"));
+
+        assert!(verify_password("correct-horse-battery-staple", &hash));
+        assert!(!verify_password("correct-horse-battery", &hash));
+        //Rows from before passwords were hashed
+        assert!(!verify_password("correct-horse-battery-staple", "correct-horse-battery-staple"));
+    }
+}
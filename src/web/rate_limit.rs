@@ -0,0 +1,215 @@
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::{Duration, Instant}};
+
+use serde::Deserialize;
+
+use super::{http::{Method, Request}, web_errors::{HttpResult, WebError}};
+
+//Full buckets are forgotten this often, so clients that went away don't pile up
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    //Logging in and registering, where passwords can be guessed
+    Auth,
+    //Anything that starts a compile
+    Submit,
+    Other
+}
+
+impl RouteGroup {
+    pub fn of(req: &Request) -> Self {
+        let path: Vec<_> = req.path.path.iter().map(|x| x.as_str()).collect();
+
+        match (&req.method, path.as_slice()) {
+            (Method::Post, ["api", "login" | "register"] | ["api", "v2", "sessions"]) => RouteGroup::Auth,
+            (Method::Post, ["api", "add_agent"] | ["api", "v2", "users", _, "agents"]) => RouteGroup::Submit,
+            _ => RouteGroup::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    //Requests that can be made back to back
+    pub burst: u32,
+    //How fast the allowance comes back
+    pub per_minute: u32
+}
+
+impl Limit {
+    fn per_second(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub auth: Limit,
+    pub submit: Limit,
+    pub other: Limit
+}
+
+impl RateLimits {
+    fn get(&self, group: RouteGroup) -> Limit {
+        match group {
+            RouteGroup::Auth => self.auth,
+            RouteGroup::Submit => self.submit,
+            RouteGroup::Other => self.other
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            auth: Limit { burst: 10, per_minute: 10 },
+            submit: Limit { burst: 5, per_minute: 5 },
+            //A page load fetches a few dozen files and API routes
+            other: Limit { burst: 200, per_minute: 1200 }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    User(i32),
+    //The account a login is for, from one address. Keyed by both so that nobody can lock an account out for everyone else
+    Username(String, IpAddr)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant
+}
+
+struct Buckets {
+    buckets: HashMap<(RouteGroup, Client), Bucket>,
+    last_prune: Instant
+}
+
+//A token bucket per route group and client
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<Buckets>
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_prune: Instant::now()
+            })
+        }
+    }
+
+    pub fn check(&self, group: RouteGroup, client: Client) -> HttpResult<()> {
+        self.check_at(group, client, Instant::now())
+    }
+
+    fn check_at(&self, group: RouteGroup, client: Client, now: Instant) -> HttpResult<()> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if now.saturating_duration_since(buckets.last_prune) >= PRUNE_INTERVAL {
+            let limits = self.limits;
+
+            buckets.buckets.retain(|(group, _), bucket| {
+                let limit = limits.get(*group);
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+
+                bucket.tokens + elapsed * limit.per_second() < limit.burst as f64
+            });
+            buckets.last_prune = now;
+        }
+
+        let limit = self.limits.get(group);
+
+        let bucket = buckets.buckets.entry((group, client)).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            updated: now
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second()).min(limit.burst as f64);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let retry_after = if limit.per_minute == 0 {
+            u64::MAX
+        } else {
+            ((1.0 - bucket.tokens) / limit.per_second()).ceil() as u64
+        };
+
+        Err(WebError::TooManyRequests(retry_after.max(1)))
+    }
+
+    #[cfg(test)]
+    fn num_buckets(&self) -> usize {
+        self.buckets.lock().unwrap().buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::{IpAddr, Ipv4Addr}, time::{Duration, Instant}};
+
+    use crate::web::{http::{Method, Request, RequestPath}, web_errors::WebError};
+
+    use super::{Client, Limit, RateLimiter, RateLimits, RouteGroup, PRUNE_INTERVAL};
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimits {
+            auth: Limit { burst: 3, per_minute: 6 },
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        let ip = Client::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(RouteGroup::Auth, ip.clone(), start).is_ok());
+        }
+
+        //One request comes back every 10 seconds
+        assert!(matches!(limiter.check_at(RouteGroup::Auth, ip.clone(), start), Err(WebError::TooManyRequests(10))));
+        assert!(matches!(limiter.check_at(RouteGroup::Auth, ip.clone(), start + Duration::from_secs(5)), Err(WebError::TooManyRequests(5))));
+        assert!(limiter.check_at(RouteGroup::Auth, ip.clone(), start + Duration::from_secs(10)).is_ok());
+
+        //Other clients and groups have their own buckets
+        assert!(limiter.check_at(RouteGroup::Auth, Client::User(1), start).is_ok());
+        assert!(limiter.check_at(RouteGroup::Other, ip.clone(), start).is_ok());
+        assert_eq!(limiter.num_buckets(), 3);
+
+        //Guesses at one account from one address run out, without locking the account for other addresses
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
+        let alice = Client::Username("alice".to_string(), first);
+        for _ in 0..3 {
+            assert!(limiter.check_at(RouteGroup::Auth, alice.clone(), start).is_ok());
+        }
+        assert!(limiter.check_at(RouteGroup::Auth, alice, start).is_err());
+        assert!(limiter.check_at(RouteGroup::Auth, Client::Username("alice".to_string(), second), start).is_ok());
+        assert!(limiter.check_at(RouteGroup::Auth, Client::Username("bob".to_string(), first), start).is_ok());
+        assert_eq!(limiter.num_buckets(), 6);
+
+        assert!(limiter.check_at(RouteGroup::Submit, Client::User(1), start + PRUNE_INTERVAL).is_ok());
+        assert_eq!(limiter.num_buckets(), 1);
+
+        let group = |method, path| RouteGroup::of(&Request::new(method, RequestPath::parse(path).unwrap(), HashMap::new(), vec![], HashMap::new()));
+
+        assert_eq!(group(Method::Post, "/api/login"), RouteGroup::Auth);
+        assert_eq!(group(Method::Post, "/api/v2/sessions"), RouteGroup::Auth);
+        assert_eq!(group(Method::Get, "/api/v2/sessions"), RouteGroup::Other);
+        assert_eq!(group(Method::Post, "/api/add_agent?id=2"), RouteGroup::Submit);
+        assert_eq!(group(Method::Post, "/api/v2/users/2/agents"), RouteGroup::Submit);
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::Display;
+
+//Ordered by how much an account can do, every role can do everything the ones before it can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    //Can log in and watch, but not submit
+    Spectator,
+    Contestant,
+    //Manages users and agents, but can't reset the competition
+    Organiser,
+    Admin
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Spectator, Role::Contestant, Role::Organiser, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Spectator => "spectator",
+            Role::Contestant => "contestant",
+            Role::Organiser => "organiser",
+            Role::Admin => "admin"
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.as_str() == role)
+    }
+
+    //Accounts with a role from before roles existed are contestants
+    pub fn of(role: &str) -> Self {
+        Self::parse(role).unwrap_or(Role::Contestant)
+    }
+
+    //Organisers can only manage accounts below them, so they can't promote themselves through someone else
+    pub fn can_manage(&self, other: Role) -> bool {
+        *self == Role::Admin || (*self >= Role::Organiser && other < *self)
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+
+use crate::entities::session;
+
+use super::{api_token::hash_token, http::Request};
+
+pub const SESSION_COOKIE: &str = "session";
+
+pub const SESSION_LIFETIME_S: i64 = 24 * 60 * 60;
+
+const TOKEN_LEN: usize = 48;
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs() as i64).unwrap_or(0)
+}
+
+fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+//The token itself only goes in the cookie, only its hash is kept
+pub async fn create_session(db: &DatabaseConnection, user_id: i32) -> Result<(session::Model, String), DbErr> {
+    let created = now();
+    let token = generate_token();
+
+    let session = session::ActiveModel {
+        hash: ActiveValue::Set(hash_token(&token)),
+        user_id: ActiveValue::Set(Some(user_id)),
+        created: ActiveValue::Set(created),
+        expires: ActiveValue::Set(created + SESSION_LIFETIME_S),
+        ..Default::default()
+    };
+
+    let id = session::Entity::insert(session).exec(db).await?.last_insert_id;
+
+    let session = session::Entity::find_by_id(id).one(db).await?
+        .ok_or(DbErr::RecordNotFound("Session that was just created".to_string()))?;
+
+    Ok((session, token))
+}
+
+//The unexpired session behind the session cookie, if there is one
+pub async fn find_session(req: &Request, db: &DatabaseConnection) -> Result<Option<session::Model>, DbErr> {
+    let token = match req.cookies.get(SESSION_COOKIE) {
+        Some(token) if !token.is_empty() => token,
+        _ => return Ok(None)
+    };
+
+    session::Entity::find()
+        .filter(session::Column::Hash.eq(hash_token(token)))
+        .filter(session::Column::Expires.gt(now()))
+        .one(db)
+        .await
+}
+
+pub async fn revoke_user_sessions(user_id: i32, db: &DatabaseConnection) -> Result<(), DbErr> {
+    session::Entity::delete_many()
+        .filter(session::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove_expired_sessions(db: &DatabaseConnection) -> Result<(), DbErr> {
+    session::Entity::delete_many()
+        .filter(session::Column::Expires.lte(now()))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+//HttpOnly keeps the token away from scripts, the pages only ever need the user id
+pub fn session_cookie(session: &session::Model, token: &str) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}", SESSION_COOKIE, token, (session.expires - now()).max(0))
+}
+
+pub fn clear_session_cookie() -> String {
+    format!("{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0", SESSION_COOKIE)
+}
@@ -0,0 +1,262 @@
+use std::{net::Shutdown, path::{Path, PathBuf}, pin::Pin, sync::{Arc, RwLock}, task::{Context, Poll}};
+
+use async_std::net::TcpStream;
+use futures_rustls::{server::TlsStream, TlsAcceptor};
+use log::{error, info};
+use rustls::{crypto::ring::default_provider, pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer}, server::{ClientHello, ResolvesServerCert}, sign::CertifiedKey, ServerConfig};
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use url_encor::Encoder;
+
+use super::http::{Request, Response, Status};
+
+fn load_certified_key(cert: &Path, key: &Path) -> Result<CertifiedKey, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|x| x.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Couldn't read certificate {}: {}", cert.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates in {}", cert.display()));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("Couldn't read key {}: {}", key.display(), e))?;
+
+    CertifiedKey::from_der(certs, key, &default_provider()).map_err(|e| format!("Invalid certificate or key: {}", e))
+}
+
+//Hands out whichever certificate was loaded last, so it can be swapped without restarting
+#[derive(Debug)]
+pub struct CertResolver {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>
+}
+
+impl CertResolver {
+    pub fn load(cert: &Path, key: &Path) -> Result<Self, String> {
+        Ok(Self {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            current: RwLock::new(Arc::new(load_certified_key(cert, key)?))
+        })
+    }
+
+    //The old certificate stays in use if the new one can't be loaded
+    pub fn reload(&self) -> Result<(), String> {
+        let certified_key = load_certified_key(&self.cert, &self.key)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+pub fn acceptor(resolver: Arc<CertResolver>) -> Result<TlsAcceptor, String> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+//Certificates get renewed under a running server, a SIGHUP picks up the new files
+pub fn reload_on_sighup(resolver: Arc<CertResolver>) -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            match resolver.reload() {
+                Ok(()) => info!("Reloaded TLS certificate"),
+                Err(e) => error!("Couldn't reload TLS certificate, keeping the old one: {}", e)
+            }
+        }
+    });
+
+    Ok(())
+}
+
+//Sends plain HTTP requests to the same path over HTTPS
+pub fn https_redirect(req: &Request, https_port: u16) -> Response {
+    let host = match req.header("Host") {
+        Some(host) => host,
+        None => return Response::basic_error(Status::BadRequest, "Missing Host header")
+    };
+
+    //Drop the port, keeping the brackets of IPv6 addresses
+    let host = match host.rfind(':') {
+        Some(idx) if !host[idx..].contains(']') => &host[..idx],
+        _ => host.as_str()
+    };
+
+    let mut location = match https_port {
+        443 => format!("https://{}/{}", host, req.path.path.join("/")),
+        port => format!("https://{}:{}/{}", host, port, req.path.path.join("/"))
+    };
+
+    if !req.path.query.is_empty() {
+        let query: Vec<_> = req.path.query.iter()
+            .map(|(key, value)| format!("{}={}", key.url_encode(), value.url_encode()))
+            .collect();
+
+        location.push('?');
+        location.push_str(&query.join("&"));
+    }
+
+    let mut response = Response::new();
+    response.set_status(Status::MovedPermanently);
+    response.set_header("Location", &location);
+
+    response
+}
+
+//A client connection, with or without TLS
+#[derive(Debug)]
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>)
+}
+
+impl Connection {
+    pub fn is_secure(&self) -> bool {
+        matches!(self, Connection::Tls(_))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.shutdown(how),
+            Connection::Tls(stream) => stream.get_ref().0.shutdown(how)
+        }
+    }
+}
+
+impl async_std::io::Read for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Connection::Tls(stream) => Pin::new(stream).poll_read(cx, buf)
+        }
+    }
+}
+
+impl async_std::io::Write for Connection {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Connection::Tls(stream) => Pin::new(stream).poll_write(cx, buf)
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Connection::Tls(stream) => Pin::new(stream).poll_flush(cx)
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_close(cx),
+            Connection::Tls(stream) => Pin::new(stream).poll_close(cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path, sync::Arc};
+
+    use async_std::net::TcpListener;
+    use futures_rustls::TlsConnector;
+    use rustls::{crypto::ring::default_provider, pki_types::{pem::PemObject, CertificateDer, ServerName}, ClientConfig, RootCertStore};
+
+    use crate::web::http::{HttpLimits, HttpReader, Method, Request, RequestPath, Response};
+
+    use super::{acceptor, https_redirect, CertResolver, Connection};
+
+    const CERT: &str = "res/test/tls/cert.pem";
+    const KEY: &str = "res/test/tls/key.pem";
+    const OTHER_KEY: &str = "res/test/tls/other_key.pem";
+
+    #[test]
+    fn test_tls_connection() {
+        assert!(CertResolver::load(Path::new(CERT), Path::new(OTHER_KEY)).is_err());
+        assert!(CertResolver::load(Path::new(KEY), Path::new(KEY)).is_err());
+
+        let resolver = Arc::new(CertResolver::load(Path::new(CERT), Path::new(KEY)).unwrap());
+        resolver.reload().unwrap();
+
+        let acceptor = acceptor(resolver).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_file(CERT).unwrap()).unwrap();
+
+        let client_config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let server = async_std::task::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = Connection::Tls(Box::new(acceptor.accept(stream).await.unwrap()));
+                let mut reader = HttpReader::new(HttpLimits::default());
+
+                //Both requests come over the one connection
+                for _ in 0..2 {
+                    let request = reader.read_request(&mut stream).await.unwrap().unwrap();
+
+                    let mut response = Response::ok();
+                    response.set_body(request.path.path.join("/").into_bytes());
+                    response.write_async(&mut stream).await.unwrap();
+                }
+
+                assert!(stream.is_secure());
+            });
+
+            let stream = async_std::net::TcpStream::connect(addr).await.unwrap();
+            let mut stream = connector.connect(ServerName::try_from("localhost").unwrap(), stream).await.unwrap();
+            let mut reader = HttpReader::new(HttpLimits::default());
+
+            for path in ["first", "second"] {
+                let request = Request::new(Method::Get, RequestPath::parse(&format!("/{}", path)).unwrap(), HashMap::new(), vec![], HashMap::new());
+                request.write_async(&mut stream).await.unwrap();
+                futures::AsyncWriteExt::flush(&mut stream).await.unwrap();
+
+                let response = reader.read_response(&mut stream).await.unwrap().unwrap();
+                assert_eq!(response.body, path.as_bytes());
+            }
+
+            server.await;
+        });
+    }
+
+    #[test]
+    fn test_https_redirect() {
+        let request = |host: Option<&str>| {
+            let headers = host.map(|x| HashMap::from([("host".to_string(), x.to_string())])).unwrap_or_default();
+            Request::new(Method::Get, RequestPath::parse("/pages/profile.html?id=4").unwrap(), headers, vec![], HashMap::new())
+        };
+
+        let location = |host, port| https_redirect(&request(Some(host)), port).header("Location").cloned();
+
+        assert_eq!(location("example.com:8080", 8443).unwrap(), "https://example.com:8443/pages/profile.html?id=4");
+        assert_eq!(location("example.com", 443).unwrap(), "https://example.com/pages/profile.html?id=4");
+        assert_eq!(location("[::1]:80", 443).unwrap(), "https://[::1]/pages/profile.html?id=4");
+        assert_eq!(location("[::1]", 443).unwrap(), "https://[::1]/pages/profile.html?id=4");
+
+        assert_eq!(https_redirect(&request(None), 443).status.get_code(), 400);
+    }
+}
@@ -1,7 +1,8 @@
 
 use std::{str::FromStr, error::Error, fmt::Debug};
 
-use serde_json::{Value, Map, Number};
+use log::error;
+use serde_json::{json, Value, Map, Number};
 
 use super::http::{Response, Status};
 
@@ -12,7 +13,12 @@ pub enum WebError {
     NotFound(String),
     InvalidMethod,
     InternalServerError(String),
-    Unauthorized
+    //Not logged in
+    Unauthorized,
+    //Logged in, but not allowed to do this
+    Forbidden(String),
+    //Seconds until the request can be retried
+    TooManyRequests(u64)
 }
 
 impl<T: Error + Debug> From<T> for WebError {
@@ -52,14 +58,72 @@ impl WebError {
                 response.set_body(format!("Internal Server Error: {}", message).into_bytes());
             },
             Self::Unauthorized => {
-                response.set_status(Status::Forbidden);
+                response.set_status(Status::Unauthorized);
                 response.set_header("Content-Type", "text/plain");
                 response.set_body("Unauthorized".as_bytes().to_vec());
+            },
+            Self::Forbidden(message) => {
+                response.set_status(Status::Forbidden);
+                response.set_header("Content-Type", "text/plain");
+                response.set_body(format!("Forbidden: {}", message).into_bytes());
+            },
+            Self::TooManyRequests(retry_after) => {
+                response.set_status(Status::TooManyRequests);
+                response.set_header("Content-Type", "text/plain");
+                response.set_header("Retry-After", &retry_after.to_string());
+                response.set_body(format!("Too many requests, try again in {} seconds", retry_after).into_bytes());
             }
         }
 
         response
     }
+
+    //Stable names for the /api/v2 error envelope
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidData(_) => "invalid_data",
+            Self::MissingParameter(_) => "missing_parameter",
+            Self::NotFound(_) => "not_found",
+            Self::InvalidMethod => "method_not_allowed",
+            Self::InternalServerError(_) => "internal_error",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::TooManyRequests(_) => "rate_limited"
+        }
+    }
+
+    //{"error": {"code", "message"}}, internal details are logged rather than sent
+    pub fn into_json_response(self) -> Response {
+        let (status, message) = match &self {
+            Self::InvalidData(message) => (Status::BadRequest, message.clone()),
+            Self::MissingParameter(parameter) => (Status::BadRequest, format!("Missing parameter {}", parameter)),
+            Self::NotFound(message) => (Status::NotFound, message.clone()),
+            Self::InvalidMethod => (Status::MethodNotAllowed, "Method not allowed for this route".to_string()),
+            Self::InternalServerError(message) => {
+                error!("Internal server error: {}", message);
+                (Status::InternalServerError, "Internal server error".to_string())
+            },
+            Self::Unauthorized => (Status::Unauthorized, "Not logged in".to_string()),
+            Self::Forbidden(message) => (Status::Forbidden, message.clone()),
+            Self::TooManyRequests(retry_after) => (Status::TooManyRequests, format!("Too many requests, try again in {} seconds", retry_after))
+        };
+
+        let mut response = Response::new();
+        response.set_status(status);
+        response.set_header("Content-Type", "application/json");
+        response.set_body(json!({
+            "error": {
+                "code": self.code(),
+                "message": message
+            }
+        }).to_string().into_bytes());
+
+        if let Self::TooManyRequests(retry_after) = self {
+            response.set_header("Retry-After", &retry_after.to_string());
+        }
+
+        response
+    }
 }
 
 pub type HttpResult<T> = Result<T, WebError>;
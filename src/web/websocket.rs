@@ -0,0 +1,430 @@
+use std::fmt::Display;
+
+use async_std::io::{Read, Write};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use sha1::{Digest, Sha1};
+
+use crate::util::asyncio::{AsyncReaderWrapper, AsyncWriterWrapper};
+
+use super::http::{Method, Request, Response, Status};
+
+//Appended to the client's key to prove the server speaks WebSocket (RFC 6455)
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+//Clients only send short commands
+pub const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED: u16 = 1003;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    //With the status code, if one was given
+    Close(Option<u16>)
+}
+
+#[derive(Debug)]
+pub enum WsError {
+    Io(std::io::Error),
+    //The client broke the protocol, the connection is closed with this code
+    Close(u16, &'static str)
+}
+
+impl From<std::io::Error> for WsError {
+    fn from(value: std::io::Error) -> Self {
+        WsError::Io(value)
+    }
+}
+
+impl Display for WsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WsError::Io(e) => write!(f, "{}", e),
+            WsError::Close(code, reason) => write!(f, "{} ({})", reason, code)
+        }
+    }
+}
+
+fn has_token(req: &Request, header: &str, token: &str) -> bool {
+    req.header(header)
+        .is_some_and(|x| x.split(',').any(|x| x.trim().eq_ignore_ascii_case(token)))
+}
+
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+
+    BASE64_STANDARD.encode(hasher.finalize())
+}
+
+//The 101 response that switches the connection over, anything else is an error to send instead
+pub fn handshake(req: &Request) -> Response {
+    if req.method != Method::Get {
+        return Response::basic_error(Status::MethodNotAllowed, "WebSockets are opened with GET");
+    }
+
+    if !has_token(req, "Connection", "upgrade") || !has_token(req, "Upgrade", "websocket") {
+        let mut response = Response::basic_error(Status::UpgradeRequired, "Expected a WebSocket upgrade");
+        response.set_header("Upgrade", "websocket");
+        return response;
+    }
+
+    if req.header("Sec-WebSocket-Version").map(|x| x.trim()) != Some("13") {
+        let mut response = Response::basic_error(Status::UpgradeRequired, "Only WebSocket version 13 is supported");
+        response.set_header("Sec-WebSocket-Version", "13");
+        return response;
+    }
+
+    //The key is 16 random bytes in base64
+    let key = match req.header("Sec-WebSocket-Key").map(|x| x.trim()) {
+        Some(key) if BASE64_STANDARD.decode(key).is_ok_and(|x| x.len() == 16) => key,
+        _ => return Response::basic_error(Status::BadRequest, "Missing or invalid Sec-WebSocket-Key")
+    };
+
+    let mut response = Response::new();
+    response.set_status(Status::SwitchingProtocols);
+    response.set_header("Upgrade", "websocket");
+    response.set_header("Connection", "Upgrade");
+    response.set_header("Sec-WebSocket-Accept", &accept_key(key));
+
+    response
+}
+
+//Server frames are never masked or fragmented
+pub async fn write_message<T: Write + Unpin>(stream: &mut T, message: &Message) -> std::io::Result<()> {
+    let close;
+    let (opcode, payload) = match message {
+        Message::Text(text) => (OP_TEXT, text.as_bytes()),
+        Message::Binary(data) => (OP_BINARY, data.as_slice()),
+        Message::Ping(data) => (OP_PING, data.as_slice()),
+        Message::Pong(data) => (OP_PONG, data.as_slice()),
+        Message::Close(code) => {
+            close = code.map(|x| x.to_be_bytes().to_vec()).unwrap_or_default();
+            (OP_CLOSE, close.as_slice())
+        }
+    };
+
+    let mut frame = vec![0x80 | opcode];
+
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+
+    frame.extend_from_slice(payload);
+
+    let mut stream = AsyncWriterWrapper::new(stream);
+    stream.write_all(&frame).await?;
+    stream.flush().await
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>
+}
+
+pub struct MessageReader {
+    //Received bytes that don't make up a whole frame yet
+    buf: Vec<u8>,
+    //Opcode and data of a fragmented message that's still coming in
+    partial: Option<(u8, Vec<u8>)>
+}
+
+impl MessageReader {
+    //Starts with whatever was read past the handshake
+    pub fn new(buffered: Vec<u8>) -> Self {
+        Self {
+            buf: buffered,
+            partial: None
+        }
+    }
+
+    //Takes a frame off the buffer once all of it has arrived
+    fn parse_frame(&mut self) -> Result<Option<Frame>, WsError> {
+        if self.buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let (first, second) = (self.buf[0], self.buf[1]);
+
+        if first & 0x70 != 0 {
+            return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Reserved bits are set"));
+        }
+
+        if second & 0x80 == 0 {
+            return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Client frames must be masked"));
+        }
+
+        let fin = first & 0x80 != 0;
+        let opcode = first & 0x0F;
+
+        let (len, start) = match second & 0x7F {
+            126 if self.buf.len() < 4 => return Ok(None),
+            126 => (u16::from_be_bytes([self.buf[2], self.buf[3]]) as u64, 4),
+            127 if self.buf.len() < 10 => return Ok(None),
+            127 => (u64::from_be_bytes(self.buf[2..10].try_into().unwrap()), 10),
+            len => (len as u64, 2)
+        };
+
+        if opcode & 0x8 != 0 && (len > 125 || !fin) {
+            return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Control frames must be short and unfragmented"));
+        }
+
+        if len > MAX_MESSAGE_LEN as u64 {
+            return Err(WsError::Close(CLOSE_TOO_BIG, "Message is too big"));
+        }
+
+        let end = start + 4 + len as usize;
+
+        if self.buf.len() < end {
+            return Ok(None);
+        }
+
+        let mask = [self.buf[start], self.buf[start + 1], self.buf[start + 2], self.buf[start + 3]];
+        let payload = self.buf[start + 4..end].iter()
+            .enumerate()
+            .map(|(i, x)| x ^ mask[i % 4])
+            .collect();
+
+        self.buf.drain(..end);
+
+        Ok(Some(Frame { fin, opcode, payload }))
+    }
+
+    //Control frames can come between the fragments of a message
+    fn assemble(&mut self, frame: Frame) -> Result<Option<Message>, WsError> {
+        let (opcode, data) = match frame.opcode {
+            OP_CLOSE => {
+                let code = frame.payload.get(..2).map(|x| u16::from_be_bytes([x[0], x[1]]));
+                return Ok(Some(Message::Close(code)));
+            }
+            OP_PING => return Ok(Some(Message::Ping(frame.payload))),
+            OP_PONG => return Ok(Some(Message::Pong(frame.payload))),
+            OP_TEXT | OP_BINARY => {
+                if self.partial.is_some() {
+                    return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Expected the rest of a fragmented message"));
+                }
+
+                if !frame.fin {
+                    self.partial = Some((frame.opcode, frame.payload));
+                    return Ok(None);
+                }
+
+                (frame.opcode, frame.payload)
+            }
+            OP_CONTINUATION => {
+                let (opcode, mut data) = match self.partial.take() {
+                    Some(partial) => partial,
+                    None => return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Nothing to continue"))
+                };
+
+                if data.len() + frame.payload.len() > MAX_MESSAGE_LEN {
+                    return Err(WsError::Close(CLOSE_TOO_BIG, "Message is too big"));
+                }
+
+                data.extend(frame.payload);
+
+                if !frame.fin {
+                    self.partial = Some((opcode, data));
+                    return Ok(None);
+                }
+
+                (opcode, data)
+            }
+            _ => return Err(WsError::Close(CLOSE_PROTOCOL_ERROR, "Unknown opcode"))
+        };
+
+        if opcode == OP_TEXT {
+            String::from_utf8(data)
+                .map(|x| Some(Message::Text(x)))
+                .map_err(|_| WsError::Close(CLOSE_INVALID_DATA, "Text isn't valid UTF-8"))
+        } else {
+            Ok(Some(Message::Binary(data)))
+        }
+    }
+
+    //Nothing is lost if this is cancelled, so it can be raced against a timeout
+    pub async fn read_message<T: Read + Unpin>(&mut self, stream: &mut T) -> Result<Message, WsError> {
+        loop {
+            while let Some(frame) = self.parse_frame()? {
+                if let Some(message) = self.assemble(frame)? {
+                    return Ok(message);
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let read = AsyncReaderWrapper::new(&mut *stream).read(&mut chunk).await?;
+
+            if read == 0 {
+                return Err(WsError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::web::http::{Method, Request, RequestPath};
+
+    use super::{handshake, write_message, Message, MessageReader, WsError, CLOSE_INVALID_DATA, CLOSE_PROTOCOL_ERROR, CLOSE_TOO_BIG, MAX_MESSAGE_LEN};
+
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(0x80 | 126);
+                frame.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend((len as u64).to_be_bytes());
+            }
+        }
+
+        frame.extend(mask);
+        frame.extend(payload.iter().enumerate().map(|(i, x)| x ^ mask[i % 4]));
+
+        frame
+    }
+
+    fn read_all(bytes: &[u8]) -> Vec<Result<Message, u16>> {
+        pollster::block_on(async {
+            let mut reader = MessageReader::new(vec![]);
+            let mut stream = bytes;
+            let mut messages = vec![];
+
+            loop {
+                match reader.read_message(&mut stream).await {
+                    Ok(message) => messages.push(Ok(message)),
+                    Err(WsError::Close(code, _)) => {
+                        messages.push(Err(code));
+                        return messages;
+                    }
+                    Err(WsError::Io(_)) => return messages
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_handshake() {
+        let request = |headers: &[(&str, &str)]| {
+            let headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>();
+            Request::new(Method::Get, RequestPath::parse("/spectate").unwrap(), headers, vec![], HashMap::new())
+        };
+
+        let mut headers = vec![
+            ("upgrade", "websocket"),
+            ("connection", "keep-alive, Upgrade"),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        ];
+
+        //The example from RFC 6455
+        let response = handshake(&request(&headers));
+        assert_eq!(response.status.get_code(), 101);
+        assert_eq!(response.header("Sec-WebSocket-Accept").unwrap(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        headers[3].1 = "dG9vIHNob3J0";
+        assert_eq!(handshake(&request(&headers)).status.get_code(), 400);
+
+        headers[2].1 = "8";
+        let response = handshake(&request(&headers));
+        assert_eq!(response.status.get_code(), 426);
+        assert_eq!(response.header("Sec-WebSocket-Version").unwrap(), "13");
+
+        assert_eq!(handshake(&request(&headers[2..])).status.get_code(), 426);
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut bytes = client_frame(true, 0x1, b"{\"cmd\":\"list\"}");
+        bytes.extend(client_frame(false, 0x1, b"frag"));
+        bytes.extend(client_frame(true, 0x9, b"ping"));
+        bytes.extend(client_frame(false, 0x0, "mented \u{2603}".as_bytes()));
+        bytes.extend(client_frame(true, 0x0, b""));
+        bytes.extend(client_frame(true, 0x2, &[0; 300]));
+        bytes.extend(client_frame(true, 0x8, &1000u16.to_be_bytes()));
+
+        assert_eq!(read_all(&bytes), vec![
+            Ok(Message::Text("{\"cmd\":\"list\"}".to_string())),
+            Ok(Message::Ping(b"ping".to_vec())),
+            Ok(Message::Text("fragmented \u{2603}".to_string())),
+            Ok(Message::Binary(vec![0; 300])),
+            Ok(Message::Close(Some(1000)))
+        ]);
+
+        //Frames split across reads are put back together
+        pollster::block_on(async {
+            let frame = client_frame(true, 0x1, b"split");
+            let mut reader = MessageReader::new(frame[..3].to_vec());
+            let mut rest = &frame[3..];
+
+            assert_eq!(reader.read_message(&mut rest).await.unwrap(), Message::Text("split".to_string()));
+        });
+
+        let mut unmasked = client_frame(true, 0x1, b"hi");
+        unmasked[1] &= 0x7F;
+        assert_eq!(read_all(&unmasked), vec![Err(CLOSE_PROTOCOL_ERROR)]);
+
+        assert_eq!(read_all(&client_frame(false, 0x9, b"")), vec![Err(CLOSE_PROTOCOL_ERROR)]);
+        assert_eq!(read_all(&client_frame(true, 0x0, b"")), vec![Err(CLOSE_PROTOCOL_ERROR)]);
+        assert_eq!(read_all(&client_frame(true, 0x3, b"")), vec![Err(CLOSE_PROTOCOL_ERROR)]);
+        assert_eq!(read_all(&client_frame(true, 0x1, &[0xFF])), vec![Err(CLOSE_INVALID_DATA)]);
+        assert_eq!(read_all(&client_frame(true, 0x2, &vec![0; MAX_MESSAGE_LEN + 1])), vec![Err(CLOSE_TOO_BIG)]);
+
+        let mut too_big = client_frame(false, 0x2, &vec![0; MAX_MESSAGE_LEN]);
+        too_big.extend(client_frame(true, 0x0, b"!"));
+        assert_eq!(read_all(&too_big), vec![Err(CLOSE_TOO_BIG)]);
+
+        let mut interrupted = client_frame(false, 0x1, b"a");
+        interrupted.extend(client_frame(true, 0x1, b"b"));
+        assert_eq!(read_all(&interrupted), vec![Err(CLOSE_PROTOCOL_ERROR)]);
+
+        //Only the header of a huge frame is needed to turn it down
+        let mut header = vec![0x82, 0x80 | 127];
+        header.extend(u64::MAX.to_be_bytes());
+        assert_eq!(read_all(&header), vec![Err(CLOSE_TOO_BIG)]);
+
+        pollster::block_on(async {
+            let mut out = vec![];
+            write_message(&mut out, &Message::Text("x".repeat(200))).await.unwrap();
+            assert_eq!(out[..4], [0x81, 126, 0, 200]);
+            assert_eq!(out.len(), 204);
+
+            let mut out = vec![];
+            write_message(&mut out, &Message::Close(Some(1001))).await.unwrap();
+            assert_eq!(out, [0x88, 2, 0x03, 0xE9]);
+        });
+    }
+}
@@ -0,0 +1,23 @@
+public class Agent {
+    public short get_a(BigStruct s) { return s.a; }
+    public int get_b(BigStruct s) { return s.b; }
+    public long get_c(BigStruct s) { return s.c; }
+    public long get_d(BigStruct s) { return s.d; }
+    public byte get_e(BigStruct s) { return s.e; }
+    public short get_f(BigStruct s) { return s.f; }
+    public int get_g(BigStruct s) { return s.g; }
+    public long get_h(BigStruct s) { return s.h; }
+    public float get_i(BigStruct s) { return s.i; }
+    public double get_j(BigStruct s) { return s.j; }
+    public boolean get_k(BigStruct s) { return s.k; }
+    public String get_l(BigStruct s) { return s.l; }
+
+    public BigStruct[] list_test(BigStruct[] x) {
+        Game.log_debug("list_test " + x.length);
+        return x;
+    }
+
+    public void note(String msg) {
+        Game.log_debug(msg);
+    }
+}
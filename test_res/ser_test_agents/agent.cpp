@@ -62,5 +62,10 @@ std::string get_l(BigStruct& s) {
 
 std::vector<BigStruct> list_test(std::vector<BigStruct>& x) {
     //Implement logic here...
+    log_debug("list_test " + std::to_string(x.size()));
     return x;
-}
\ No newline at end of file
+}
+
+void note(std::string& msg) {
+    log_debug(msg);
+}
@@ -0,0 +1,25 @@
+package main
+
+import "fmt"
+
+func GetA(s BigStruct) uint8   { return s.a }
+func GetB(s BigStruct) uint16  { return s.b }
+func GetC(s BigStruct) uint32  { return s.c }
+func GetD(s BigStruct) uint64  { return s.d }
+func GetE(s BigStruct) int8    { return s.e }
+func GetF(s BigStruct) int16   { return s.f }
+func GetG(s BigStruct) int32   { return s.g }
+func GetH(s BigStruct) int64   { return s.h }
+func GetI(s BigStruct) float32 { return s.i }
+func GetJ(s BigStruct) float64 { return s.j }
+func GetK(s BigStruct) bool    { return s.k }
+func GetL(s BigStruct) string  { return s.l }
+
+func ListTest(x []BigStruct) []BigStruct {
+	LogDebug(fmt.Sprintf("list_test %d", len(x)))
+	return x
+}
+
+func Note(msg string) {
+	LogDebug(msg)
+}
@@ -0,0 +1,25 @@
+const { log_debug } = require('game_io');
+
+const get_a = (s) => s.a;
+const get_b = (s) => s.b;
+const get_c = (s) => s.c;
+const get_d = (s) => s.d;
+const get_e = (s) => s.e;
+const get_f = (s) => s.f;
+const get_g = (s) => s.g;
+const get_h = (s) => s.h;
+const get_i = (s) => s.i;
+const get_j = (s) => s.j;
+const get_k = (s) => s.k;
+const get_l = (s) => s.l;
+
+function list_test(x) {
+    log_debug(`list_test ${x.length}`);
+    return x;
+}
+
+function note(msg) {
+    log_debug(msg);
+}
+
+module.exports = { get_a, get_b, get_c, get_d, get_e, get_f, get_g, get_h, get_i, get_j, get_k, get_l, list_test, note };
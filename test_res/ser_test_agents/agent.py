@@ -1,4 +1,5 @@
 from game_types import BigStruct
+from game_io import log_debug
 from typing import List
 
 def get_a(s: BigStruct) -> int:
@@ -38,4 +39,8 @@ def get_l(s: BigStruct) -> str:
     return s.l
 
 def list_test(s: List[BigStruct]) -> List[BigStruct]:
-    return s
\ No newline at end of file
+    log_debug(f"list_test {len(s)}")
+    return s
+
+def note(msg: str) -> None:
+    log_debug(msg)
\ No newline at end of file
@@ -0,0 +1,31 @@
+use crate::game::*;
+
+pub struct SerTest;
+
+pub fn make_bot() -> SerTest {
+    SerTest
+}
+
+impl Bot for SerTest {
+    fn get_a(&mut self, s: BigStruct) -> u8 { s.a }
+    fn get_b(&mut self, s: BigStruct) -> u16 { s.b }
+    fn get_c(&mut self, s: BigStruct) -> u32 { s.c }
+    fn get_d(&mut self, s: BigStruct) -> u64 { s.d }
+    fn get_e(&mut self, s: BigStruct) -> i8 { s.e }
+    fn get_f(&mut self, s: BigStruct) -> i16 { s.f }
+    fn get_g(&mut self, s: BigStruct) -> i32 { s.g }
+    fn get_h(&mut self, s: BigStruct) -> i64 { s.h }
+    fn get_i(&mut self, s: BigStruct) -> f32 { s.i }
+    fn get_j(&mut self, s: BigStruct) -> f64 { s.j }
+    fn get_k(&mut self, s: BigStruct) -> bool { s.k }
+    fn get_l(&mut self, s: BigStruct) -> String { s.l }
+
+    fn list_test(&mut self, x: Vec<BigStruct>) -> Vec<BigStruct> {
+        log_debug(format!("list_test {}", x.len()));
+        x
+    }
+
+    fn note(&mut self, msg: String) {
+        log_debug(msg);
+    }
+}